@@ -0,0 +1,16 @@
+CREATE TABLE upload_sessions (
+    id VARCHAR(8) NOT NULL PRIMARY KEY,
+    delete_key VARCHAR(21) NOT NULL,
+    cipher_key VARCHAR(64),
+    nonce VARCHAR(38),
+    file_name VARCHAR(255) NOT NULL,
+    upload_length BIGINT NOT NULL,
+    upload_offset BIGINT NOT NULL DEFAULT 0,
+    parts TEXT[] NOT NULL DEFAULT '{}',
+    tail BYTEA NOT NULL DEFAULT '',
+    expiry_hours INT,
+    expiry_downloads INT,
+    embedded BOOL NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+)
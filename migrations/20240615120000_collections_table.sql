@@ -0,0 +1,9 @@
+CREATE TABLE collections (
+    id VARCHAR(8) NOT NULL PRIMARY KEY,
+    key_hash VARCHAR(64),
+    delete_key VARCHAR(21) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE uploads ADD COLUMN collection_id VARCHAR(8) REFERENCES collections (id);
+CREATE INDEX uploads_collection_id_idx ON uploads (collection_id);
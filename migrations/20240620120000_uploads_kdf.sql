@@ -0,0 +1,5 @@
+ALTER TABLE uploads
+    ADD COLUMN kdf_salt VARCHAR(32),
+    ADD COLUMN kdf_memory INT,
+    ADD COLUMN kdf_iterations INT,
+    ADD COLUMN kdf_parallelism INT;
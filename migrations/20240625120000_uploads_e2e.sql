@@ -0,0 +1,3 @@
+ALTER TABLE uploads
+    ADD COLUMN e2e_version SMALLINT,
+    ADD COLUMN e2e_chunk_size INT;
@@ -0,0 +1,9 @@
+CREATE TABLE blobs (
+    hash VARCHAR(64) NOT NULL PRIMARY KEY,
+    bytes BIGINT NOT NULL,
+    refs INT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE uploads ADD COLUMN blob_hash VARCHAR(64) REFERENCES blobs (hash);
+CREATE INDEX uploads_blob_hash_idx ON uploads (blob_hash);
@@ -0,0 +1,6 @@
+CREATE TABLE blacklist (
+    hash VARCHAR(64) NOT NULL PRIMARY KEY,
+    reason TEXT,
+    added_by VARCHAR(64) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,9 @@
+CREATE TABLE admin_audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    admin VARCHAR(64) NOT NULL,
+    action VARCHAR(32) NOT NULL,
+    target TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX admin_audit_log_created_at_idx ON admin_audit_log (created_at);
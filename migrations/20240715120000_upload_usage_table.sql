@@ -0,0 +1,6 @@
+CREATE TABLE upload_usage (
+    client VARCHAR(64) NOT NULL,
+    day DATE NOT NULL,
+    bytes BIGINT NOT NULL,
+    PRIMARY KEY (client, day)
+);
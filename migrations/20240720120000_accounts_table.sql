@@ -0,0 +1,22 @@
+CREATE TABLE accounts (
+    id VARCHAR(8) NOT NULL PRIMARY KEY,
+    name VARCHAR(64) NOT NULL,
+    max_file_bytes BIGINT,
+    default_expiry_hours INT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE api_keys (
+    id BIGSERIAL PRIMARY KEY,
+    account_id VARCHAR(8) NOT NULL REFERENCES accounts (id) ON DELETE CASCADE,
+    key_hash VARCHAR(64) NOT NULL UNIQUE,
+    label VARCHAR(64),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX api_keys_account_id_idx ON api_keys (account_id);
+
+ALTER TABLE uploads ADD COLUMN owner_id VARCHAR(8) REFERENCES accounts (id) ON DELETE SET NULL;
+CREATE INDEX uploads_owner_id_idx ON uploads (owner_id);
+
+ALTER TABLE upload_sessions ADD COLUMN owner_id VARCHAR(8) REFERENCES accounts (id) ON DELETE SET NULL;
@@ -0,0 +1,14 @@
+CREATE TABLE usage_stats (
+    hour TIMESTAMPTZ PRIMARY KEY,
+    uploads BIGINT NOT NULL DEFAULT 0,
+    bytes_in BIGINT NOT NULL DEFAULT 0,
+    encrypted_uploads BIGINT NOT NULL DEFAULT 0,
+    downloads BIGINT NOT NULL DEFAULT 0,
+    bytes_out BIGINT NOT NULL DEFAULT 0,
+    expired BIGINT NOT NULL DEFAULT 0,
+    blacklisted BIGINT NOT NULL DEFAULT 0
+);
+
+ALTER TABLE stats
+    ADD COLUMN files_downloaded BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN bytes_downloaded BIGINT NOT NULL DEFAULT 0;
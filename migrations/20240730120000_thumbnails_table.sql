@@ -0,0 +1,8 @@
+-- resized previews of a blob, stored next to it as `<blob_key>.<variant>`
+CREATE TABLE thumbnails (
+    blob_key VARCHAR(64) NOT NULL,
+    variant VARCHAR(64) NOT NULL,
+    bytes BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (blob_key, variant)
+);
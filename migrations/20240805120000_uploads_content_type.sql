@@ -0,0 +1,2 @@
+ALTER TABLE uploads ADD COLUMN content_type VARCHAR(255);
+ALTER TABLE upload_sessions ADD COLUMN content_type VARCHAR(255);
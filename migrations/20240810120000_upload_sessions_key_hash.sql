@@ -0,0 +1,5 @@
+-- sessions keep only a hash of their key, the client sends the key itself with every chunk
+ALTER TABLE upload_sessions RENAME COLUMN cipher_key TO key_hash;
+UPDATE upload_sessions SET key_hash = encode(sha256(convert_to(key_hash, 'UTF8')), 'hex') WHERE key_hash IS NOT NULL;
+-- their tails are still plaintext and can't be resumed, so they're left for the reaper
+UPDATE upload_sessions SET updated_at = 'epoch' WHERE key_hash IS NOT NULL;
@@ -0,0 +1,96 @@
+//! Accounts own uploads made with one of their API keys, sent as `Authorization: Bearer <key>`.
+//! Keys are only kept as sha256 digests, so they're shown once when they're created.
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap},
+};
+use sqlx::PgPool;
+
+use crate::{
+    errors::{AppError, AppResult},
+    models::{Account, ApiKey},
+    repository::{fetch_account_by_key, insert_api_key},
+    utilities::friendly_id,
+    AppContext,
+};
+
+const API_KEY_PREFIX: &str = "cf_";
+const MAX_LABEL_LEN: usize = 64;
+
+fn generate_api_key() -> String {
+    format!("{API_KEY_PREFIX}{}", friendly_id(40))
+}
+
+fn hash_api_key(key: &str) -> String {
+    sha256::digest(key)
+}
+
+/// Account of the API key the request was made with, `None` when it wasn't made with any.
+pub async fn authenticate(db: &PgPool, headers: &HeaderMap) -> AppResult<Option<Account>> {
+    let Some(value) = headers.get(AUTHORIZATION) else {
+        return Ok(None);
+    };
+
+    let key = value
+        .to_str()
+        .ok()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(AppError::InvalidApiKey)?;
+
+    let account = fetch_account_by_key(db, &hash_api_key(key))
+        .await?
+        .ok_or(AppError::InvalidApiKey)?;
+    Ok(Some(account))
+}
+
+fn context(parts: &Parts) -> AppResult<&AppContext> {
+    parts
+        .extensions
+        .get::<AppContext>()
+        .ok_or_else(|| anyhow::anyhow!("app context is missing").into())
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Account
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> AppResult<Self> {
+        let ctx = context(parts)?;
+        authenticate(&ctx.db, &parts.headers)
+            .await?
+            .ok_or(AppError::MissingApiKey)
+    }
+}
+
+/// Account for requests made with an API key, anonymous requests are still allowed
+/// but a key that isn't valid is rejected instead of being ignored.
+pub struct MaybeAccount(pub Option<Account>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for MaybeAccount
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> AppResult<Self> {
+        let ctx = context(parts)?;
+        Ok(Self(authenticate(&ctx.db, &parts.headers).await?))
+    }
+}
+
+/// Creates a key for the account, returned key is the only time it's seen in plain.
+pub async fn create_api_key(db: &PgPool, account_id: &str, label: Option<&str>) -> AppResult<(ApiKey, String)> {
+    if label.is_some_and(|label| label.chars().count() > MAX_LABEL_LEN) {
+        return Err(AppError::Validation(format!("key label can have at most {MAX_LABEL_LEN} characters.")));
+    }
+
+    let key = generate_api_key();
+    let api_key = insert_api_key(db, account_id, &hash_api_key(&key), label).await?;
+    Ok((api_key, key))
+}
@@ -0,0 +1,126 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use sqlx::{postgres::PgListener, PgPool};
+use tokio::{sync::RwLock, task::JoinHandle};
+
+use crate::{
+    errors::{AppError, AppResult},
+    metrics,
+    repository::{add_usage_stats, fetch_blacklist, insert_blacklist_entry, InsertBlacklistEntry, UsageDelta},
+};
+
+/// Channel every instance listens on, so a change made through one of them reaches the others.
+const CHANNEL: &str = "blacklist";
+
+/// Blacklisted sha256 hashes, lower-cased. Loaded from the database on the first check
+/// and reloaded whenever the blacklist changes.
+#[derive(Debug, Clone, Default)]
+pub struct Blacklist {
+    hashes: Arc<RwLock<Option<HashSet<String>>>>,
+}
+
+impl Blacklist {
+    pub async fn check(&self, db: &PgPool, hash: &str) -> AppResult<()> {
+        let hash = hash.to_lowercase();
+
+        if self.contains(db, &hash).await? {
+            metrics::blacklist_hit();
+            count_blacklisted(db, 1).await;
+            return Err(AppError::FileBlacklisted);
+        }
+        Ok(())
+    }
+
+    async fn contains(&self, db: &PgPool, hash: &str) -> AppResult<bool> {
+        if let Some(hashes) = &*self.hashes.read().await {
+            return Ok(hashes.contains(hash));
+        }
+
+        let mut hashes = self.hashes.write().await;
+        // someone else could've loaded it while we waited for the lock
+        if hashes.is_none() {
+            *hashes = Some(load(db).await?);
+        }
+        Ok(hashes.as_ref().is_some_and(|hashes| hashes.contains(hash)))
+    }
+
+    pub async fn reload(&self, db: &PgPool) -> AppResult<()> {
+        let loaded = load(db).await?;
+        *self.hashes.write().await = Some(loaded);
+        Ok(())
+    }
+
+    /// Reloads this instance and lets the others know that they should reload as well.
+    pub async fn changed(&self, db: &PgPool) -> AppResult<()> {
+        sqlx::query!("SELECT pg_notify($1, '')", CHANNEL).execute(db).await?;
+        self.reload(db).await
+    }
+}
+
+/// Counts uploads that were rejected or removed because they're blacklisted.
+pub async fn count_blacklisted(db: &PgPool, count: usize) {
+    if count == 0 {
+        return;
+    }
+
+    let delta = UsageDelta {
+        blacklisted: count as i64,
+        ..Default::default()
+    };
+    if let Err(why) = add_usage_stats(db, delta).await {
+        tracing::error!("failed to update usage stats: {why:?}");
+    }
+}
+
+async fn load(db: &PgPool) -> AppResult<HashSet<String>> {
+    let entries = fetch_blacklist(db).await?;
+    Ok(entries.into_iter().map(|entry| entry.hash).collect())
+}
+
+/// Adds hashes from the config file, which is how the blacklist used to be kept.
+pub async fn import(db: &PgPool, hashes: &[String]) -> AppResult<()> {
+    for hash in hashes {
+        let inserted = insert_blacklist_entry(
+            db,
+            InsertBlacklistEntry {
+                hash: hash.to_lowercase(),
+                reason: None,
+                added_by: String::from("config"),
+            },
+        )
+        .await?;
+
+        if inserted.is_some() {
+            tracing::info!("imported blacklisted hash {hash} from config");
+        }
+    }
+    Ok(())
+}
+
+/// Reloads the blacklist whenever another instance changes it.
+pub fn spawn_listener(db: PgPool, blacklist: Blacklist) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            if let Err(why) = listen(&db, &blacklist).await {
+                tracing::error!("blacklist listener failed: {why:?}");
+            }
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    })
+}
+
+async fn listen(db: &PgPool, blacklist: &Blacklist) -> AppResult<()> {
+    let mut listener = PgListener::connect_with(db).await?;
+    listener.listen(CHANNEL).await?;
+
+    // anything could've changed while we weren't listening
+    blacklist.reload(db).await?;
+
+    loop {
+        match listener.try_recv().await? {
+            Some(_) => tracing::debug!("blacklist changed, reloading"),
+            None => tracing::warn!("lost connection to blacklist channel, reloading"),
+        }
+        blacklist.reload(db).await?;
+    }
+}
@@ -0,0 +1,89 @@
+//! Guard that keeps uploads within the storage budget and away from filling up the disk.
+
+use std::{sync::Arc, time::{Duration, Instant}};
+
+use sqlx::PgPool;
+use tokio::{io, sync::Mutex};
+
+use crate::{
+    config::StorageConfig,
+    errors::{AppError, AppResult},
+    repository::fetch_storage_usage,
+    AppContext,
+};
+
+// `/stats` is public, so the sums behind it aren't run for every request
+const USAGE_TTL: Duration = Duration::from_secs(10);
+
+/// Stored bytes as of the last few seconds, shared by everyone who asks for them.
+#[derive(Debug, Clone, Default)]
+pub struct UsageCache {
+    last: Arc<Mutex<Option<(Instant, u64)>>>,
+}
+
+impl UsageCache {
+    pub async fn stored_bytes(&self, db: &PgPool) -> AppResult<u64> {
+        // held while it's fetched, so requests that come in meanwhile don't fetch it again
+        let mut last = self.last.lock().await;
+        if let Some((fetched, bytes)) = *last {
+            if fetched.elapsed() < USAGE_TTL {
+                return Ok(bytes);
+            }
+        }
+
+        let bytes = fetch_storage_usage(db).await? as u64;
+        *last = Some((Instant::now(), bytes));
+        Ok(bytes)
+    }
+}
+
+/// How many more bytes can be stored, `None` when there's neither budget nor free space threshold.
+pub async fn available(ctx: &AppContext) -> AppResult<Option<u64>> {
+    let budget = match ctx.cfg.general.storage_budget_bytes {
+        Some(budget) => {
+            let used = fetch_storage_usage(&ctx.db).await?;
+            Some(budget.saturating_sub(used as u64))
+        }
+        None => None,
+    };
+
+    Ok(match (budget, free_space(ctx)?) {
+        (Some(budget), Some(free)) => Some(budget.min(free)),
+        (budget, free) => budget.or(free),
+    })
+}
+
+/// Free space above `min_free_bytes` on every disk uploads are written to.
+pub fn free_space(ctx: &AppContext) -> AppResult<Option<u64>> {
+    let Some(min_free) = ctx.cfg.general.min_free_bytes else {
+        return Ok(None);
+    };
+
+    // uploads are staged in temp dir even when they're stored elsewhere
+    let mut free = disk_free(&ctx.cfg.general.temp_dir)?;
+    if let StorageConfig::Local = ctx.cfg.storage {
+        free = free.min(disk_free(&ctx.cfg.general.storage_dir)?);
+    }
+
+    Ok(Some(free.saturating_sub(min_free)))
+}
+
+/// Rejects `incoming` bytes when they don't fit into what's `available`.
+pub fn check(available: Option<u64>, incoming: u64) -> AppResult<()> {
+    match available {
+        Some(available) if incoming > available => Err(AppError::StorageFull),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn disk_free(dir: &str) -> io::Result<u64> {
+    let stat = rustix::fs::statvfs(dir)?;
+    Ok(stat.f_bavail.saturating_mul(stat.f_frsize))
+}
+
+// free space isn't checked where it can't be read
+#[cfg(not(unix))]
+fn disk_free(_dir: &str) -> io::Result<u64> {
+    Ok(u64::MAX)
+}
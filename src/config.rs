@@ -17,10 +17,118 @@ pub struct DatabaseConfig {
 #[derive(Debug, Clone, Deserialize)]
 pub struct GeneralConfig {
     pub bind_address: String,
+    /// Where the API is reached from outside, links in embeds point there.
+    pub public_url: Option<String>,
     pub cors_origin: String,
     pub storage_dir: String,
     pub temp_dir: String,
     pub max_preview_bytes: u64,
+    /// How much of a text file is shown in its preview.
+    #[serde(default = "default_text_preview_bytes")]
+    pub text_preview_bytes: u64,
+    /// Larger images aren't decoded to make thumbnails of them, see `max_thumbnail_source_bytes()`.
+    #[serde(default = "default_max_thumbnail_source_bytes")]
+    pub max_thumbnail_source_bytes: u64,
+    /// How many thumbnails are made at once, others wait for their turn.
+    #[serde(default = "default_max_concurrent_thumbnails")]
+    pub max_concurrent_thumbnails: usize,
+    /// How many bytes the files of one upload can have together.
+    #[serde(default = "default_max_upload_bytes")]
+    pub max_upload_bytes: u64,
+    /// Replaces `max_upload_bytes` for uploads made with an API key.
+    pub authenticated_max_upload_bytes: Option<u64>,
+    #[serde(default = "default_reaper_interval_secs")]
+    pub reaper_interval_secs: u64,
+    #[serde(default = "default_upload_session_ttl_hours")]
+    pub upload_session_ttl_hours: u32,
+    /// How many bytes all uploads together can take.
+    pub storage_budget_bytes: Option<u64>,
+    /// Uploads are rejected once there's less free space than that where they're written.
+    pub min_free_bytes: Option<u64>,
+}
+
+impl GeneralConfig {
+    /// `public_url` without a trailing slash, or the bind address when it isn't set.
+    pub fn public_url(&self) -> String {
+        match &self.public_url {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => format!("http://{}", self.bind_address),
+        }
+    }
+
+    /// Size of the largest image a thumbnail is made of, it's at least `max_preview_bytes`
+    /// so that images too big to preview get a thumbnail instead.
+    pub fn max_thumbnail_source_bytes(&self) -> u64 {
+        self.max_thumbnail_source_bytes.max(self.max_preview_bytes)
+    }
+}
+
+fn default_max_upload_bytes() -> u64 {
+    1024 * 1024 * 1024
+}
+
+fn default_text_preview_bytes() -> u64 {
+    64 * 1024
+}
+
+fn default_max_thumbnail_source_bytes() -> u64 {
+    128 * 1024 * 1024
+}
+
+fn default_max_concurrent_thumbnails() -> usize {
+    2
+}
+
+fn default_reaper_interval_secs() -> u64 {
+    300
+}
+
+fn default_upload_session_ttl_hours() -> u32 {
+    24
+}
+
+/// Rate limits of each route group, groups without one aren't limited.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LimitsConfig {
+    /// Take client address from `X-Forwarded-For`, only safe behind a proxy that sets it.
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+    /// How many bytes a client can upload per day (UTC).
+    pub upload_quota_bytes: Option<u64>,
+    pub upload: Option<RateLimitConfig>,
+    pub download: Option<RateLimitConfig>,
+    pub info: Option<RateLimitConfig>,
+    pub delete: Option<RateLimitConfig>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct RateLimitConfig {
+    /// Requests that can be made at once.
+    pub burst: u32,
+    /// Requests that become available again every minute.
+    pub per_minute: u32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AdminConfig {
+    #[serde(default)]
+    pub tokens: Vec<AdminToken>,
+}
+
+/// Prometheus metrics on `/metrics`, anyone who can reach it can read them.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MetricsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Serve them on this address instead of `bind_address`, so they can stay private.
+    pub bind_address: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AdminToken {
+    /// Who the token belongs to, admin actions are attributed to it.
+    pub name: String,
+    pub token: String,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -28,10 +136,41 @@ pub struct InstrumentationConfig {
     pub directives: Vec<String>,
 }
 
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum StorageConfig {
+    /// Files are stored in `general.storage_dir`.
+    #[default]
+    Local,
+    Memory,
+    S3(S3Config),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct S3Config {
+    pub bucket: String,
+    pub region: Option<String>,
+    pub endpoint: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    #[serde(default)]
+    pub allow_http: bool,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
+    /// Imported into the database blacklist on startup.
+    #[serde(default)]
     pub blacklist: Vec<String>,
+    #[serde(default)]
+    pub admin: AdminConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
     pub database: DatabaseConfig,
     pub general: GeneralConfig,
     pub instrumentation: InstrumentationConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
 }
@@ -0,0 +1,41 @@
+//! Content type of an upload, detected once while it's received and stored with it.
+
+use std::path::Path;
+
+use mime::Mime;
+
+use crate::text_preview;
+
+/// How much of a file is enough to recognize its type.
+pub const SNIFF_BYTES: usize = 8192;
+
+const TEXT: &str = "text/plain; charset=utf-8";
+// same as the column
+const MAX_LEN: usize = 255;
+
+/// Type of a file from its first bytes, then from what the client said it is, then from its extension.
+/// Text that isn't anything more specific is plain text.
+pub fn detect(head: &[u8], declared: Option<&str>, file_name: &str) -> Option<String> {
+    sniff(head)
+        .map(String::from)
+        .or_else(|| declared_or_guessed(declared, file_name))
+        .or_else(|| (!head.is_empty() && text_preview::is_text(head)).then(|| String::from(TEXT)))
+}
+
+/// Type recognized from the first bytes of a file.
+pub fn sniff(head: &[u8]) -> Option<&'static str> {
+    infer::get(head).map(|kind| kind.mime_type())
+}
+
+/// Type the client declared for a file, or the one its extension suggests when it didn't declare anything useful.
+pub fn declared_or_guessed(declared: Option<&str>, file_name: &str) -> Option<String> {
+    // browsers send octet-stream for anything they don't know
+    let declared = declared
+        .filter(|declared| declared.len() <= MAX_LEN)
+        .and_then(|declared| declared.parse::<Mime>().ok())
+        .filter(|declared| *declared != mime::APPLICATION_OCTET_STREAM);
+
+    declared
+        .or_else(|| mime_guess::from_path(Path::new(file_name)).first())
+        .map(|mime| mime.to_string())
+}
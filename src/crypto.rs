@@ -0,0 +1,213 @@
+use std::{ops::Range, sync::Arc};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use axum::async_trait;
+use bytes::Bytes;
+use chacha20poly1305::{
+    aead::{
+        rand_core::RngCore,
+        stream::{NewStream, StreamBE32, StreamPrimitive},
+        OsRng,
+    },
+    XChaCha20Poly1305,
+};
+use futures::stream;
+use tokio::io;
+use tokio_util::io::StreamReader;
+
+use crate::{
+    errors::{AppError, AppResult},
+    metrics::CryptoTimer,
+    models::Upload,
+    ranges::RangeSource,
+    storage::{ByteStream, Storage},
+    utilities::{read_chunk, DEC_CHUNK_SIZE, ENC_CHUNK_SIZE},
+};
+
+const ENC_CHUNK: u64 = ENC_CHUNK_SIZE as u64;
+const DEC_CHUNK: u64 = DEC_CHUNK_SIZE as u64;
+const TAG_SIZE: u64 = DEC_CHUNK - ENC_CHUNK;
+
+/// Random key, one can be shared by several uploads as long as each of them has its own nonce.
+pub fn generate_key() -> [u8; 32] {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+/// Random STREAM nonce for a new encrypted upload.
+pub fn generate_nonce() -> [u8; 19] {
+    let mut nonce = [0u8; 19];
+    OsRng.fill_bytes(&mut nonce);
+    nonce
+}
+
+/// Whatever the client supplied to decrypt an upload, either the key itself or password it's derived from.
+#[derive(Default, Clone, Copy)]
+pub struct Secret<'a> {
+    pub key: Option<&'a str>,
+    pub password: Option<&'a str>,
+}
+
+/// Argon2id parameters and salt a password is turned into key with, stored next to the nonce.
+#[derive(Clone)]
+pub struct Kdf {
+    pub salt: [u8; 16],
+    pub params: Params,
+}
+
+impl Kdf {
+    /// Random salt with recommended parameters.
+    pub fn generate() -> Self {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+
+        Self {
+            salt,
+            params: Params::DEFAULT,
+        }
+    }
+
+    /// Parameters stored with the upload, `None` when its key isn't derived from a password.
+    pub fn of(upload: &Upload) -> AppResult<Option<Self>> {
+        let (Some(salt), Some(memory), Some(iterations), Some(parallelism)) = (
+            &upload.kdf_salt,
+            upload.kdf_memory,
+            upload.kdf_iterations,
+            upload.kdf_parallelism,
+        ) else {
+            return Ok(None);
+        };
+
+        let mut salt_bytes = [0u8; 16];
+        hex::decode_to_slice(salt, &mut salt_bytes)?;
+        let params = Params::new(memory as u32, iterations as u32, parallelism as u32, Some(32))
+            .map_err(|_| AppError::CorruptedUpload)?;
+
+        Ok(Some(Self {
+            salt: salt_bytes,
+            params,
+        }))
+    }
+
+    /// Derives the key, it's deliberately slow so it runs on blocking thread.
+    pub async fn derive(&self, password: &str) -> AppResult<[u8; 32]> {
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone());
+        let password = password.to_string();
+        let salt = self.salt;
+
+        tokio::task::spawn_blocking(move || {
+            let mut key = [0u8; 32];
+            argon2
+                .hash_password_into(password.as_bytes(), &salt, &mut key)
+                .map_err(|why| AppError::Validation(format!("password can't be used, {why}.")))?;
+            Ok(key)
+        })
+        .await
+        .map_err(anyhow::Error::from)?
+    }
+}
+
+/// Checks supplied key, or key derived from supplied password, against hash stored with the upload.
+pub async fn upload_key(upload: &Upload, secret: Secret<'_>) -> AppResult<[u8; 32]> {
+    let key_hash = upload.key_hash.as_deref().ok_or(AppError::CorruptedUpload)?;
+    unlock(key_hash, Kdf::of(upload)?.as_ref(), secret).await
+}
+
+/// Same as [`upload_key`] for anything that has key hash and optionally password parameters.
+pub async fn unlock(key_hash: &str, kdf: Option<&Kdf>, secret: Secret<'_>) -> AppResult<[u8; 32]> {
+    let key = match (secret.key, secret.password, kdf) {
+        (Some(key), _, _) => key.to_string(),
+        (None, Some(password), Some(kdf)) => hex::encode(kdf.derive(password).await?),
+        (None, _, Some(_)) => return Err(AppError::MissingPassword),
+        (None, _, None) => return Err(AppError::MissingKey),
+    };
+
+    if sha256::digest(&key) != key_hash {
+        return Err(match secret.key {
+            Some(_) => AppError::InvalidDecryptionKey,
+            None => AppError::InvalidPassword,
+        });
+    }
+
+    let mut key_bytes = [0u8; 32];
+    hex::decode_to_slice(key, &mut key_bytes)?;
+    Ok(key_bytes)
+}
+
+/// Every encrypted file ends with a chunk shorter than [`DEC_CHUNK_SIZE`],
+/// which is only a tag when plaintext size is a multiple of [`ENC_CHUNK_SIZE`].
+pub fn plaintext_size(ciphertext_size: u64) -> u64 {
+    let full_chunks = ciphertext_size / DEC_CHUNK;
+    let last_chunk = ciphertext_size % DEC_CHUNK;
+    full_chunks * ENC_CHUNK + last_chunk.saturating_sub(TAG_SIZE)
+}
+
+/// Encrypted blob that can be decrypted from any position, because every
+/// STREAM chunk is authenticated with its own counter.
+#[derive(Clone)]
+pub struct EncryptedBlob {
+    pub storage: Arc<dyn Storage>,
+    pub key: String,
+    pub cipher_key: [u8; 32],
+    pub nonce: [u8; 19],
+    pub ciphertext_size: u64,
+    /// Shared by every range of the blob, so a request is measured as a whole.
+    pub timer: CryptoTimer,
+}
+
+impl EncryptedBlob {
+    pub fn plaintext_size(&self) -> u64 {
+        plaintext_size(self.ciphertext_size)
+    }
+}
+
+#[async_trait]
+impl RangeSource for EncryptedBlob {
+    async fn range(&self, range: Range<u64>) -> io::Result<ByteStream> {
+        if range.is_empty() {
+            return Ok(Box::pin(stream::empty()));
+        }
+
+        let first_chunk = range.start / ENC_CHUNK;
+        let last_chunk = (range.end - 1) / ENC_CHUNK;
+        let total_chunks = self.ciphertext_size.div_ceil(DEC_CHUNK);
+
+        let ciphertext = first_chunk * DEC_CHUNK..((last_chunk + 1) * DEC_CHUNK).min(self.ciphertext_size);
+        let reader = StreamReader::new(self.storage.get_range(&self.key, ciphertext).await?);
+
+        let stream = StreamBE32::<XChaCha20Poly1305>::new(
+            self.cipher_key.as_ref().into(),
+            self.nonce.as_ref().into(),
+        );
+
+        let (start, end) = (range.start, range.end);
+        let state = (reader, stream, first_chunk, self.timer.clone());
+        let chunks = stream::try_unfold(state, move |(mut reader, stream, chunk, timer)| async move {
+            if chunk > last_chunk {
+                return Ok(None);
+            }
+
+            let ciphertext = read_chunk(&mut reader, DEC_CHUNK_SIZE).await?;
+            let is_last = chunk + 1 == total_chunks;
+            let plaintext = timer
+                .time(ciphertext.len().saturating_sub(TAG_SIZE as usize), || stream.decrypt(chunk as u32, is_last, ciphertext.as_slice()))
+                .map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("failed to authenticate chunk {chunk}"),
+                    )
+                })?;
+
+            // only first and last chunk can contain bytes outside of the range
+            let chunk_start = chunk * ENC_CHUNK;
+            let to = ((end - chunk_start) as usize).min(plaintext.len());
+            let from = (start.saturating_sub(chunk_start) as usize).min(to);
+            let bytes = Bytes::from(plaintext).slice(from..to);
+
+            Ok(Some((bytes, (reader, stream, chunk + 1, timer))))
+        });
+
+        Ok(Box::pin(chunks))
+    }
+}
@@ -0,0 +1,70 @@
+//! End-to-end encrypted uploads, encrypted and decrypted only by clients.
+//!
+//! The server stores and serves them as they are, it only reads the header to check that the
+//! upload is well formed and to tell clients how to decrypt it. Format version 1 looks like this:
+//!
+//! | offset | size | content                                              |
+//! |--------|------|------------------------------------------------------|
+//! | 0      | 1    | format version, always `1`                           |
+//! | 1      | 4    | plaintext chunk size, big endian                     |
+//! | 5      | 19   | XChaCha20-Poly1305 STREAM (BE32) nonce               |
+//! | 24     | ...  | encrypted chunks, `chunk size + 16` bytes each       |
+//!
+//! The last chunk is encrypted as the last STREAM segment and is always shorter than the others,
+//! so it's only a 16 byte tag when plaintext size is a multiple of the chunk size. Offsets of
+//! chunks are fixed, so clients can decrypt any part of the upload by requesting a range of it.
+
+use crate::errors::{AppError, AppResult};
+
+pub const HEADER_SIZE: usize = 24;
+pub const FORMAT_VERSION: u8 = 1;
+const TAG_SIZE: u64 = 16;
+const MIN_CHUNK_SIZE: u32 = 1024;
+const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+pub struct Header {
+    pub version: u8,
+    pub chunk_size: u32,
+    pub nonce: [u8; 19],
+}
+
+impl Header {
+    pub fn parse(header: &[u8]) -> AppResult<Self> {
+        let invalid = |why: &str| AppError::Validation(format!("end-to-end encrypted upload {why}."));
+
+        let header: &[u8; HEADER_SIZE] = header
+            .try_into()
+            .map_err(|_| invalid("is shorter than its header"))?;
+
+        let version = header[0];
+        if version != FORMAT_VERSION {
+            return Err(invalid(&format!("has unsupported format version {version}")));
+        }
+
+        let chunk_size = u32::from_be_bytes(header[1..5].try_into().unwrap());
+        if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&chunk_size) {
+            return Err(invalid(&format!(
+                "chunk size has to be between {MIN_CHUNK_SIZE} and {MAX_CHUNK_SIZE} bytes"
+            )));
+        }
+
+        Ok(Self {
+            version,
+            chunk_size,
+            nonce: header[5..].try_into().unwrap(),
+        })
+    }
+
+    /// Size of the plaintext, `None` when the chunks can't be what the header says.
+    pub fn plaintext_size(&self, upload_size: u64) -> Option<u64> {
+        let encrypted_chunk = self.chunk_size as u64 + TAG_SIZE;
+        let ciphertext = upload_size.checked_sub(HEADER_SIZE as u64)?;
+
+        let last_chunk = ciphertext % encrypted_chunk;
+        if last_chunk < TAG_SIZE {
+            return None;
+        }
+
+        Some(ciphertext / encrypted_chunk * self.chunk_size as u64 + last_chunk - TAG_SIZE)
+    }
+}
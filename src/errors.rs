@@ -1,12 +1,15 @@
 use axum::{
     extract::multipart::MultipartError,
-    http::StatusCode,
+    http::{header::{CONTENT_RANGE, WWW_AUTHENTICATE}, HeaderMap, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
 use hex::FromHexError;
 use serde::Serialize;
 use tokio::io;
+use utoipa::ToSchema;
+
+use crate::ratelimit::RateLimit;
 
 pub type AppResult<T> = Result<T, AppError>;
 
@@ -26,6 +29,12 @@ pub enum AppError {
     CorruptedUpload,
     #[error("This file is encrypted! You need to provide decryption key.")]
     MissingKey,
+    #[error("This file is protected with a password! You need to provide it.")]
+    MissingPassword,
+    #[error("Invalid password! Please make sure you've entered correct one.")]
+    InvalidPassword,
+    #[error("This file is end-to-end encrypted, it's decrypted on your device so never send us its key!")]
+    KeyNotAccepted,
     #[error("You can only set either expiration hours or expiration downloads! You can't do both at once man ://")]
     BothExpirations,
     #[error("Oops.. Looks like this file expired! What a luck...")]
@@ -34,10 +43,42 @@ pub enum AppError {
     MediaTooBig,
     #[error("Preview of this file is not supported yet!")]
     PreviewNotSupported,
+    #[error("This upload isn't shared as an embed!")]
+    EmbedNotEnabled,
     #[error("Failed to upload, file is blacklisted.")]
     FileBlacklisted,
+    #[error("This hash isn't blacklisted.")]
+    HashNotBlacklisted,
+    #[error("You need a valid admin token to do this!")]
+    AdminUnauthorized,
+    #[error("You need to sign in with an API key to do this!")]
+    MissingApiKey,
+    #[error("This API key isn't valid! It might've been revoked.")]
+    InvalidApiKey,
+    #[error("We couldn't find this account!")]
+    AccountNotFound,
+    #[error("We couldn't find this API key! It might've been revoked already.")]
+    ApiKeyNotFound,
+    #[error("This file is too big! You can upload at most {0} bytes.")]
+    FileTooLarge(u64),
+    #[error("We're running out of space! Please try again later.")]
+    StorageFull,
+    #[error("Woah, slow down! Try again in {} seconds.", .0.retry_after.unwrap_or_default())]
+    RateLimited(RateLimit),
+    #[error("You've uploaded too much for today! Try again in {} seconds.", .0.reset)]
+    QuotaExceeded(RateLimit),
     #[error("Failed to validate your request, {0}")]
     Validation(String),
+    #[error("Requested range is outside of this file! It has only {0} bytes.")]
+    RangeNotSatisfiable(u64),
+    #[error("We couldn't find this upload session! It might've expired or been finished already.")]
+    UploadSessionNotFound,
+    #[error("Upload offset doesn't match! Ask for the current one and continue from there.")]
+    OffsetMismatch,
+    #[error("Chunks must be sent as application/offset+octet-stream.")]
+    InvalidChunkContentType,
+    #[error("Unsupported tus version! Only 1.0.0 is supported.")]
+    TusVersionMismatch,
 
     #[error("Something went wrong on our side! Please try again later.")]
     Other(#[from] anyhow::Error),
@@ -45,16 +86,32 @@ pub enum AppError {
     Crypto(chacha20poly1305::Error),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let code = match self {
+impl AppError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
             Self::UploadExpired => StatusCode::NOT_FOUND,
             Self::PreviewNotSupported => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::EmbedNotEnabled => StatusCode::NOT_FOUND,
+            Self::RangeNotSatisfiable(_) => StatusCode::RANGE_NOT_SATISFIABLE,
+            Self::UploadSessionNotFound => StatusCode::NOT_FOUND,
+            Self::OffsetMismatch => StatusCode::CONFLICT,
+            Self::InvalidChunkContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::TusVersionMismatch => StatusCode::PRECONDITION_FAILED,
+            Self::HashNotBlacklisted => StatusCode::NOT_FOUND,
+            Self::AdminUnauthorized => StatusCode::UNAUTHORIZED,
+            Self::MissingApiKey | Self::InvalidApiKey => StatusCode::UNAUTHORIZED,
+            Self::FileTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::StorageFull => StatusCode::INSUFFICIENT_STORAGE,
+            Self::AccountNotFound | Self::ApiKeyNotFound => StatusCode::NOT_FOUND,
+            Self::RateLimited(_) | Self::QuotaExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
             Self::Other(_) | Self::Crypto(_) => StatusCode::INTERNAL_SERVER_ERROR,
             _ => StatusCode::BAD_REQUEST,
-        };
+        }
+    }
 
-        let error_code = match self {
+    /// Stable code clients can tell errors apart by, messages can change anytime.
+    pub fn error_code(&self) -> &'static str {
+        match self {
             AppError::EmptyUpload => "empty-upload",
             AppError::InvalidFileName => "invalid-file-name",
             AppError::UploadNotFound => "upload-not-found",
@@ -62,25 +119,61 @@ impl IntoResponse for AppError {
             AppError::InvalidDecryptionKey => "invalid-decryption-key",
             AppError::CorruptedUpload => "corrupted-upload",
             AppError::MissingKey => "missing-key",
+            AppError::MissingPassword => "missing-password",
+            AppError::InvalidPassword => "invalid-password",
+            AppError::KeyNotAccepted => "key-not-accepted",
             AppError::BothExpirations => "both-expirations",
             AppError::UploadExpired => "upload-expired",
             AppError::MediaTooBig => "media-too-big",
             AppError::PreviewNotSupported => "preview-not-supported",
+            AppError::EmbedNotEnabled => "embed-not-enabled",
             AppError::FileBlacklisted => "file-blacklist",
+            AppError::HashNotBlacklisted => "hash-not-blacklisted",
+            AppError::AdminUnauthorized => "admin-unauthorized",
+            AppError::MissingApiKey => "missing-api-key",
+            AppError::InvalidApiKey => "invalid-api-key",
+            AppError::AccountNotFound => "account-not-found",
+            AppError::ApiKeyNotFound => "api-key-not-found",
+            AppError::FileTooLarge(_) => "file-too-large",
+            AppError::StorageFull => "storage-full",
+            AppError::RateLimited(_) => "rate-limited",
+            AppError::QuotaExceeded(_) => "quota-exceeded",
             AppError::Validation(_) => "validation",
+            AppError::RangeNotSatisfiable(_) => "range-not-satisfiable",
+            AppError::UploadSessionNotFound => "upload-session-not-found",
+            AppError::OffsetMismatch => "offset-mismatch",
+            AppError::InvalidChunkContentType => "invalid-chunk-content-type",
+            AppError::TusVersionMismatch => "tus-version-mismatch",
             AppError::Other(_) | AppError::Crypto(_) => "other",
-        };
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let code = self.status_code();
 
         // TODO(hito): better error handling, something like color_eyre
         if code == StatusCode::INTERNAL_SERVER_ERROR {
             tracing::error!("{self:?}");
         }
 
+        let mut headers = HeaderMap::new();
+        if let Self::RangeNotSatisfiable(size) = self {
+            headers.insert(CONTENT_RANGE, HeaderValue::from_str(&format!("bytes */{size}")).unwrap());
+        }
+        if let Self::RateLimited(limit) | Self::QuotaExceeded(limit) = &self {
+            limit.add_headers(&mut headers);
+        }
+        if let Self::AdminUnauthorized | Self::MissingApiKey | Self::InvalidApiKey = self {
+            headers.insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+
         let res = ErrorResponse {
-            error_code: error_code.to_string(),
+            error_code: self.error_code().to_string(),
             error: self.to_string(),
         };
-        (code, Json(res)).into_response()
+        (code, headers, Json(res)).into_response()
     }
 }
 
@@ -120,7 +213,7 @@ impl From<toml::de::Error> for AppError {
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ErrorResponse {
     error_code: String,
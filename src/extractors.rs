@@ -10,6 +10,7 @@ use serde::de::DeserializeOwned;
 
 use crate::errors::AppError;
 
+#[allow(dead_code)]
 pub struct Json<T>(pub T);
 
 #[async_trait]
@@ -36,8 +37,8 @@ where
 
                         format!("I couldn't deserialize JSON you sent me. {s}")
                     }
-                    JsonRejection::JsonSyntaxError(_) => format!("There's an issue in JSON you sent me, plz fix it."),
-                    JsonRejection::MissingJsonContentType(_) => format!("Hey.. Um.. It looks like you didn't send me correct content type! I need application/json."),
+                    JsonRejection::JsonSyntaxError(_) => "There's an issue in JSON you sent me, plz fix it.".to_string(),
+                    JsonRejection::MissingJsonContentType(_) => "Hey.. Um.. It looks like you didn't send me correct content type! I need application/json.".to_string(),
                     rej => rej.to_string(),
                 };
 
@@ -156,6 +157,7 @@ fn get_indefinite_article(word: &str) -> String {
     .to_string()
 }
 
+#[allow(dead_code)]
 fn first_letter_uppercase(s: String) -> String {
     let mut c = s.chars();
     match c.next() {
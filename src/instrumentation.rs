@@ -1,7 +1,10 @@
 use std::{io::IsTerminal, time::Duration};
 
 use axum::{
-    http::{Request, Response},
+    body::Body,
+    extract::MatchedPath,
+    http::{Request, Response, Uri},
+    middleware::{self, Next},
     Router,
 };
 use tower_http::trace::TraceLayer;
@@ -9,7 +12,7 @@ use tracing::Span;
 use tracing_error::ErrorLayer;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
-use crate::utilities::friendly_id;
+use crate::{metrics, utilities::friendly_id};
 
 pub fn setup(directives: &[String]) -> anyhow::Result<()> {
     let filter = filter_layer(directives)?;
@@ -45,14 +48,14 @@ fn filter_layer(directives: &[String]) -> anyhow::Result<EnvFilter> {
 }
 
 pub fn add_layer(router: Router) -> Router {
-    router.layer(
+    router.layer(middleware::from_fn(keep_matched_path)).layer(
         TraceLayer::new_for_http()
             .make_span_with(|req: &Request<_>| {
                 tracing::span!(
                     tracing::Level::INFO,
                     "request",
                     id = %friendly_id(8),
-                    uri = %req.uri(),
+                    uri = %redacted_uri(req.uri()),
                     method = %req.method(),
                     status = tracing::field::Empty,
                     latency = tracing::field::Empty,
@@ -68,6 +71,37 @@ pub fn add_layer(router: Router) -> Router {
                 );
                 span.record("status", tracing::field::display(res.status()));
                 tracing::trace!("responded");
+
+                // labelled by route instead of path, so ids don't make every request its own series
+                let route = res.extensions().get::<MatchedPath>().map_or("unmatched", MatchedPath::as_str);
+                metrics::record_request(route, res.status().as_u16(), latency);
             }),
     )
 }
+
+/// Hands the matched route over to the response, where it's labelled in metrics.
+async fn keep_matched_path(req: Request<Body>, next: Next) -> Response<Body> {
+    let matched_path = req.extensions().get::<MatchedPath>().cloned();
+    let mut res = next.run(req).await;
+    if let Some(matched_path) = matched_path {
+        res.extensions_mut().insert(matched_path);
+    }
+    res
+}
+
+/// Keys and passwords must never end up in logs.
+fn redacted_uri(uri: &Uri) -> String {
+    let Some(query) = uri.query() else {
+        return uri.to_string();
+    };
+
+    let query = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name @ ("key" | "password"), _)) => format!("{name}=[redacted]"),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    format!("{}?{query}", uri.path())
+}
@@ -1,29 +1,46 @@
+mod accounts;
+mod blacklist;
+mod capacity;
 mod errors;
+mod metrics;
 mod routes;
 mod instrumentation;
 mod models;
+mod ranges;
+mod ratelimit;
+mod reaper;
 mod repository;
+mod storage;
 mod tests;
+mod text_preview;
+mod thumbnails;
 mod utilities;
 mod config;
+mod content_type;
+mod crypto;
+mod e2e;
 mod extractors;
+mod zip;
 
 #[cfg(not(unix))]
 use std::future;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use axum::{
-    extract::DefaultBodyLimit, http::{HeaderValue, Method}, routing::{delete, get, post}, Extension, Json, Router
+    extract::DefaultBodyLimit, middleware, http::{header::{AUTHORIZATION, CONTENT_TYPE, LOCATION, RETRY_AFTER}, HeaderValue, Method}, routing::{delete, get, post}, Extension, Json, Router
 };
+use blacklist::Blacklist;
+use capacity::UsageCache;
 use config::Config;
 use dotenvy_macro::dotenv;
 use errors::AppResult;
-use routes::{delete::delete_endpoint, download::download_endpoint, info::info_endpoint, preview::preview_endpoint, stats::service_stats, upload::upload_endpoint};
+use ratelimit::{rate_limit, Group, RateLimiter, RATELIMIT_LIMIT, RATELIMIT_REMAINING, RATELIMIT_RESET};
+use routes::{admin, delete::delete_endpoint, download::download_endpoint, embed::{embed_endpoint, oembed_endpoint}, info::info_endpoint, me, metrics::metrics_endpoint, openapi::openapi_endpoint, preview::{preview_endpoint, DECRYPTION_KEY}, stats::service_stats, tus, upload::upload_endpoint, zip::zip_endpoint};
 use sqlx::{postgres::PgPoolOptions, PgPool};
-use tokio::{net::TcpListener, signal};
-use tower_http::{
-    cors::{AllowOrigin, CorsLayer},
-    limit::RequestBodyLimitLayer,
-};
+use storage::Storage;
+use tokio::{net::TcpListener, signal, sync::Semaphore};
+use thumbnails::ThumbnailCache;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 
 use crate::config::load_config;
 
@@ -34,30 +51,80 @@ const DATABASE_URL: &str = dotenv!("DATABASE_URL");
 struct AppContext {
     cfg: Config,
     db: PgPool,
+    storage: Arc<dyn Storage>,
+    blacklist: Blacklist,
+    limiter: RateLimiter,
+    usage: UsageCache,
+    /// Permits to make thumbnails, see `max_concurrent_thumbnails`.
+    renders: Arc<Semaphore>,
+    thumbnails: ThumbnailCache,
+}
+
+/// App without anyone listening for blacklist changes, which is all that tests need.
+#[cfg(test)]
+fn router(cfg: Config, db: PgPool, storage: Arc<dyn Storage>) -> Router {
+    app(AppContext {
+        renders: Arc::new(Semaphore::new(cfg.general.max_concurrent_thumbnails)),
+        cfg,
+        db,
+        storage,
+        blacklist: Blacklist::default(),
+        limiter: RateLimiter::default(),
+        usage: UsageCache::default(),
+        thumbnails: ThumbnailCache::default(),
+    })
 }
 
-fn router(cfg: Config, db: PgPool) -> Router {
+fn app(ctx: AppContext) -> Router {
+    let tus_headers = [
+        tus::TUS_RESUMABLE,
+        tus::TUS_VERSION_HEADER,
+        tus::TUS_EXTENSION,
+        tus::UPLOAD_LENGTH,
+        tus::UPLOAD_OFFSET,
+        tus::UPLOAD_METADATA,
+        tus::UPLOAD_EXPIRES,
+    ];
     let cors_layer = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::HEAD, Method::PATCH])
+        .allow_headers(tus_headers.iter().cloned().chain([AUTHORIZATION, CONTENT_TYPE, DECRYPTION_KEY]).collect::<Vec<_>>())
+        .expose_headers(
+            tus_headers
+                .iter()
+                .cloned()
+                .chain([LOCATION, RETRY_AFTER, RATELIMIT_LIMIT, RATELIMIT_REMAINING, RATELIMIT_RESET])
+                .collect::<Vec<_>>(),
+        )
         .allow_origin(AllowOrigin::exact(
-            HeaderValue::from_str(&cfg.general.cors_origin).unwrap(),
+            HeaderValue::from_str(&ctx.cfg.general.cors_origin).unwrap(),
         ))
         .allow_credentials(true);
 
-    let router = Router::new()
+    let limited = |group| middleware::from_fn_with_state(group, rate_limit);
+
+    let mut router = Router::new()
         .route("/health", get(health_check))
-        .route("/upload", post(upload_endpoint))
-        .route("/delete/:upload_id", delete(delete_endpoint))
-        .route("/download/:upload_id", get(download_endpoint))
-        .route("/info/:upload_id", get(info_endpoint))
-        .route("/preview/:upload_id", get(preview_endpoint))
-        .route("/stats", get(service_stats))
-        .layer((
-            DefaultBodyLimit::disable(),
-            RequestBodyLimitLayer::new(1024 * 1024 * 1024 + 1024),
-            Extension(AppContext { cfg, db }),
-            cors_layer,
-        ));
+        // uploads are limited by `max_upload_bytes` while they're received
+        .route("/upload", post(upload_endpoint).layer((limited(Group::Upload), DefaultBodyLimit::disable())))
+        .route("/delete/:upload_id", delete(delete_endpoint).layer(limited(Group::Delete)))
+        .route("/download/:upload_id", get(download_endpoint).layer(limited(Group::Download)))
+        .route("/info/:upload_id", get(info_endpoint).layer(limited(Group::Info)))
+        .route("/preview/:upload_id", get(preview_endpoint).layer(limited(Group::Download)))
+        .route("/embed/:upload_id", get(embed_endpoint).layer(limited(Group::Info)))
+        .route("/oembed", get(oembed_endpoint).layer(limited(Group::Info)))
+        .route("/stats", get(service_stats).layer(limited(Group::Info)))
+        .route("/openapi.json", get(openapi_endpoint))
+        .route("/zip", get(zip_endpoint).layer(limited(Group::Download)))
+        .nest("/tus", tus::router())
+        .nest("/me", me::router())
+        .nest("/admin", admin::router());
+
+    // otherwise they're served on their own address by `main`
+    if ctx.cfg.metrics.enabled && ctx.cfg.metrics.bind_address.is_none() {
+        router = router.route("/metrics", get(metrics_endpoint));
+    }
+
+    let router = router.layer((Extension(ctx), cors_layer));
 
     instrumentation::add_layer(router)
 }
@@ -69,14 +136,56 @@ async fn main() -> AppResult<()> {
     instrumentation::setup(&config.instrumentation.directives)?;
 
     let db = PgPoolOptions::new()
-        .max_connections(50)
+        .max_connections(config.database.max_connections)
         .connect(DATABASE_URL)
         .await?;
 
+    let storage = storage::from_config(&config)?;
+
+    reaper::spawn(
+        db.clone(),
+        storage.clone(),
+        Duration::from_secs(config.general.reaper_interval_secs),
+        config.general.upload_session_ttl_hours,
+    );
+
+    blacklist::import(&db, &config.blacklist).await?;
+    let blacklist = Blacklist::default();
+    blacklist::spawn_listener(db.clone(), blacklist.clone());
+
+    if config.metrics.enabled {
+        metrics::spawn_upkeep(Duration::from_secs(5));
+    }
+
     let listener = TcpListener::bind(&config.general.bind_address).await?;
     tracing::info!("api is available on http://{}", config.general.bind_address);
 
-    axum::serve(listener, router(config, db))
+    let ctx = AppContext {
+        renders: Arc::new(Semaphore::new(config.general.max_concurrent_thumbnails)),
+        cfg: config,
+        db,
+        storage,
+        blacklist,
+        limiter: RateLimiter::default(),
+        usage: UsageCache::default(),
+        thumbnails: ThumbnailCache::default(),
+    };
+    if let Some(address) = ctx.cfg.metrics.bind_address.clone().filter(|_| ctx.cfg.metrics.enabled) {
+        let listener = TcpListener::bind(&address).await?;
+        tracing::info!("metrics are available on http://{address}/metrics");
+
+        let router = Router::new()
+            .route("/metrics", get(metrics_endpoint))
+            .layer(Extension(ctx.clone()));
+        tokio::spawn(async move {
+            if let Err(why) = axum::serve(listener, router).await {
+                tracing::error!("metrics server failed: {why:?}");
+            }
+        });
+    }
+
+    // client addresses are needed for rate limits
+    axum::serve(listener, app(ctx).into_make_service_with_connect_info::<SocketAddr>())
         .with_graceful_shutdown(shutdown_signal())
         .await?;
 
@@ -107,6 +216,7 @@ async fn shutdown_signal() {
     }
 }
 
+#[utoipa::path(get, path = "/health", tag = "service", responses((status = 200, body = String)))]
 async fn health_check() -> Json<String> {
     Json(String::from("im alive!"))
 }
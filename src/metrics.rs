@@ -0,0 +1,139 @@
+//! Prometheus metrics. Every instance counts its own, so they're summed up when scraped.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use ::metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use sqlx::PgPool;
+use tokio::task::JoinHandle;
+
+const REQUEST_DURATION: &str = "http_request_duration_seconds";
+const REQUEST_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Installs the recorder the first time it's needed, anything recorded before that isn't kept.
+pub fn handle() -> &'static PrometheusHandle {
+    HANDLE.get_or_init(|| {
+        PrometheusBuilder::new()
+            .set_buckets_for_metric(Matcher::Full(REQUEST_DURATION.to_string()), REQUEST_BUCKETS)
+            .expect("buckets aren't empty")
+            .install_recorder()
+            .expect("no other metrics recorder is installed")
+    })
+}
+
+/// Keeps summaries from growing between scrapes.
+pub fn spawn_upkeep(interval: Duration) -> JoinHandle<()> {
+    let handle = handle();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            handle.run_upkeep();
+        }
+    })
+}
+
+/// Metrics in Prometheus text format, pool usage is read right before they're rendered.
+pub fn render(db: &PgPool) -> String {
+    gauge!("db_pool_connections").set(db.size() as f64);
+    gauge!("db_pool_idle_connections").set(db.num_idle() as f64);
+    handle().render()
+}
+
+pub fn record_request(route: &str, status: u16, latency: Duration) {
+    let labels = [("route", route.to_string()), ("status", status.to_string())];
+    counter!("http_requests_total", &labels).increment(1);
+    histogram!(REQUEST_DURATION, &labels).record(latency.as_secs_f64());
+}
+
+pub fn uploaded(bytes: u64) {
+    counter!("uploaded_bytes_total").increment(bytes);
+}
+
+pub fn downloaded(bytes: u64) {
+    counter!("downloaded_bytes_total").increment(bytes);
+}
+
+/// Time a request spent encrypting or decrypting, recorded as one observation once every clone is dropped.
+/// Throughput is `encrypted_bytes_total` over the sum of `encryption_duration_seconds`.
+#[derive(Clone)]
+pub struct CryptoTimer(Arc<CryptoTotals>);
+
+struct CryptoTotals {
+    bytes_metric: &'static str,
+    duration_metric: &'static str,
+    bytes: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl CryptoTimer {
+    pub fn encryption() -> Self {
+        Self::new("encrypted_bytes_total", "encryption_duration_seconds")
+    }
+
+    pub fn decryption() -> Self {
+        Self::new("decrypted_bytes_total", "decryption_duration_seconds")
+    }
+
+    fn new(bytes_metric: &'static str, duration_metric: &'static str) -> Self {
+        Self(Arc::new(CryptoTotals {
+            bytes_metric,
+            duration_metric,
+            bytes: AtomicU64::new(0),
+            nanos: AtomicU64::new(0),
+        }))
+    }
+
+    /// Runs `work` on `bytes` of input and adds how long it took.
+    pub fn time<T>(&self, bytes: usize, work: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = work();
+        self.0.nanos.fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        self.0.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        result
+    }
+}
+
+impl Drop for CryptoTotals {
+    fn drop(&mut self) {
+        let bytes = *self.bytes.get_mut();
+        // requests that failed before anything was encrypted or decrypted
+        if bytes == 0 {
+            return;
+        }
+        counter!(self.bytes_metric).increment(bytes);
+        histogram!(self.duration_metric).record(Duration::from_nanos(*self.nanos.get_mut()).as_secs_f64());
+    }
+}
+
+pub fn blacklist_hit() {
+    counter!("blacklist_hits_total").increment(1);
+}
+
+pub fn expired_uploads_deleted(count: usize) {
+    counter!("expired_uploads_deleted_total").increment(count as u64);
+}
+
+/// Counts an upload as in flight for as long as it's held.
+pub struct InFlightUpload(());
+
+impl InFlightUpload {
+    pub fn start() -> Self {
+        gauge!("uploads_in_flight").increment(1.0);
+        Self(())
+    }
+}
+
+impl Drop for InFlightUpload {
+    fn drop(&mut self) {
+        gauge!("uploads_in_flight").decrement(1.0);
+    }
+}
@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::Deserialize;
 
 #[derive(Deserialize)]
@@ -14,4 +14,106 @@ pub struct Upload {
     pub expiry_downloads: Option<i32>,
     pub embedded: bool,
     pub created_at: DateTime<Utc>,
+    pub collection_id: Option<String>,
+    pub kdf_salt: Option<String>,
+    pub kdf_memory: Option<i32>,
+    pub kdf_iterations: Option<i32>,
+    pub kdf_parallelism: Option<i32>,
+    pub e2e_version: Option<i16>,
+    pub e2e_chunk_size: Option<i32>,
+    pub blob_hash: Option<String>,
+    pub owner_id: Option<String>,
+    pub content_type: Option<String>,
+}
+
+impl Upload {
+    /// Key of the upload's content in storage, deduplicated uploads share it.
+    pub fn storage_key(&self) -> &str {
+        self.blob_hash.as_deref().unwrap_or(&self.id)
+    }
+
+    /// Whether the upload is past its expiry, even if the reaper didn't get to it yet.
+    pub fn is_expired(&self) -> bool {
+        let by_hours = self
+            .expiry_hours
+            .is_some_and(|hours| Utc::now() >= self.created_at + Duration::hours(hours as _));
+        let by_downloads = self
+            .expiry_downloads
+            .is_some_and(|downloads| self.downloads >= downloads);
+        by_hours || by_downloads
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Collection {
+    pub id: String,
+    pub key_hash: Option<String>,
+    pub delete_key: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct UploadSession {
+    pub id: String,
+    pub delete_key: String,
+    pub key_hash: Option<String>,
+    pub nonce: Option<String>,
+    pub file_name: String,
+    pub upload_length: i64,
+    pub upload_offset: i64,
+    pub parts: Vec<String>,
+    pub tail: Vec<u8>,
+    pub expiry_hours: Option<i32>,
+    pub expiry_downloads: Option<i32>,
+    pub embedded: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub owner_id: Option<String>,
+    pub content_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BlacklistEntry {
+    pub hash: String,
+    pub reason: Option<String>,
+    pub added_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Usage in one bucket of time, starting at `start`.
+#[derive(Deserialize)]
+pub struct UsageBucket {
+    pub start: DateTime<Utc>,
+    pub uploads: i64,
+    pub bytes_in: i64,
+    pub encrypted_uploads: i64,
+    pub downloads: i64,
+    pub bytes_out: i64,
+    pub expired: i64,
+    pub blacklisted: i64,
+}
+
+#[derive(Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub admin: String,
+    pub action: String,
+    pub target: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Account {
+    pub id: String,
+    pub name: String,
+    pub max_file_bytes: Option<i64>,
+    pub default_expiry_hours: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct ApiKey {
+    pub id: i64,
+    pub label: Option<String>,
+    pub created_at: DateTime<Utc>,
 }
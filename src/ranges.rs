@@ -0,0 +1,240 @@
+use std::{ops::Range, sync::Arc};
+
+use axum::{
+    async_trait,
+    body::Body,
+    http::{
+        header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, LAST_MODIFIED},
+        HeaderMap, HeaderValue, StatusCode,
+    },
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt, TryStreamExt};
+use tokio::io;
+
+use crate::{
+    errors::{AppError, AppResult},
+    models::Upload,
+    storage::{ByteStream, Storage},
+    utilities::friendly_id,
+};
+
+// more ranges than that are most likely an attempt to make us do a lot of work for nothing
+const MAX_RANGES: usize = 16;
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Anything that can be served in parts.
+#[async_trait]
+pub trait RangeSource: Send + Sync + 'static {
+    async fn range(&self, range: Range<u64>) -> io::Result<ByteStream>;
+
+    async fn all(&self, size: u64) -> io::Result<ByteStream> {
+        self.range(0..size).await
+    }
+}
+
+/// Plain blob served straight from storage.
+pub struct StoredBlob {
+    pub storage: Arc<dyn Storage>,
+    pub key: String,
+}
+
+#[async_trait]
+impl RangeSource for StoredBlob {
+    async fn range(&self, range: Range<u64>) -> io::Result<ByteStream> {
+        self.storage.get_range(&self.key, range).await
+    }
+
+    async fn all(&self, _size: u64) -> io::Result<ByteStream> {
+        self.storage.get(&self.key).await
+    }
+}
+
+/// Validators used for `If-Range`, uploads never change so both of them are strong.
+pub struct Validators {
+    pub etag: String,
+    pub last_modified: DateTime<Utc>,
+}
+
+impl Validators {
+    pub fn of(upload: &Upload) -> Self {
+        Self {
+            etag: format!(r#""{}-{:x}""#, upload.id, upload.created_at.timestamp()),
+            last_modified: upload.created_at,
+        }
+    }
+
+    fn http_date(&self) -> String {
+        self.last_modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+    }
+
+    fn matches(&self, if_range: &str) -> bool {
+        let if_range = if_range.trim();
+        if if_range.starts_with('"') {
+            return if_range == self.etag;
+        }
+
+        // weak etags never match, so only dates are left
+        match DateTime::parse_from_rfc2822(if_range) {
+            Ok(date) => date.timestamp() == self.last_modified.timestamp(),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Ranges that should be served, empty when whole content is requested.
+pub fn requested_ranges(
+    headers: &HeaderMap,
+    size: u64,
+    validators: &Validators,
+) -> AppResult<Vec<Range<u64>>> {
+    let Some(range) = headers.get(axum::http::header::RANGE) else {
+        return Ok(Vec::new());
+    };
+
+    if let Some(if_range) = headers.get(IF_RANGE) {
+        match if_range.to_str() {
+            Ok(if_range) if validators.matches(if_range) => (),
+            _ => return Ok(Vec::new()),
+        }
+    }
+
+    let Ok(range) = range.to_str() else {
+        return Ok(Vec::new());
+    };
+
+    match parse_range(range, size) {
+        Some(Ok(ranges)) if ranges.len() <= MAX_RANGES => Ok(ranges),
+        Some(Ok(_)) | None => Ok(Vec::new()),
+        Some(Err(())) => Err(AppError::RangeNotSatisfiable(size)),
+    }
+}
+
+/// Parses value of `Range` header, ranges are end exclusive.
+///
+/// Returns `None` when the header is malformed and should be ignored,
+/// or `Some(Err)` when none of the ranges can be satisfied.
+pub fn parse_range(header: &str, size: u64) -> Option<Result<Vec<Range<u64>>, ()>> {
+    let (unit, specs) = header.trim().split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (start, end) = spec.split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+
+        let range = if start.is_empty() {
+            let suffix: u64 = end.parse().ok()?;
+            (suffix > 0 && size > 0).then(|| size - suffix.min(size)..size)
+        } else {
+            let start: u64 = start.parse().ok()?;
+            let end = if end.is_empty() {
+                u64::MAX
+            } else {
+                let end: u64 = end.parse().ok()?;
+                if end < start {
+                    return None;
+                }
+                end
+            };
+            (start < size).then(|| start..end.min(size - 1) + 1)
+        };
+
+        ranges.extend(range);
+    }
+
+    if ranges.is_empty() {
+        return Some(Err(()));
+    }
+
+    Some(Ok(ranges))
+}
+
+/// Builds response with whole content or requested parts of it.
+pub async fn respond(
+    source: Arc<dyn RangeSource>,
+    size: u64,
+    ranges: Vec<Range<u64>>,
+    validators: &Validators,
+    content_type: Option<&str>,
+) -> AppResult<Response> {
+    let mut headers = HeaderMap::new();
+    if let Some(content_type) = content_type.filter(|_| ranges.len() <= 1) {
+        headers.insert(CONTENT_TYPE, header_value(content_type));
+    }
+    headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    headers.insert(ETAG, header_value(&validators.etag));
+    headers.insert(LAST_MODIFIED, header_value(&validators.http_date()));
+
+    let (status, length, body) = match ranges.as_slice() {
+        [] => (StatusCode::OK, size, Body::from_stream(source.all(size).await?)),
+        [range] => {
+            let content_range = format!("bytes {}-{}/{size}", range.start, range.end - 1);
+            headers.insert(CONTENT_RANGE, header_value(&content_range));
+
+            let stream = source.range(range.clone()).await?;
+            (StatusCode::PARTIAL_CONTENT, range.end - range.start, Body::from_stream(stream))
+        }
+        ranges => {
+            let boundary = friendly_id(24);
+            let multipart_type = format!("multipart/byteranges; boundary={boundary}");
+            headers.insert(CONTENT_TYPE, header_value(&multipart_type));
+
+            let part_type = content_type.unwrap_or(DEFAULT_CONTENT_TYPE);
+            let (length, stream) = multipart_body(source, size, ranges.to_vec(), &boundary, part_type);
+            (StatusCode::PARTIAL_CONTENT, length, Body::from_stream(stream))
+        }
+    };
+
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(length));
+    Ok((status, headers, body).into_response())
+}
+
+fn multipart_body(
+    source: Arc<dyn RangeSource>,
+    size: u64,
+    ranges: Vec<Range<u64>>,
+    boundary: &str,
+    content_type: &str,
+) -> (u64, ByteStream) {
+    let parts = ranges
+        .into_iter()
+        .map(|range| {
+            let head = format!(
+                "\r\n--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: bytes {}-{}/{size}\r\n\r\n",
+                range.start,
+                range.end - 1,
+            );
+            (Bytes::from(head), range)
+        })
+        .collect::<Vec<_>>();
+    let tail = Bytes::from(format!("\r\n--{boundary}--\r\n"));
+
+    let length = parts
+        .iter()
+        .map(|(head, range)| head.len() as u64 + range.end - range.start)
+        .sum::<u64>()
+        + tail.len() as u64;
+
+    // every part is opened only after the previous one was fully sent
+    let body = stream::iter(parts)
+        .then(move |(head, range)| {
+            let source = source.clone();
+            async move {
+                let data = source.range(range).await?;
+                io::Result::Ok(stream::once(async { Ok(head) }).chain(data))
+            }
+        })
+        .try_flatten()
+        .chain(stream::once(async { Ok(tail) }));
+
+    (length, Box::pin(body))
+}
+
+fn header_value(value: &str) -> HeaderValue {
+    HeaderValue::from_str(value).expect("header value should be visible ascii")
+}
@@ -0,0 +1,304 @@
+//! Token bucket rate limits for each route group and daily upload quotas, both counted per client.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, Request, State},
+    http::{
+        header::RETRY_AFTER,
+        request::Parts,
+        Extensions, HeaderMap, HeaderName, HeaderValue,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{Days, Utc};
+
+use crate::{
+    accounts::authenticate,
+    config::{LimitsConfig, RateLimitConfig},
+    errors::{AppError, AppResult},
+    repository::{add_upload_usage, fetch_upload_usage},
+    AppContext,
+};
+
+pub const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+pub const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+pub const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+// buckets that filled up again are forgotten at most this often
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Group {
+    Upload,
+    Download,
+    Info,
+    Delete,
+}
+
+impl Group {
+    fn config(self, limits: &LimitsConfig) -> Option<RateLimitConfig> {
+        match self {
+            Self::Upload => limits.upload,
+            Self::Download => limits.download,
+            Self::Info => limits.info,
+            Self::Delete => limits.delete,
+        }
+    }
+}
+
+/// Who requests are counted against.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Client {
+    /// Requests made with a valid API key count against its account, wherever they come from.
+    Account(String),
+    Ip(IpAddr),
+    /// Address isn't known when the app is served without connection info, like in tests.
+    Unknown,
+}
+
+impl Client {
+    async fn resolve(ctx: &AppContext, headers: &HeaderMap, extensions: &Extensions) -> Self {
+        // invalid keys are rejected by the endpoint, until then they're counted by address
+        if let Ok(Some(account)) = authenticate(&ctx.db, headers).await {
+            return Self::Account(account.id);
+        }
+        Self::by_address(headers, extensions, &ctx.cfg.limits)
+    }
+
+    fn by_address(headers: &HeaderMap, extensions: &Extensions, limits: &LimitsConfig) -> Self {
+        // the last address is the one our proxy appended, anything before it is up to the client
+        let forwarded = limits
+            .trust_forwarded_for
+            .then(|| headers.get("x-forwarded-for"))
+            .flatten()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|addr| addr.trim().parse().ok());
+
+        let connected = extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+
+        match forwarded.or(connected) {
+            Some(ip) => Self::Ip(ip),
+            None => Self::Unknown,
+        }
+    }
+
+    fn key(&self) -> String {
+        match self {
+            Self::Account(id) => format!("account:{id}"),
+            Self::Ip(ip) => format!("ip:{ip}"),
+            Self::Unknown => String::from("unknown"),
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Client
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> AppResult<Self> {
+        let ctx = parts
+            .extensions
+            .get::<AppContext>()
+            .ok_or_else(|| anyhow::anyhow!("app context is missing"))?;
+        Ok(Self::resolve(ctx, &parts.headers, &parts.extensions).await)
+    }
+}
+
+/// State of a limit after a request, sent back in `RateLimit-*` headers.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub limit: u64,
+    pub remaining: u64,
+    /// Seconds until the limit is fully available again.
+    pub reset: u64,
+    /// Seconds until the request can be made, only set when it was over the limit.
+    pub retry_after: Option<u64>,
+}
+
+impl RateLimit {
+    pub fn add_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(RATELIMIT_LIMIT, HeaderValue::from(self.limit));
+        headers.insert(RATELIMIT_REMAINING, HeaderValue::from(self.remaining));
+        headers.insert(RATELIMIT_RESET, HeaderValue::from(self.reset));
+        if let Some(retry_after) = self.retry_after {
+            headers.insert(RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    capacity: f64,
+    per_second: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.updated = now;
+    }
+
+    fn secs_until(&self, tokens: f64) -> u64 {
+        ((tokens - self.tokens).max(0.0) / self.per_second).ceil() as u64
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    buckets: HashMap<(Group, Client), Bucket>,
+    pruned: Instant,
+}
+
+/// Buckets live in memory, so every instance counts requests on its own.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    inner: Arc<Mutex<Buckets>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                pruned: Instant::now(),
+            })),
+        }
+    }
+}
+
+impl RateLimiter {
+    fn take(&self, group: Group, client: Client, config: RateLimitConfig) -> Result<RateLimit, RateLimit> {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+
+        if now.duration_since(inner.pruned) >= PRUNE_INTERVAL {
+            inner.buckets.retain(|_, bucket| {
+                bucket.refill(now);
+                bucket.tokens < bucket.capacity
+            });
+            inner.pruned = now;
+        }
+
+        let capacity = config.burst.max(1) as f64;
+        let bucket = inner.buckets.entry((group, client)).or_insert_with(|| Bucket {
+            tokens: capacity,
+            capacity,
+            per_second: config.per_minute.max(1) as f64 / 60.0,
+            updated: now,
+        });
+        bucket.refill(now);
+
+        if bucket.tokens < 1.0 {
+            let retry_after = bucket.secs_until(1.0);
+            return Err(RateLimit {
+                limit: capacity as u64,
+                remaining: 0,
+                reset: bucket.secs_until(capacity),
+                retry_after: Some(retry_after),
+            });
+        }
+
+        bucket.tokens -= 1.0;
+        Ok(RateLimit {
+            limit: capacity as u64,
+            remaining: bucket.tokens as u64,
+            reset: bucket.secs_until(capacity),
+            retry_after: None,
+        })
+    }
+}
+
+/// Middleware limiting requests of the group it's given as state.
+pub async fn rate_limit(State(group): State<Group>, req: Request, next: Next) -> Response {
+    let mut outcome = None;
+    if let Some(ctx) = req.extensions().get::<AppContext>() {
+        if let Some(config) = group.config(&ctx.cfg.limits) {
+            let client = Client::resolve(ctx, req.headers(), req.extensions()).await;
+            outcome = Some(ctx.limiter.take(group, client, config));
+        }
+    }
+
+    match outcome {
+        None => next.run(req).await,
+        Some(Ok(limit)) => {
+            let mut res = next.run(req).await;
+            limit.add_headers(res.headers_mut());
+            res
+        }
+        Some(Err(limit)) => AppError::RateLimited(limit).into_response(),
+    }
+}
+
+/// Daily upload quota of a client and how much of it was already used.
+#[derive(Debug, Clone, Copy)]
+pub struct Quota {
+    pub limit: u64,
+    pub used: u64,
+}
+
+impl Quota {
+    pub fn remaining(&self) -> u64 {
+        self.limit.saturating_sub(self.used)
+    }
+
+    /// Error for an upload that doesn't fit in what's left, it's available again at midnight (UTC).
+    pub fn exceeded(&self) -> AppError {
+        let now = Utc::now();
+        let midnight = now
+            .date_naive()
+            .checked_add_days(Days::new(1))
+            .and_then(|day| day.and_hms_opt(0, 0, 0))
+            .map(|midnight| midnight.and_utc());
+        let reset = midnight.map_or(0, |midnight| (midnight - now).num_seconds().max(1) as u64);
+
+        AppError::QuotaExceeded(RateLimit {
+            limit: self.limit,
+            remaining: self.remaining(),
+            reset,
+            retry_after: Some(reset),
+        })
+    }
+}
+
+/// Fails when `incoming` bytes don't fit in the client's quota, otherwise returns the quota when there's one.
+/// Size of a body isn't always known upfront, so what's actually received has to be checked against it as well.
+pub async fn check_quota(ctx: &AppContext, client: &Client, incoming: u64) -> AppResult<Option<Quota>> {
+    let Some(limit) = ctx.cfg.limits.upload_quota_bytes else {
+        return Ok(None);
+    };
+
+    let used = fetch_upload_usage(&ctx.db, &client.key()).await? as u64;
+    let quota = Quota { limit, used };
+    if used.saturating_add(incoming) <= limit && used < limit {
+        return Ok(Some(quota));
+    }
+
+    Err(quota.exceeded())
+}
+
+pub async fn record_usage(ctx: &AppContext, client: &Client, bytes: u64) {
+    if ctx.cfg.limits.upload_quota_bytes.is_none() {
+        return;
+    }
+
+    if let Err(why) = add_upload_usage(&ctx.db, &client.key(), bytes).await {
+        tracing::error!("failed to record upload usage of {client:?}: {why:?}");
+    }
+}
@@ -0,0 +1,122 @@
+use std::{sync::Arc, time::Duration};
+
+use sqlx::PgPool;
+use tokio::{task::JoinHandle, time::MissedTickBehavior};
+
+use crate::{
+    errors::AppResult,
+    metrics,
+    repository::{add_usage_stats, delete_empty_collections, delete_past_upload_usage, delete_stale_upload_sessions, fetch_expired_uploads, UsageDelta},
+    routes::{delete::delete_upload, tus::remove_blobs},
+    storage::Storage,
+};
+
+// arbitrary key for `pg_try_advisory_xact_lock`, makes sure that only one instance
+// sweeps at a time when several of them are connected to the same database
+const REAPER_LOCK_KEY: i64 = 0x6369_7068_6572;
+
+pub fn spawn(
+    db: PgPool,
+    storage: Arc<dyn Storage>,
+    interval: Duration,
+    session_ttl_hours: u32,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            match purge_expired(&db, storage.as_ref()).await {
+                Ok(0) => tracing::trace!("no expired uploads to purge"),
+                Ok(purged) => tracing::info!("purged {purged} expired uploads"),
+                Err(why) => tracing::error!("failed to purge expired uploads: {why:?}"),
+            }
+
+            match purge_stale_sessions(&db, storage.as_ref(), session_ttl_hours).await {
+                Ok(0) => (),
+                Ok(purged) => tracing::info!("purged {purged} stale upload sessions"),
+                Err(why) => tracing::error!("failed to purge stale upload sessions: {why:?}"),
+            }
+
+            // quotas are daily, so past usage isn't needed anymore
+            if let Err(why) = delete_past_upload_usage(&db).await {
+                tracing::error!("failed to purge past upload usage: {why:?}");
+            }
+        }
+    })
+}
+
+/// Removes every upload that expired either by time or by downloads count.
+/// Returns how many uploads were purged, zero if another instance holds the lock.
+pub async fn purge_expired(db: &PgPool, storage: &dyn Storage) -> AppResult<usize> {
+    let mut tx = db.begin().await?;
+
+    let locked = sqlx::query_scalar!("SELECT pg_try_advisory_xact_lock($1)", REAPER_LOCK_KEY)
+        .fetch_one(&mut *tx)
+        .await?
+        .unwrap_or(false);
+
+    if !locked {
+        tracing::debug!("another instance is purging expired uploads, skipping");
+        return Ok(0);
+    }
+
+    let mut purged = 0;
+    for upload in fetch_expired_uploads(db).await? {
+        match delete_upload(db, storage, &upload.id).await {
+            Ok(()) => {
+                tracing::debug!(id = upload.id, file_name = upload.file_name, bytes = upload.bytes, "purged expired upload");
+                purged += 1;
+            }
+            Err(why) => tracing::warn!("failed to purge expired upload `{}`: {why:?}", upload.id),
+        }
+    }
+
+    count_expired(db, purged).await;
+
+    // collections go away together with their last upload
+    let collections = delete_empty_collections(db).await?;
+    if collections > 0 {
+        tracing::debug!("purged {collections} empty collections");
+    }
+
+    // lock is released with the transaction
+    tx.commit().await?;
+
+    Ok(purged)
+}
+
+/// Counts uploads that were removed because they expired.
+pub async fn count_expired(db: &PgPool, count: usize) {
+    if count == 0 {
+        return;
+    }
+
+    metrics::expired_uploads_deleted(count);
+    let delta = UsageDelta {
+        expired: count as i64,
+        ..Default::default()
+    };
+    if let Err(why) = add_usage_stats(db, delta).await {
+        tracing::error!("failed to update usage stats: {why:?}");
+    }
+}
+
+/// Removes resumable upload sessions that weren't continued for `ttl_hours`, with their parts.
+pub async fn purge_stale_sessions(db: &PgPool, storage: &dyn Storage, ttl_hours: u32) -> AppResult<usize> {
+    let sessions = delete_stale_upload_sessions(db, ttl_hours).await?;
+
+    for session in &sessions {
+        remove_blobs(storage, session.parts.iter().map(String::as_str)).await;
+        tracing::debug!(
+            id = session.id,
+            started = %session.created_at,
+            offset = session.upload_offset,
+            "purged stale upload session"
+        );
+    }
+
+    Ok(sessions.len())
+}
@@ -1,6 +1,10 @@
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgExecutor, PgConnection, PgPool};
 
-use crate::models::Upload;
+use crate::{
+    crypto::Kdf,
+    models::{Account, ApiKey, AuditEntry, BlacklistEntry, Collection, Upload, UploadSession, UsageBucket},
+};
 
 pub async fn fetch_upload(db: &PgPool, id: &str) -> sqlx::Result<Option<Upload>> {
     let res = sqlx::query_as!(Upload, "SELECT * FROM uploads WHERE id = $1", id)
@@ -9,13 +13,15 @@ pub async fn fetch_upload(db: &PgPool, id: &str) -> sqlx::Result<Option<Upload>>
     Ok(res)
 }
 
-pub async fn insert_upload(db: &PgPool, insert: InsertUpload) -> sqlx::Result<()> {
+pub async fn insert_upload(db: impl PgExecutor<'_>, insert: InsertUpload) -> sqlx::Result<()> {
     sqlx::query!(
         r#"
         INSERT INTO uploads
-            (id, key_hash, delete_key, nonce, file_name, bytes, expiry_hours, expiry_downloads, embedded)
+            (id, key_hash, delete_key, nonce, file_name, bytes, expiry_hours, expiry_downloads, embedded,
+             kdf_salt, kdf_memory, kdf_iterations, kdf_parallelism, e2e_version, e2e_chunk_size, blob_hash, owner_id,
+             content_type)
         VALUES
-            ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18)
         "#,
         insert.id,
         insert.key_hash,
@@ -26,17 +32,73 @@ pub async fn insert_upload(db: &PgPool, insert: InsertUpload) -> sqlx::Result<()
         insert.expiry_hours.map(|n| n as i32),
         insert.expiry_downloads.map(|n| n as i32),
         insert.embedded,
+        insert.kdf.as_ref().map(|kdf| hex::encode(kdf.salt)),
+        insert.kdf.as_ref().map(|kdf| kdf.params.m_cost() as i32),
+        insert.kdf.as_ref().map(|kdf| kdf.params.t_cost() as i32),
+        insert.kdf.as_ref().map(|kdf| kdf.params.p_cost() as i32),
+        insert.e2e_version.map(|n| n as i16),
+        insert.e2e_chunk_size.map(|n| n as i32),
+        insert.blob_hash,
+        insert.owner_id,
+        insert.content_type,
     )
     .execute(db)
     .await?;
     Ok(())
 }
 
-pub async fn delete_upload(db: &PgPool, id: &str) -> sqlx::Result<()> {
-    sqlx::query!("DELETE FROM uploads WHERE id = $1", id)
-        .execute(db)
+pub async fn delete_upload(db: &PgPool, id: &str) -> sqlx::Result<Option<Upload>> {
+    let res = sqlx::query_as!(Upload, "DELETE FROM uploads WHERE id = $1 RETURNING *", id)
+        .fetch_optional(db)
         .await?;
-    Ok(())
+    Ok(res)
+}
+
+/// Adds reference to the blob, returns true when it's new and its content still has to be stored.
+/// Row stays locked until the transaction ends, so nobody can see it before the content is there.
+pub async fn acquire_blob(conn: &mut PgConnection, hash: &str, bytes: u64) -> sqlx::Result<bool> {
+    let refs = sqlx::query_scalar!(
+        r#"
+        INSERT INTO blobs (hash, bytes, refs) VALUES ($1, $2, 1)
+        ON CONFLICT (hash) DO UPDATE SET refs = blobs.refs + 1
+        RETURNING refs
+        "#,
+        hash,
+        bytes as i64,
+    )
+    .fetch_one(conn)
+    .await?;
+    Ok(refs == 1)
+}
+
+/// Removes reference to the blob, returns true when it was the last one and the blob is gone.
+pub async fn release_blob(conn: &mut PgConnection, hash: &str) -> sqlx::Result<bool> {
+    let refs = sqlx::query_scalar!("UPDATE blobs SET refs = refs - 1 WHERE hash = $1 RETURNING refs", hash)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    if refs.is_some_and(|refs| refs <= 0) {
+        sqlx::query!("DELETE FROM blobs WHERE hash = $1", hash)
+            .execute(conn)
+            .await?;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+pub async fn fetch_expired_uploads(db: &PgPool) -> sqlx::Result<Vec<Upload>> {
+    let res = sqlx::query_as!(
+        Upload,
+        r#"
+        SELECT * FROM uploads
+        WHERE
+            (expiry_hours IS NOT NULL AND created_at + make_interval(hours => expiry_hours) <= NOW())
+            OR (expiry_downloads IS NOT NULL AND downloads >= expiry_downloads)
+        "#
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(res)
 }
 
 pub async fn add_download(db: &PgPool, id: &str) -> sqlx::Result<()> {
@@ -49,13 +111,508 @@ pub async fn add_download(db: &PgPool, id: &str) -> sqlx::Result<()> {
     Ok(())
 }
 
-pub async fn update_stats(db: &PgPool, bytes: u64) -> sqlx::Result<()> {
+pub async fn update_stats(db: &PgPool, bytes: u64, encrypted: bool) -> sqlx::Result<()> {
     sqlx::query!("UPDATE stats SET files_uploaded = files_uploaded + 1, bytes_uploaded = bytes_uploaded + $1 WHERE id = 1", bytes as i64)
         .execute(db)
         .await?;
+    add_usage_stats(
+        db,
+        UsageDelta {
+            uploads: 1,
+            bytes_in: bytes as i64,
+            encrypted_uploads: encrypted as i64,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+pub async fn update_download_stats(db: &PgPool, downloads: i64, bytes: u64) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE stats SET files_downloaded = files_downloaded + $1, bytes_downloaded = bytes_downloaded + $2 WHERE id = 1",
+        downloads,
+        bytes as i64
+    )
+    .execute(db)
+    .await?;
+    add_usage_stats(
+        db,
+        UsageDelta {
+            downloads,
+            bytes_out: bytes as i64,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// What happened since the last change, added to the bucket of the current hour.
+#[derive(Debug, Default)]
+pub struct UsageDelta {
+    pub uploads: i64,
+    pub bytes_in: i64,
+    pub encrypted_uploads: i64,
+    pub downloads: i64,
+    pub bytes_out: i64,
+    pub expired: i64,
+    pub blacklisted: i64,
+}
+
+pub async fn add_usage_stats(db: &PgPool, delta: UsageDelta) -> sqlx::Result<()> {
+    sqlx::query!(
+        "INSERT INTO usage_stats (hour, uploads, bytes_in, encrypted_uploads, downloads, bytes_out, expired, blacklisted)
+        VALUES (date_trunc('hour', NOW()), $1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (hour) DO UPDATE SET
+            uploads = usage_stats.uploads + EXCLUDED.uploads,
+            bytes_in = usage_stats.bytes_in + EXCLUDED.bytes_in,
+            encrypted_uploads = usage_stats.encrypted_uploads + EXCLUDED.encrypted_uploads,
+            downloads = usage_stats.downloads + EXCLUDED.downloads,
+            bytes_out = usage_stats.bytes_out + EXCLUDED.bytes_out,
+            expired = usage_stats.expired + EXCLUDED.expired,
+            blacklisted = usage_stats.blacklisted + EXCLUDED.blacklisted",
+        delta.uploads,
+        delta.bytes_in,
+        delta.encrypted_uploads,
+        delta.downloads,
+        delta.bytes_out,
+        delta.expired,
+        delta.blacklisted
+    )
+    .execute(db)
+    .await?;
     Ok(())
 }
 
+/// Usage between `from` and `to` in buckets of one `granularity` (`hour` or `day`), empty ones included.
+pub async fn fetch_usage_stats(
+    db: &PgPool,
+    granularity: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> sqlx::Result<Vec<UsageBucket>> {
+    sqlx::query_as!(
+        UsageBucket,
+        r#"SELECT
+            buckets.start AS "start!",
+            COALESCE(SUM(u.uploads), 0)::BIGINT AS "uploads!",
+            COALESCE(SUM(u.bytes_in), 0)::BIGINT AS "bytes_in!",
+            COALESCE(SUM(u.encrypted_uploads), 0)::BIGINT AS "encrypted_uploads!",
+            COALESCE(SUM(u.downloads), 0)::BIGINT AS "downloads!",
+            COALESCE(SUM(u.bytes_out), 0)::BIGINT AS "bytes_out!",
+            COALESCE(SUM(u.expired), 0)::BIGINT AS "expired!",
+            COALESCE(SUM(u.blacklisted), 0)::BIGINT AS "blacklisted!"
+        FROM generate_series(date_trunc($1, $2::TIMESTAMPTZ), $3::TIMESTAMPTZ, ('1 ' || $1)::INTERVAL) AS buckets(start)
+        LEFT JOIN usage_stats u ON u.hour >= buckets.start AND u.hour < buckets.start + ('1 ' || $1)::INTERVAL
+        GROUP BY buckets.start
+        ORDER BY buckets.start"#,
+        granularity,
+        from,
+        to
+    )
+    .fetch_all(db)
+    .await
+}
+
+pub async fn fetch_collection(db: &PgPool, id: &str) -> sqlx::Result<Option<Collection>> {
+    let res = sqlx::query_as!(Collection, "SELECT * FROM collections WHERE id = $1", id)
+        .fetch_optional(db)
+        .await?;
+    Ok(res)
+}
+
+pub async fn fetch_collection_uploads(db: &PgPool, id: &str) -> sqlx::Result<Vec<Upload>> {
+    let res = sqlx::query_as!(
+        Upload,
+        "SELECT * FROM uploads WHERE collection_id = $1 ORDER BY created_at, id",
+        id
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(res)
+}
+
+/// Creates collection and attaches already inserted uploads to it.
+pub async fn insert_collection(db: &PgPool, insert: InsertCollection, upload_ids: &[String]) -> sqlx::Result<()> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query!(
+        "INSERT INTO collections (id, key_hash, delete_key) VALUES ($1, $2, $3)",
+        insert.id,
+        insert.key_hash,
+        insert.delete_key,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE uploads SET collection_id = $1 WHERE id = ANY($2)",
+        insert.id,
+        upload_ids,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await
+}
+
+pub async fn delete_collection(db: &PgPool, id: &str) -> sqlx::Result<()> {
+    sqlx::query!("DELETE FROM collections WHERE id = $1", id)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// Removes collections whose every upload is already gone, returns how many were removed.
+pub async fn delete_empty_collections(db: &PgPool) -> sqlx::Result<u64> {
+    let res = sqlx::query!(
+        "DELETE FROM collections c WHERE NOT EXISTS (SELECT 1 FROM uploads u WHERE u.collection_id = c.id)"
+    )
+    .execute(db)
+    .await?;
+    Ok(res.rows_affected())
+}
+
+pub async fn fetch_upload_session(db: &PgPool, id: &str) -> sqlx::Result<Option<UploadSession>> {
+    let res = sqlx::query_as!(UploadSession, "SELECT * FROM upload_sessions WHERE id = $1", id)
+        .fetch_optional(db)
+        .await?;
+    Ok(res)
+}
+
+pub async fn insert_upload_session(db: &PgPool, insert: InsertUploadSession) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO upload_sessions
+            (id, delete_key, key_hash, nonce, file_name, upload_length, expiry_hours, expiry_downloads, embedded, owner_id,
+             content_type)
+        VALUES
+            ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        "#,
+        insert.id,
+        insert.delete_key,
+        insert.key_hash,
+        insert.nonce,
+        insert.file_name,
+        insert.upload_length as i64,
+        insert.expiry_hours.map(|n| n as i32),
+        insert.expiry_downloads.map(|n| n as i32),
+        insert.embedded,
+        insert.owner_id,
+        insert.content_type,
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Moves session offset forward, returns false if someone else did it first.
+pub async fn advance_upload_session(
+    db: &PgPool,
+    id: &str,
+    from_offset: u64,
+    to_offset: u64,
+    part: Option<&str>,
+    tail: &[u8],
+) -> sqlx::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+        UPDATE upload_sessions SET
+            upload_offset = $3,
+            parts = CASE WHEN $4::TEXT IS NULL THEN parts ELSE array_append(parts, $4) END,
+            tail = $5,
+            updated_at = NOW()
+        WHERE id = $1 AND upload_offset = $2
+        "#,
+        id,
+        from_offset as i64,
+        to_offset as i64,
+        part,
+        tail,
+    )
+    .execute(db)
+    .await?;
+    Ok(res.rows_affected() == 1)
+}
+
+/// Type recognized from the first chunk of a resumable upload.
+pub async fn set_upload_session_content_type(db: &PgPool, id: &str, content_type: &str) -> sqlx::Result<()> {
+    sqlx::query!("UPDATE upload_sessions SET content_type = $2 WHERE id = $1", id, content_type)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+pub async fn delete_upload_session(db: &PgPool, id: &str) -> sqlx::Result<Option<UploadSession>> {
+    let res = sqlx::query_as!(UploadSession, "DELETE FROM upload_sessions WHERE id = $1 RETURNING *", id)
+        .fetch_optional(db)
+        .await?;
+    Ok(res)
+}
+
+pub async fn delete_stale_upload_sessions(db: &PgPool, ttl_hours: u32) -> sqlx::Result<Vec<UploadSession>> {
+    let res = sqlx::query_as!(
+        UploadSession,
+        "DELETE FROM upload_sessions WHERE updated_at + make_interval(hours => $1) <= NOW() RETURNING *",
+        ttl_hours as i32
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(res)
+}
+
+pub async fn fetch_blacklist(db: &PgPool) -> sqlx::Result<Vec<BlacklistEntry>> {
+    let res = sqlx::query_as!(BlacklistEntry, "SELECT * FROM blacklist ORDER BY created_at, hash")
+        .fetch_all(db)
+        .await?;
+    Ok(res)
+}
+
+/// Returns the entry when the hash wasn't blacklisted yet.
+pub async fn insert_blacklist_entry(db: &PgPool, insert: InsertBlacklistEntry) -> sqlx::Result<Option<BlacklistEntry>> {
+    let res = sqlx::query_as!(
+        BlacklistEntry,
+        "INSERT INTO blacklist (hash, reason, added_by) VALUES ($1, $2, $3) ON CONFLICT (hash) DO NOTHING RETURNING *",
+        insert.hash,
+        insert.reason,
+        insert.added_by,
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(res)
+}
+
+pub async fn delete_blacklist_entry(db: &PgPool, hash: &str) -> sqlx::Result<Option<BlacklistEntry>> {
+    let res = sqlx::query_as!(BlacklistEntry, "DELETE FROM blacklist WHERE hash = $1 RETURNING *", hash)
+        .fetch_optional(db)
+        .await?;
+    Ok(res)
+}
+
+pub async fn fetch_uploads_by_blob(db: &PgPool, hash: &str) -> sqlx::Result<Vec<Upload>> {
+    let res = sqlx::query_as!(Upload, "SELECT * FROM uploads WHERE blob_hash = $1", hash)
+        .fetch_all(db)
+        .await?;
+    Ok(res)
+}
+
+pub async fn thumbnail_exists(db: &PgPool, blob_key: &str, variant: &str) -> sqlx::Result<bool> {
+    let res = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM thumbnails WHERE blob_key = $1 AND variant = $2) AS "exists!""#,
+        blob_key,
+        variant
+    )
+    .fetch_one(db)
+    .await?;
+    Ok(res)
+}
+
+pub async fn insert_thumbnail(db: &PgPool, blob_key: &str, variant: &str, bytes: u64) -> sqlx::Result<()> {
+    sqlx::query!(
+        "INSERT INTO thumbnails (blob_key, variant, bytes) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+        blob_key,
+        variant,
+        bytes as i64
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Forgets every thumbnail of the blob and returns their variants.
+pub async fn delete_thumbnails(db: &PgPool, blob_key: &str) -> sqlx::Result<Vec<String>> {
+    let res = sqlx::query_scalar!("DELETE FROM thumbnails WHERE blob_key = $1 RETURNING variant", blob_key)
+        .fetch_all(db)
+        .await?;
+    Ok(res)
+}
+
+/// Uploads whose id or file name contains `search`, newest first, together with how many there are.
+pub async fn search_uploads(db: &PgPool, search: Option<&str>, limit: u32, offset: u32) -> sqlx::Result<(Vec<Upload>, i64)> {
+    let pattern = search.map(|search| {
+        let escaped = search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        format!("%{escaped}%")
+    });
+
+    let uploads = sqlx::query_as!(
+        Upload,
+        r#"
+        SELECT * FROM uploads
+        WHERE $1::TEXT IS NULL OR id ILIKE $1 OR file_name ILIKE $1
+        ORDER BY created_at DESC, id
+        LIMIT $2 OFFSET $3
+        "#,
+        pattern,
+        limit as i64,
+        offset as i64,
+    )
+    .fetch_all(db)
+    .await?;
+
+    let total = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM uploads WHERE $1::TEXT IS NULL OR id ILIKE $1 OR file_name ILIKE $1",
+        pattern,
+    )
+    .fetch_one(db)
+    .await?
+    .unwrap_or(0);
+
+    Ok((uploads, total))
+}
+
+pub async fn insert_audit_entry(db: &PgPool, admin: &str, action: &str, target: Option<&str>) -> sqlx::Result<()> {
+    sqlx::query!(
+        "INSERT INTO admin_audit_log (admin, action, target) VALUES ($1, $2, $3)",
+        admin,
+        action,
+        target,
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn fetch_audit_log(db: &PgPool, limit: u32, offset: u32) -> sqlx::Result<(Vec<AuditEntry>, i64)> {
+    let entries = sqlx::query_as!(
+        AuditEntry,
+        "SELECT * FROM admin_audit_log ORDER BY id DESC LIMIT $1 OFFSET $2",
+        limit as i64,
+        offset as i64,
+    )
+    .fetch_all(db)
+    .await?;
+
+    let total = sqlx::query_scalar!("SELECT COUNT(*) FROM admin_audit_log")
+        .fetch_one(db)
+        .await?
+        .unwrap_or(0);
+
+    Ok((entries, total))
+}
+
+/// Bytes the client uploaded today (UTC).
+pub async fn fetch_upload_usage(db: &PgPool, client: &str) -> sqlx::Result<i64> {
+    let res = sqlx::query_scalar!(
+        "SELECT bytes FROM upload_usage WHERE client = $1 AND day = (NOW() AT TIME ZONE 'UTC')::DATE",
+        client
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(res.unwrap_or(0))
+}
+
+pub async fn add_upload_usage(db: &PgPool, client: &str, bytes: u64) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO upload_usage (client, day, bytes) VALUES ($1, (NOW() AT TIME ZONE 'UTC')::DATE, $2)
+        ON CONFLICT (client, day) DO UPDATE SET bytes = upload_usage.bytes + EXCLUDED.bytes
+        "#,
+        client,
+        bytes as i64
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_past_upload_usage(db: &PgPool) -> sqlx::Result<u64> {
+    let res = sqlx::query!("DELETE FROM upload_usage WHERE day < (NOW() AT TIME ZONE 'UTC')::DATE")
+        .execute(db)
+        .await?;
+    Ok(res.rows_affected())
+}
+
+pub async fn fetch_owned_uploads(db: &PgPool, owner_id: &str, limit: u32, offset: u32) -> sqlx::Result<(Vec<Upload>, i64)> {
+    let uploads = sqlx::query_as!(
+        Upload,
+        "SELECT * FROM uploads WHERE owner_id = $1 ORDER BY created_at DESC, id LIMIT $2 OFFSET $3",
+        owner_id,
+        limit as i64,
+        offset as i64,
+    )
+    .fetch_all(db)
+    .await?;
+
+    let total = sqlx::query_scalar!("SELECT COUNT(*) FROM uploads WHERE owner_id = $1", owner_id)
+        .fetch_one(db)
+        .await?
+        .unwrap_or(0);
+
+    Ok((uploads, total))
+}
+
+pub async fn insert_account(db: &PgPool, insert: InsertAccount) -> sqlx::Result<Account> {
+    let res = sqlx::query_as!(
+        Account,
+        "INSERT INTO accounts (id, name, max_file_bytes, default_expiry_hours) VALUES ($1, $2, $3, $4) RETURNING *",
+        insert.id,
+        insert.name,
+        insert.max_file_bytes.map(|n| n as i64),
+        insert.default_expiry_hours.map(|n| n as i32),
+    )
+    .fetch_one(db)
+    .await?;
+    Ok(res)
+}
+
+pub async fn fetch_account(db: &PgPool, id: &str) -> sqlx::Result<Option<Account>> {
+    let res = sqlx::query_as!(Account, "SELECT * FROM accounts WHERE id = $1", id)
+        .fetch_optional(db)
+        .await?;
+    Ok(res)
+}
+
+pub async fn fetch_account_by_key(db: &PgPool, key_hash: &str) -> sqlx::Result<Option<Account>> {
+    let res = sqlx::query_as!(
+        Account,
+        "SELECT accounts.* FROM accounts JOIN api_keys ON api_keys.account_id = accounts.id WHERE api_keys.key_hash = $1",
+        key_hash
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(res)
+}
+
+pub async fn insert_api_key(db: &PgPool, account_id: &str, key_hash: &str, label: Option<&str>) -> sqlx::Result<ApiKey> {
+    let res = sqlx::query_as!(
+        ApiKey,
+        "INSERT INTO api_keys (account_id, key_hash, label) VALUES ($1, $2, $3) RETURNING id, label, created_at",
+        account_id,
+        key_hash,
+        label,
+    )
+    .fetch_one(db)
+    .await?;
+    Ok(res)
+}
+
+pub async fn delete_api_key(db: &PgPool, account_id: &str, id: i64) -> sqlx::Result<Option<ApiKey>> {
+    let res = sqlx::query_as!(
+        ApiKey,
+        "DELETE FROM api_keys WHERE account_id = $1 AND id = $2 RETURNING id, label, created_at",
+        account_id,
+        id
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(res)
+}
+
+/// Bytes taken by stored uploads, deduplicated content once, by unfinished resumable uploads and by thumbnails.
+pub async fn fetch_storage_usage(db: &PgPool) -> sqlx::Result<i64> {
+    let res = sqlx::query_scalar!(
+        r#"
+        SELECT
+            (SELECT COALESCE(SUM(bytes), 0) FROM uploads WHERE blob_hash IS NULL)::BIGINT
+            + (SELECT COALESCE(SUM(bytes), 0) FROM blobs)::BIGINT
+            + (SELECT COALESCE(SUM(upload_length), 0) FROM upload_sessions)::BIGINT
+            + (SELECT COALESCE(SUM(bytes), 0) FROM thumbnails)::BIGINT AS "bytes!"
+        "#
+    )
+    .fetch_one(db)
+    .await?;
+    Ok(res)
+}
+
 pub struct InsertUpload {
     pub id: String,
     pub key_hash: Option<String>,
@@ -66,4 +623,43 @@ pub struct InsertUpload {
     pub expiry_hours: Option<u32>,
     pub expiry_downloads: Option<u32>,
     pub embedded: bool,
+    pub kdf: Option<Kdf>,
+    pub e2e_version: Option<u8>,
+    pub e2e_chunk_size: Option<u32>,
+    pub blob_hash: Option<String>,
+    pub owner_id: Option<String>,
+    pub content_type: Option<String>,
+}
+
+pub struct InsertCollection {
+    pub id: String,
+    pub key_hash: Option<String>,
+    pub delete_key: String,
+}
+
+pub struct InsertUploadSession {
+    pub id: String,
+    pub delete_key: String,
+    pub key_hash: Option<String>,
+    pub nonce: Option<String>,
+    pub file_name: String,
+    pub upload_length: u64,
+    pub expiry_hours: Option<u32>,
+    pub expiry_downloads: Option<u32>,
+    pub embedded: bool,
+    pub owner_id: Option<String>,
+    pub content_type: Option<String>,
+}
+
+pub struct InsertBlacklistEntry {
+    pub hash: String,
+    pub reason: Option<String>,
+    pub added_by: String,
+}
+
+pub struct InsertAccount {
+    pub id: String,
+    pub name: String,
+    pub max_file_bytes: Option<u64>,
+    pub default_expiry_hours: Option<u32>,
 }
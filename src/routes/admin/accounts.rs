@@ -0,0 +1,113 @@
+use axum::{http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    accounts::create_api_key,
+    errors::{AppError, AppResult, ErrorResponse},
+    extractors,
+    repository::{fetch_account, insert_account, InsertAccount},
+    routes::me::{AccountResponse, CreateKeyBody, CreatedKeyResponse},
+    utilities::friendly_id,
+    AppContext,
+};
+
+use super::Admin;
+
+const MAX_NAME_LEN: usize = 64;
+
+#[utoipa::path(
+    post,
+    path = "/admin/accounts",
+    tag = "admin",
+    request_body = CreateBody,
+    responses(
+        (status = 201, description = "Account with its first API key.", body = CreatedAccountResponse),
+        (status = 400, description = "`validation`.", body = ErrorResponse),
+        (status = 401, description = "`admin-unauthorized`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+    security(("admin_token" = [])),
+)]
+#[tracing::instrument(skip(ctx))]
+pub async fn create_endpoint(
+    ctx: Extension<AppContext>,
+    admin: Admin,
+    extractors::Json(body): extractors::Json<CreateBody>,
+) -> AppResult<(StatusCode, Json<CreatedAccountResponse>)> {
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(AppError::Validation(format!("account name must have between 1 and {MAX_NAME_LEN} characters.")));
+    }
+
+    let id = friendly_id(8);
+    admin.record(&ctx.db, "create-account", Some(&id)).await?;
+
+    let account = insert_account(
+        &ctx.db,
+        InsertAccount {
+            id,
+            name: name.to_string(),
+            max_file_bytes: body.max_file_bytes,
+            default_expiry_hours: body.default_expiry_hours,
+        },
+    )
+    .await?;
+    let (api_key, key) = create_api_key(&ctx.db, &account.id, Some("created by admin")).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedAccountResponse {
+            account: account.into(),
+            api_key: CreatedKeyResponse::new(api_key, key),
+        }),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/accounts/{account_id}/keys",
+    tag = "admin",
+    params(("account_id" = String, Path)),
+    request_body = CreateKeyBody,
+    responses(
+        (status = 201, body = CreatedKeyResponse),
+        (status = 400, description = "`validation`.", body = ErrorResponse),
+        (status = 401, description = "`admin-unauthorized`.", body = ErrorResponse),
+        (status = 404, description = "`account-not-found`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+    security(("admin_token" = [])),
+)]
+#[tracing::instrument(skip(ctx))]
+pub async fn create_key_endpoint(
+    ctx: Extension<AppContext>,
+    admin: Admin,
+    extractors::Path(account_id): extractors::Path<String>,
+    extractors::Json(body): extractors::Json<CreateKeyBody>,
+) -> AppResult<(StatusCode, Json<CreatedKeyResponse>)> {
+    admin.record(&ctx.db, "create-api-key", Some(&account_id)).await?;
+
+    let account = fetch_account(&ctx.db, &account_id)
+        .await?
+        .ok_or(AppError::AccountNotFound)?;
+    let (api_key, key) = create_api_key(&ctx.db, &account.id, body.label.as_deref()).await?;
+
+    Ok((StatusCode::CREATED, Json(CreatedKeyResponse::new(api_key, key))))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateBody {
+    name: String,
+    max_file_bytes: Option<u64>,
+    default_expiry_hours: Option<u32>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedAccountResponse {
+    #[serde(flatten)]
+    account: AccountResponse,
+    api_key: CreatedKeyResponse,
+}
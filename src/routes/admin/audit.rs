@@ -0,0 +1,73 @@
+use axum::{Extension, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    errors::{AppResult, ErrorResponse},
+    extractors,
+    models::AuditEntry,
+    repository::fetch_audit_log,
+    routes::pagination::{Page, Paginated},
+    AppContext,
+};
+
+use super::Admin;
+
+/// Admin actions, newest first. Reading the log isn't recorded in it.
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    tag = "admin",
+    params(ListQuery),
+    responses(
+        (status = 200, body = Paginated<EntryResponse>),
+        (status = 400, description = "`validation`.", body = ErrorResponse),
+        (status = 401, description = "`admin-unauthorized`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+    security(("admin_token" = [])),
+)]
+pub async fn list_endpoint(
+    ctx: Extension<AppContext>,
+    _admin: Admin,
+    extractors::Query(query): extractors::Query<ListQuery>,
+) -> AppResult<Json<Paginated<EntryResponse>>> {
+    let page = Page::new(query.page, query.per_page)?;
+    let (entries, total) = fetch_audit_log(&ctx.db, page.limit(), page.offset()).await?;
+    let entries = entries.into_iter().map(EntryResponse::from).collect();
+
+    Ok(Json(Paginated::new(entries, page, total)))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListQuery {
+    /// Starts at 1.
+    page: Option<u32>,
+    /// Between 1 and 200, defaults to 50.
+    per_page: Option<u32>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[schema(as = AuditEntry)]
+pub struct EntryResponse {
+    id: i64,
+    admin: String,
+    action: String,
+    target: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<AuditEntry> for EntryResponse {
+    fn from(entry: AuditEntry) -> Self {
+        Self {
+            id: entry.id,
+            admin: entry.admin,
+            action: entry.action,
+            target: entry.target,
+            created_at: entry.created_at,
+        }
+    }
+}
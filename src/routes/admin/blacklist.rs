@@ -0,0 +1,158 @@
+use axum::{http::StatusCode, Extension, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    blacklist::count_blacklisted,
+    errors::{AppError, AppResult, ErrorResponse},
+    extractors,
+    models::BlacklistEntry,
+    repository::{delete_blacklist_entry, fetch_blacklist, fetch_uploads_by_blob, insert_blacklist_entry, InsertBlacklistEntry},
+    routes::delete::delete_upload,
+    AppContext,
+};
+
+use super::Admin;
+
+#[utoipa::path(
+    get,
+    path = "/admin/blacklist",
+    tag = "admin",
+    responses(
+        (status = 200, body = Vec<EntryResponse>),
+        (status = 401, description = "`admin-unauthorized`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+    security(("admin_token" = [])),
+)]
+pub async fn list_endpoint(ctx: Extension<AppContext>, admin: Admin) -> AppResult<Json<Vec<EntryResponse>>> {
+    admin.record(&ctx.db, "list-blacklist", None).await?;
+    let entries = fetch_blacklist(&ctx.db).await?;
+    Ok(Json(entries.into_iter().map(EntryResponse::from).collect()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/blacklist",
+    tag = "admin",
+    request_body = AddBody,
+    responses(
+        (status = 201, body = AddResponse),
+        (status = 400, description = "`validation`.", body = ErrorResponse),
+        (status = 401, description = "`admin-unauthorized`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+    security(("admin_token" = [])),
+)]
+#[tracing::instrument(skip(ctx))]
+pub async fn add_endpoint(
+    ctx: Extension<AppContext>,
+    admin: Admin,
+    extractors::Json(body): extractors::Json<AddBody>,
+) -> AppResult<(StatusCode, Json<AddResponse>)> {
+    let hash = body.hash.to_lowercase();
+    if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(AppError::Validation(String::from("hash has to be a hex encoded sha256 digest.")));
+    }
+    let action = if body.purge { "blacklist-and-purge" } else { "blacklist" };
+    admin.record(&ctx.db, action, Some(&hash)).await?;
+
+    let entry = insert_blacklist_entry(
+        &ctx.db,
+        InsertBlacklistEntry {
+            hash,
+            reason: body.reason,
+            added_by: admin.name,
+        },
+    )
+    .await?
+    .ok_or_else(|| AppError::Validation(String::from("this hash is blacklisted already.")))?;
+    ctx.blacklist.changed(&ctx.db).await?;
+
+    // only uploads stored as they are know their content's hash,
+    // encrypted ones are hashed after encryption so they never match
+    let mut purged = 0;
+    if body.purge {
+        for upload in fetch_uploads_by_blob(&ctx.db, &entry.hash).await? {
+            delete_upload(&ctx.db, ctx.storage.as_ref(), &upload.id).await?;
+            tracing::info!(id = upload.id, file_name = upload.file_name, "purged blacklisted upload");
+            purged += 1;
+        }
+        count_blacklisted(&ctx.db, purged as usize).await;
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        Json(AddResponse {
+            entry: entry.into(),
+            purged,
+        }),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/blacklist/{hash}",
+    tag = "admin",
+    params(("hash" = String, Path, description = "Hex encoded sha256 digest.")),
+    responses(
+        (status = 204, description = "Content can be uploaded again."),
+        (status = 401, description = "`admin-unauthorized`.", body = ErrorResponse),
+        (status = 404, description = "`hash-not-blacklisted`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+    security(("admin_token" = [])),
+)]
+#[tracing::instrument(skip(ctx))]
+pub async fn remove_endpoint(
+    ctx: Extension<AppContext>,
+    admin: Admin,
+    extractors::Path(hash): extractors::Path<String>,
+) -> AppResult<StatusCode> {
+    admin.record(&ctx.db, "unblacklist", Some(&hash)).await?;
+    delete_blacklist_entry(&ctx.db, &hash.to_lowercase())
+        .await?
+        .ok_or(AppError::HashNotBlacklisted)?;
+    ctx.blacklist.changed(&ctx.db).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AddBody {
+    hash: String,
+    reason: Option<String>,
+    /// Removes uploads that already have this content.
+    #[serde(default)]
+    purge: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[schema(as = BlacklistEntry)]
+pub struct EntryResponse {
+    hash: String,
+    reason: Option<String>,
+    added_by: String,
+    created_at: DateTime<Utc>,
+}
+
+impl From<BlacklistEntry> for EntryResponse {
+    fn from(entry: BlacklistEntry) -> Self {
+        Self {
+            hash: entry.hash,
+            reason: entry.reason,
+            added_by: entry.added_by,
+            created_at: entry.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AddResponse {
+    #[serde(flatten)]
+    entry: EntryResponse,
+    purged: u32,
+}
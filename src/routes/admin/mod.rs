@@ -0,0 +1,81 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header::AUTHORIZATION, request::Parts},
+    routing::{delete, get, post},
+    Router,
+};
+use sqlx::PgPool;
+
+use crate::{
+    errors::{AppError, AppResult},
+    repository::insert_audit_entry,
+    AppContext,
+};
+
+pub mod accounts;
+pub mod audit;
+pub mod blacklist;
+pub mod stats;
+pub mod uploads;
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/uploads", get(uploads::list_endpoint))
+        .route("/uploads/:upload_id", get(uploads::show_endpoint).delete(uploads::delete_endpoint))
+        .route("/accounts", post(accounts::create_endpoint))
+        .route("/accounts/:account_id/keys", post(accounts::create_key_endpoint))
+        .route("/stats", get(stats::stats_endpoint))
+        .route("/audit", get(audit::list_endpoint))
+        .route("/blacklist", get(blacklist::list_endpoint).post(blacklist::add_endpoint))
+        .route("/blacklist/:hash", delete(blacklist::remove_endpoint))
+}
+
+/// Holder of one of the configured admin tokens, required by every admin endpoint.
+#[derive(Debug)]
+pub struct Admin {
+    pub name: String,
+}
+
+impl Admin {
+    /// Writes the action to the audit log, it's recorded before it's done so nothing goes unrecorded.
+    pub async fn record(&self, db: &PgPool, action: &str, target: Option<&str>) -> AppResult<()> {
+        insert_audit_entry(db, &self.name, action, target).await?;
+        tracing::info!(admin = self.name, action, target, "admin action");
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Admin
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> AppResult<Self> {
+        let ctx = parts
+            .extensions
+            .get::<AppContext>()
+            .ok_or_else(|| anyhow::anyhow!("app context is missing"))?;
+
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(AppError::AdminUnauthorized)?;
+
+        // digests are compared, so how long it takes doesn't tell how much of the token was right
+        let digest = sha256::digest(token);
+        ctx.cfg
+            .admin
+            .tokens
+            .iter()
+            .find(|admin| sha256::digest(&admin.token) == digest)
+            .map(|admin| Admin {
+                name: admin.name.clone(),
+            })
+            .ok_or(AppError::AdminUnauthorized)
+    }
+}
@@ -0,0 +1,81 @@
+use axum::{Extension, Json};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{errors::{AppResult, ErrorResponse}, AppContext};
+
+use super::Admin;
+
+#[utoipa::path(
+    get,
+    path = "/admin/stats",
+    tag = "admin",
+    responses(
+        (status = 200, body = ExtendedStats),
+        (status = 401, description = "`admin-unauthorized`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+    security(("admin_token" = [])),
+)]
+pub async fn stats_endpoint(ctx: Extension<AppContext>, admin: Admin) -> AppResult<Json<ExtendedStats>> {
+    admin.record(&ctx.db, "show-stats", None).await?;
+
+    let totals = sqlx::query!("SELECT * FROM stats WHERE id = 1")
+        .fetch_one(&ctx.db)
+        .await?;
+
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM uploads) AS "uploads!",
+            (SELECT COALESCE(SUM(bytes), 0) FROM uploads)::BIGINT AS "upload_bytes!",
+            (SELECT COALESCE(SUM(downloads), 0) FROM uploads)::BIGINT AS "downloads!",
+            (SELECT COUNT(*) FROM uploads WHERE key_hash IS NOT NULL) AS "encrypted!",
+            (SELECT COUNT(*) FROM uploads WHERE kdf_salt IS NOT NULL) AS "password_protected!",
+            (SELECT COUNT(*) FROM uploads WHERE e2e_version IS NOT NULL) AS "e2e!",
+            (SELECT COUNT(*) FROM collections) AS "collections!",
+            (SELECT COUNT(*) FROM blobs) AS "blobs!",
+            (SELECT COALESCE(SUM(bytes), 0) FROM blobs)::BIGINT AS "blob_bytes!",
+            (SELECT COALESCE(SUM(bytes), 0) FROM uploads WHERE blob_hash IS NOT NULL)::BIGINT AS "deduplicated_bytes!",
+            (SELECT COUNT(*) FROM upload_sessions) AS "upload_sessions!",
+            (SELECT COUNT(*) FROM blacklist) AS "blacklisted!"
+        "#
+    )
+    .fetch_one(&ctx.db)
+    .await?;
+
+    Ok(Json(ExtendedStats {
+        total_uploads: totals.files_uploaded as u32,
+        total_bytes: totals.bytes_uploaded as u64,
+        uploads: row.uploads,
+        upload_bytes: row.upload_bytes,
+        downloads: row.downloads,
+        encrypted: row.encrypted,
+        password_protected: row.password_protected,
+        e2e: row.e2e,
+        collections: row.collections,
+        blobs: row.blobs,
+        saved_by_deduplication: row.deduplicated_bytes - row.blob_bytes,
+        upload_sessions: row.upload_sessions,
+        blacklisted: row.blacklisted,
+    }))
+}
+
+/// Public stats together with what's stored right now.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtendedStats {
+    total_uploads: u32,
+    total_bytes: u64,
+    uploads: i64,
+    upload_bytes: i64,
+    downloads: i64,
+    encrypted: i64,
+    password_protected: i64,
+    e2e: i64,
+    collections: i64,
+    blobs: i64,
+    saved_by_deduplication: i64,
+    upload_sessions: i64,
+    blacklisted: i64,
+}
@@ -0,0 +1,158 @@
+use axum::{http::StatusCode, Extension, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    errors::{AppError, AppResult, ErrorResponse},
+    extractors,
+    models::Upload,
+    repository::{fetch_upload, search_uploads},
+    routes::{
+        delete::delete_upload,
+        pagination::{Page, Paginated},
+    },
+    AppContext,
+};
+
+use super::Admin;
+
+#[utoipa::path(
+    get,
+    path = "/admin/uploads",
+    tag = "admin",
+    params(ListQuery),
+    responses(
+        (status = 200, body = Paginated<UploadResponse>),
+        (status = 400, description = "`validation`.", body = ErrorResponse),
+        (status = 401, description = "`admin-unauthorized`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+    security(("admin_token" = [])),
+)]
+#[tracing::instrument(skip(ctx))]
+pub async fn list_endpoint(
+    ctx: Extension<AppContext>,
+    admin: Admin,
+    extractors::Query(query): extractors::Query<ListQuery>,
+) -> AppResult<Json<Paginated<UploadResponse>>> {
+    let page = Page::new(query.page, query.per_page)?;
+    let search = query.search.as_deref().filter(|search| !search.is_empty());
+    admin.record(&ctx.db, "search-uploads", search).await?;
+
+    let (uploads, total) = search_uploads(&ctx.db, search, page.limit(), page.offset()).await?;
+    let uploads = uploads.into_iter().map(UploadResponse::from).collect();
+
+    Ok(Json(Paginated::new(uploads, page, total)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/uploads/{upload_id}",
+    tag = "admin",
+    params(("upload_id" = String, Path)),
+    responses(
+        (status = 200, body = UploadResponse),
+        (status = 400, description = "`upload-not-found`.", body = ErrorResponse),
+        (status = 401, description = "`admin-unauthorized`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+    security(("admin_token" = [])),
+)]
+#[tracing::instrument(skip(ctx))]
+pub async fn show_endpoint(
+    ctx: Extension<AppContext>,
+    admin: Admin,
+    extractors::Path(upload_id): extractors::Path<String>,
+) -> AppResult<Json<UploadResponse>> {
+    admin.record(&ctx.db, "show-upload", Some(&upload_id)).await?;
+
+    let upload = fetch_upload(&ctx.db, &upload_id)
+        .await?
+        .ok_or(AppError::UploadNotFound)?;
+
+    Ok(Json(upload.into()))
+}
+
+/// Removes the upload without its delete key.
+#[utoipa::path(
+    delete,
+    path = "/admin/uploads/{upload_id}",
+    tag = "admin",
+    params(("upload_id" = String, Path)),
+    responses(
+        (status = 204, description = "Upload is removed."),
+        (status = 400, description = "`upload-not-found`.", body = ErrorResponse),
+        (status = 401, description = "`admin-unauthorized`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+    security(("admin_token" = [])),
+)]
+#[tracing::instrument(skip(ctx))]
+pub async fn delete_endpoint(
+    ctx: Extension<AppContext>,
+    admin: Admin,
+    extractors::Path(upload_id): extractors::Path<String>,
+) -> AppResult<StatusCode> {
+    fetch_upload(&ctx.db, &upload_id)
+        .await?
+        .ok_or(AppError::UploadNotFound)?;
+
+    admin.record(&ctx.db, "delete-upload", Some(&upload_id)).await?;
+    delete_upload(&ctx.db, ctx.storage.as_ref(), &upload_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListQuery {
+    /// Part of the id or file name.
+    search: Option<String>,
+    /// Starts at 1.
+    page: Option<u32>,
+    /// Between 1 and 200, defaults to 50.
+    per_page: Option<u32>,
+}
+
+/// Everything about the upload except for what would let an admin decrypt or delete it the usual way.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[schema(as = AdminUpload)]
+pub struct UploadResponse {
+    id: String,
+    file_name: String,
+    bytes: i64,
+    downloads: i32,
+    expiry_hours: Option<i32>,
+    expiry_downloads: Option<i32>,
+    expired: bool,
+    embedded: bool,
+    created_at: DateTime<Utc>,
+    collection_id: Option<String>,
+    encrypted: bool,
+    password_protected: bool,
+    e2e_version: Option<i16>,
+    blob_hash: Option<String>,
+}
+
+impl From<Upload> for UploadResponse {
+    fn from(upload: Upload) -> Self {
+        Self {
+            expired: upload.is_expired(),
+            encrypted: upload.key_hash.is_some(),
+            password_protected: upload.kdf_salt.is_some(),
+            id: upload.id,
+            file_name: upload.file_name,
+            bytes: upload.bytes,
+            downloads: upload.downloads,
+            expiry_hours: upload.expiry_hours,
+            expiry_downloads: upload.expiry_downloads,
+            embedded: upload.embedded,
+            created_at: upload.created_at,
+            collection_id: upload.collection_id,
+            e2e_version: upload.e2e_version,
+            blob_hash: upload.blob_hash,
+        }
+    }
+}
@@ -4,21 +4,52 @@ use axum::{
 };
 use serde::Deserialize;
 use sqlx::PgPool;
-use tokio::fs;
+use utoipa::IntoParams;
 
 use crate::{
-    errors::{AppError, AppResult}, extractors, models::Upload, repository, AppContext
+    errors::{AppError, AppResult, ErrorResponse}, extractors, models::Upload, repository::{self, fetch_collection, fetch_collection_uploads, release_blob}, storage::Storage, thumbnails::remove_thumbnails, AppContext
 };
 
-pub async fn delete_upload(db: &PgPool, storage_dir: &str, upload_id: &str) -> AppResult<()> {
-    repository::delete_upload(db, &upload_id).await?;
+pub async fn delete_upload(db: &PgPool, storage: &dyn Storage, upload_id: &str) -> AppResult<()> {
+    let blob_hash = repository::delete_upload(db, upload_id)
+        .await?
+        .and_then(|upload| upload.blob_hash);
+
+    let Some(blob_hash) = blob_hash else {
+        // blob could already be gone if someone else removed the upload in the meantime
+        if storage.exists(upload_id).await? {
+            storage.delete(upload_id).await?;
+        }
+        remove_thumbnails(db, storage, upload_id).await?;
+        return Ok(());
+    };
 
-    let file_path = format!("{storage_dir}{upload_id}");
-    fs::remove_file(file_path).await?;
+    // deduplicated content is removed only with its last upload, row lock keeps
+    // anyone from uploading the same content again until it's gone from storage
+    let mut tx = db.begin().await?;
+    if release_blob(&mut tx, &blob_hash).await? {
+        if storage.exists(&blob_hash).await? {
+            storage.delete(&blob_hash).await?;
+        }
+        remove_thumbnails(db, storage, &blob_hash).await?;
+    }
+    tx.commit().await?;
 
     Ok(())
 }
 
+#[utoipa::path(
+    delete,
+    path = "/delete/{upload_id}",
+    tag = "uploads",
+    params(("upload_id" = String, Path, description = "Id of an upload, or of a collection that's removed with all of its uploads."), DeleteQuery),
+    responses(
+        (status = 204, description = "Upload is removed."),
+        (status = 400, description = "`upload-not-found`, `invalid-delete-key` or `validation`.", body = ErrorResponse),
+        (status = 429, description = "`rate-limited`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+)]
 #[tracing::instrument]
 pub async fn delete_endpoint(
     ctx: Extension<AppContext>,
@@ -26,22 +57,44 @@ pub async fn delete_endpoint(
     extractors::Query(query): extractors::Query<DeleteQuery>,
 ) -> AppResult<StatusCode> {
     // check if upload exists
-    let upload = sqlx::query_as!(Upload, "SELECT * FROM uploads WHERE id = $1", upload_id)
+    let Some(upload) = sqlx::query_as!(Upload, "SELECT * FROM uploads WHERE id = $1", upload_id)
         .fetch_optional(&ctx.db)
         .await?
-        .ok_or(AppError::UploadNotFound)?;
+    else {
+        return delete_collection(&ctx, &upload_id, &query.key).await;
+    };
 
     // check if delete key matches
     if upload.delete_key != query.key {
         return Err(AppError::InvalidDeleteKey);
     }
 
-    delete_upload(&ctx.db, &ctx.cfg.general.storage_dir, &upload_id).await?;
+    delete_upload(&ctx.db, ctx.storage.as_ref(), &upload_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Removes collection together with every upload in it.
+async fn delete_collection(ctx: &AppContext, collection_id: &str, key: &str) -> AppResult<StatusCode> {
+    let collection = fetch_collection(&ctx.db, collection_id)
+        .await?
+        .ok_or(AppError::UploadNotFound)?;
+
+    if collection.delete_key != key {
+        return Err(AppError::InvalidDeleteKey);
+    }
+
+    for upload in fetch_collection_uploads(&ctx.db, collection_id).await? {
+        delete_upload(&ctx.db, ctx.storage.as_ref(), &upload.id).await?;
+    }
+    repository::delete_collection(&ctx.db, collection_id).await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct DeleteQuery {
+    /// Delete key that was returned with the upload.
     key: String,
 }
@@ -1,116 +1,261 @@
-use std::io::SeekFrom;
+use std::sync::Arc;
 
 use axum::{
     body::Body,
-    http::header::CONTENT_DISPOSITION,
-    response::IntoResponse,
+    http::{header::{ACCEPT_RANGES, CONTENT_DISPOSITION}, HeaderMap, HeaderValue},
+    response::Response,
     Extension,
 };
-use chacha20poly1305::{aead::stream::DecryptorBE32, XChaCha20Poly1305};
 use chrono::{Duration, Utc};
+use futures::StreamExt;
 use serde::Deserialize;
-use tokio::{
-    fs::{self, File},
-    io::{AsyncSeekExt, AsyncWriteExt},
-};
-use tokio_util::io::ReaderStream;
+use utoipa::IntoParams;
 
 use crate::{
-    errors::{AppError, AppResult}, extractors, repository::{add_download, fetch_upload}, utilities::{read_chunk, temp_file, DEC_CHUNK_SIZE}, AppContext
+    crypto::{upload_key, EncryptedBlob, Secret}, errors::{AppError, AppResult, ErrorResponse}, extractors, metrics::{self, CryptoTimer}, models::Upload, ranges::{self, requested_ranges, RangeSource, StoredBlob, Validators}, reaper::count_expired, repository::{add_download, fetch_upload, update_download_stats}, AppContext
 };
 
-use super::delete::delete_upload;
+use super::{delete::delete_upload, zip::collection_archive};
 
-#[tracing::instrument]
+#[utoipa::path(
+    get,
+    path = "/download/{upload_id}",
+    tag = "uploads",
+    params(
+        ("upload_id" = String, Path, description = "Id of an upload, or of a collection that's downloaded as a zip archive."),
+        DownloadQuery,
+        ("Range" = Option<String>, Header, description = "Bytes to download, several of them are sent as multipart/byteranges. \
+            Ignored for uploads that expire by downloads, they're always sent whole."),
+    ),
+    responses(
+        (status = 200, description = "Content of the upload, decrypted when it was encrypted by us, with the type detected when it was uploaded.", content_type = "application/octet-stream"),
+        (status = 206, description = "Requested ranges of the content.", content_type = "application/octet-stream"),
+        (status = 400, description = "`upload-not-found`, `missing-key`, `missing-password`, `invalid-decryption-key`, `invalid-password`, `key-not-accepted`, `corrupted-upload` or `validation`.", body = ErrorResponse),
+        (status = 404, description = "`upload-expired`.", body = ErrorResponse),
+        (status = 416, description = "`range-not-satisfiable`.", body = ErrorResponse),
+        (status = 429, description = "`rate-limited`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+)]
+#[tracing::instrument(skip(query, headers))]
 pub async fn download_endpoint(
     ctx: Extension<AppContext>,
     extractors::Path(upload_id): extractors::Path<String>,
     extractors::Query(query): extractors::Query<DownloadQuery>,
-) -> AppResult<impl IntoResponse> {
-    let upload = fetch_upload(&ctx.db, &upload_id)
-        .await?
-        .ok_or(AppError::UploadNotFound)?;
-
-    // TODO(hito): actually nice and better way of handling expired uploads
-    // because right now they are only removed IF someone tries to download them
-    // thus files that never get requested will stay in database and storage forever
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    let Some(upload) = fetch_upload(&ctx.db, &upload_id).await? else {
+        return Ok(count_downloaded(&ctx, collection_archive(&ctx, &upload_id, query.secret()).await?));
+    };
+
+    check_expiry(&ctx, &upload).await?;
+
+    let validators = Validators::of(&upload);
+    let (source, size) = upload_source(&ctx, &upload, query.secret()).await?;
+
+    // uploads that expire by downloads are only sent whole and every time counts,
+    // otherwise their parts could be fetched without ever reaching the limit
+    let limited = upload.expiry_downloads.is_some();
+    let ranges = if limited {
+        Vec::new()
+    } else {
+        requested_ranges(&headers, size, &validators)?
+    };
+
+    // resuming or seeking shouldn't count as another download
+    let counts_as_download = ranges.is_empty() || ranges.iter().any(|range| range.start == 0);
+
+    let mut response = ranges::respond(source, size, ranges, &validators, upload.content_type.as_deref()).await?;
+    if limited {
+        response.headers_mut().insert(ACCEPT_RANGES, HeaderValue::from_static("none"));
+    }
+
+    let mut response = if counts_as_download {
+        record_download(&ctx, &upload, response).await
+    } else {
+        response
+    };
+
+    let content_disposition = format!(r#"attachment; filename="{}""#, upload.file_name);
+    response.headers_mut().insert(
+        CONTENT_DISPOSITION,
+        HeaderValue::try_from(content_disposition).map_err(anyhow::Error::from)?,
+    );
+
+    Ok(count_downloaded(&ctx, response))
+}
+
+/// Counts bytes of the body as they're sent, so aborted downloads only count what they got.
+fn count_downloaded(ctx: &AppContext, response: Response) -> Response {
+    let mut sent = SentBytes {
+        ctx: ctx.clone(),
+        bytes: 0,
+    };
+    response.map(|body| {
+        let stream = body.into_data_stream().inspect(move |chunk| {
+            // whole guard has to move in, it'd be dropped right away otherwise
+            let sent = &mut sent;
+            if let Ok(chunk) = chunk {
+                metrics::downloaded(chunk.len() as u64);
+                sent.bytes += chunk.len() as u64;
+            }
+        });
+        Body::from_stream(stream)
+    })
+}
+
+/// Adds sent bytes to usage stats once the body is done, rather than with every chunk.
+struct SentBytes {
+    ctx: AppContext,
+    bytes: u64,
+}
+
+impl Drop for SentBytes {
+    fn drop(&mut self) {
+        if self.bytes == 0 {
+            return;
+        }
+
+        let db = self.ctx.db.clone();
+        let bytes = self.bytes;
+        tokio::spawn(async move {
+            if let Err(why) = update_download_stats(&db, 0, bytes).await {
+                tracing::error!("failed to update download stats: {why:?}");
+            }
+        });
+    }
+}
+
+/// Fails for uploads that are already expired.
+pub async fn check_expiry(ctx: &AppContext, upload: &Upload) -> AppResult<()> {
+    // expired uploads are purged periodically by the reaper, but one
+    // could still be requested before the next sweep
     if let Some(expiry_hours) = upload.expiry_hours {
         if Utc::now() >= upload.created_at + Duration::hours(expiry_hours as _) {
-            if let Err(why) = delete_upload(&ctx.db, &ctx.cfg.general.storage_dir, &upload_id).await {
-                tracing::error!("Failed to remove expired upload with id {upload_id}: {why:?}");
+            match delete_upload(&ctx.db, ctx.storage.as_ref(), &upload.id).await {
+                Ok(()) => count_expired(&ctx.db, 1).await,
+                Err(why) => tracing::error!("Failed to remove expired upload with id {}: {why:?}", upload.id),
             }
             return Err(AppError::UploadExpired);
         }
     }
 
-    let mut file = File::open(format!("{}{upload_id}", ctx.cfg.general.storage_dir)).await?;
+    if let Some(expiry_downloads) = upload.expiry_downloads {
+        if upload.downloads >= expiry_downloads {
+            return Err(AppError::UploadExpired);
+        }
+    }
 
-    let body = if let Some(nonce) = upload.nonce {
-        let nonce_bytes = hex::decode(nonce)?;
-        let key = query.key.ok_or(AppError::MissingKey)?;
-        let key_hash = upload.key_hash.ok_or(AppError::CorruptedUpload)?;
+    Ok(())
+}
 
-        if sha256::digest(&key) != key_hash {
-            return Err(AppError::InvalidDecryptionKey);
+/// Plaintext of the upload and its size.
+pub async fn upload_source(
+    ctx: &AppContext,
+    upload: &Upload,
+    secret: Secret<'_>,
+) -> AppResult<(Arc<dyn RangeSource>, u64)> {
+    // end-to-end encrypted uploads are served as they are, for the client to decrypt
+    if upload.e2e_version.is_some() {
+        if secret.key.is_some() || secret.password.is_some() {
+            return Err(AppError::KeyNotAccepted);
         }
 
-        let key_bytes = hex::decode(key)?;
-
-        let (mut temp_file, temp_path) = temp_file(&ctx.cfg.general.storage_dir).await?;
-        let mut decryptor = DecryptorBE32::<XChaCha20Poly1305>::new(
-            key_bytes.as_slice().into(),
-            nonce_bytes.as_slice().into(),
-        );
+        let blob = StoredBlob {
+            storage: ctx.storage.clone(),
+            key: upload.storage_key().to_string(),
+        };
+        return Ok((Arc::new(blob), ctx.storage.size(upload.storage_key()).await?));
+    }
 
-        loop {
-            let chunk = read_chunk(&mut file, DEC_CHUNK_SIZE).await?;
+    // encrypted uploads are decrypted chunk by chunk while the client reads the response
+    match &upload.nonce {
+        Some(nonce) => {
+            let cipher_key = upload_key(upload, secret).await?;
+            let mut nonce_bytes = [0u8; 19];
+            hex::decode_to_slice(nonce, &mut nonce_bytes)?;
 
-            if chunk.len() < DEC_CHUNK_SIZE {
-                let plaintext = decryptor.decrypt_last(chunk.as_slice())?;
-                temp_file.write_all(&plaintext).await?;
-                break;
-            } else {
-                let plaintext = decryptor.decrypt_next(chunk.as_slice())?;
-                temp_file.write_all(&plaintext).await?;
-            }
+            let blob = EncryptedBlob {
+                storage: ctx.storage.clone(),
+                key: upload.storage_key().to_string(),
+                cipher_key,
+                nonce: nonce_bytes,
+                ciphertext_size: ctx.storage.size(upload.storage_key()).await?,
+                timer: CryptoTimer::decryption(),
+            };
+            let size = blob.plaintext_size();
+            Ok((Arc::new(blob), size))
         }
+        None => {
+            let blob = StoredBlob {
+                storage: ctx.storage.clone(),
+                key: upload.storage_key().to_string(),
+            };
+            Ok((Arc::new(blob), ctx.storage.size(upload.storage_key()).await?))
+        }
+    }
+}
 
-        temp_file.seek(SeekFrom::Start(0)).await?;
-        let stream = ReaderStream::new(temp_file);
-        let body = Body::from_stream(stream);
+/// Increments download count of the upload, when it was the last allowed download
+/// the upload is removed as soon as the response body is sent or dropped.
+pub async fn record_download(ctx: &AppContext, upload: &Upload, response: Response) -> Response {
+    if let Err(why) = add_download(&ctx.db, &upload.id).await {
+        tracing::warn!("failed to increment download count for `{}`: {why:?}", upload.id);
+    }
+    if let Err(why) = update_download_stats(&ctx.db, 1, 0).await {
+        tracing::error!("failed to update download stats: {why:?}");
+    }
 
-        if let Err(why) = fs::remove_file(&temp_path).await {
-            tracing::warn!("failed to remove decryption temp file ({temp_path}): {why:?}");
+    match upload.expiry_downloads {
+        Some(expiry_downloads) if expiry_downloads <= upload.downloads + 1 => {
+            let guard = DeleteOnDrop {
+                ctx: ctx.clone(),
+                upload_id: upload.id.clone(),
+            };
+            response.map(|body| {
+                let stream = body.into_data_stream().map(move |chunk| {
+                    let _ = &guard;
+                    chunk
+                });
+                Body::from_stream(stream)
+            })
         }
+        _ => response,
+    }
+}
 
-        body
-    } else {
-        let stream = ReaderStream::new(file);
-        Body::from_stream(stream)
-    };
+struct DeleteOnDrop {
+    ctx: AppContext,
+    upload_id: String,
+}
 
-    if let Err(why) = add_download(&ctx.db, &upload_id).await {
-        tracing::warn!("failed to increment download count for `{upload_id}`: {why:?}");
-    }
+impl Drop for DeleteOnDrop {
+    fn drop(&mut self) {
+        let ctx = self.ctx.clone();
+        let upload_id = std::mem::take(&mut self.upload_id);
 
-    if let Some(expiry_downloads) = upload.expiry_downloads {
-        if expiry_downloads <= upload.downloads + 1 {
-            if let Err(why) = delete_upload(&ctx.db, &ctx.cfg.general.storage_dir, &upload_id).await {
-                tracing::error!("Failed to remove expired upload with id {upload_id}: {why:?}");
+        tokio::spawn(async move {
+            match delete_upload(&ctx.db, ctx.storage.as_ref(), &upload_id).await {
+                Ok(()) => count_expired(&ctx.db, 1).await,
+                Err(why) => tracing::error!("Failed to remove expired upload with id {upload_id}: {why:?}"),
             }
-        }
+        });
     }
-
-    Ok((
-        [(
-            CONTENT_DISPOSITION,
-            format!(r#"attachment; filename="{}""#, upload.file_name),
-        )],
-        body,
-    ))
 }
 
-#[derive(Debug, Deserialize)]
+/// Encrypted uploads need either their key or their password.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct DownloadQuery {
     key: Option<String>,
+    password: Option<String>,
+}
+
+impl DownloadQuery {
+    pub fn secret(&self) -> Secret<'_> {
+        Secret {
+            key: self.key.as_deref(),
+            password: self.password.as_deref(),
+        }
+    }
 }
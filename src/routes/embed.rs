@@ -0,0 +1,358 @@
+//! Link previews of embedded uploads: a page with OpenGraph and Twitter card tags that chat apps
+//! and social sites unfurl, and oEmbed responses for the ones that ask for those instead.
+
+use axum::{
+    http::header::CONTENT_SECURITY_POLICY,
+    response::{Html, IntoResponse, Response},
+    Extension, Json,
+};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    crypto::Secret, errors::{AppError, AppResult, ErrorResponse}, extractors, models::Upload, repository::fetch_upload, thumbnails::{dimensions, snap_down}, AppContext
+};
+
+use super::{download::upload_source, preview::PreviewKind};
+
+const PROVIDER_NAME: &str = "cipherfiles";
+// headers of common image formats are well within that, their size is all that's read
+const HEADER_BYTES: u64 = 64 * 1024;
+// size of the thumbnail linked in `og:image`
+const IMAGE_WIDTH: u32 = 1024;
+const IMAGE_HEIGHT: u32 = 2048;
+// size of the player when nothing else is asked for, videos aren't probed for their own
+const PLAYER_WIDTH: u32 = 640;
+const PLAYER_HEIGHT: u32 = 360;
+
+#[utoipa::path(
+    get,
+    path = "/embed/{upload_id}",
+    tag = "uploads",
+    params(("upload_id" = String, Path, description = "Id of an upload that was uploaded as embedded.")),
+    responses(
+        (status = 200, description = "Page showing the upload, with OpenGraph and Twitter card tags pointing at its preview. \
+            Encrypted uploads only get their name, their key would have to be in the links.", content_type = "text/html"),
+        (status = 400, description = "`upload-not-found`.", body = ErrorResponse),
+        (status = 404, description = "`embed-not-enabled` or `upload-expired`.", body = ErrorResponse),
+        (status = 429, description = "`rate-limited`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+)]
+pub async fn embed_endpoint(
+    ctx: Extension<AppContext>,
+    extractors::Path(upload_id): extractors::Path<String>,
+) -> AppResult<Response> {
+    let upload = embedded_upload(&ctx, &upload_id).await?;
+    let media = Media::of(&ctx, &upload).await?;
+    let links = Links::new(&ctx, &upload.id);
+
+    Ok((
+        // media comes from the api itself, the page doesn't need anything else
+        [(CONTENT_SECURITY_POLICY, "default-src 'none'; img-src 'self'; media-src 'self'")],
+        Html(page(&upload, media, &links)),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/oembed",
+    tag = "uploads",
+    params(OembedQuery),
+    responses(
+        (status = 200, description = "`photo` for images, `video` for videos and `link` for anything else.", body = OembedResponse),
+        (status = 400, description = "`upload-not-found` or `validation`.", body = ErrorResponse),
+        (status = 404, description = "`embed-not-enabled` or `upload-expired`.", body = ErrorResponse),
+        (status = 429, description = "`rate-limited`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+)]
+pub async fn oembed_endpoint(
+    ctx: Extension<AppContext>,
+    extractors::Query(query): extractors::Query<OembedQuery>,
+) -> AppResult<Json<OembedResponse>> {
+    if query.format.as_deref().is_some_and(|format| format != "json") {
+        return Err(AppError::Validation(String::from("only json oEmbed responses are supported.")));
+    }
+
+    let prefix = format!("{}/embed/", ctx.cfg.general.public_url());
+    let upload_id = query
+        .url
+        .strip_prefix(&prefix)
+        .and_then(|rest| rest.split(['/', '?', '#']).next())
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| AppError::Validation(String::from("url isn't a link to an embedded upload.")))?;
+
+    let upload = embedded_upload(&ctx, upload_id).await?;
+    let media = Media::of(&ctx, &upload).await?;
+    let links = Links::new(&ctx, &upload.id);
+    let max_width = query.maxwidth.unwrap_or(u32::MAX);
+    let max_height = query.maxheight.unwrap_or(u32::MAX);
+
+    let mut response = OembedResponse {
+        version: String::from("1.0"),
+        kind: OembedType::Link,
+        title: upload.file_name.clone(),
+        provider_name: String::from(PROVIDER_NAME),
+        provider_url: ctx.cfg.general.public_url(),
+        url: None,
+        html: None,
+        width: None,
+        height: None,
+    };
+
+    match media {
+        Media::Image { width, height } => {
+            // thumbnail sizes only go in steps, so the largest one that still fits is used
+            let bound_width = snap_down(max_width.min(IMAGE_WIDTH));
+            let bound_height = snap_down(max_height.min(IMAGE_HEIGHT));
+            let (width, height) = fit_within(width, height, bound_width, bound_height);
+
+            response.kind = OembedType::Photo;
+            response.url = Some(format!("{}?w={bound_width}&h={bound_height}&format=jpeg", links.preview));
+            response.width = Some(width);
+            response.height = Some(height);
+        }
+        Media::Video => {
+            let (width, height) = fit_within(PLAYER_WIDTH, PLAYER_HEIGHT, max_width, max_height);
+
+            response.kind = OembedType::Video;
+            response.html = Some(format!(
+                r#"<iframe src="{}" width="{width}" height="{height}" frameborder="0" allowfullscreen></iframe>"#,
+                escape(&links.page)
+            ));
+            response.width = Some(width);
+            response.height = Some(height);
+        }
+        Media::None => {}
+    }
+
+    Ok(Json(response))
+}
+
+/// Upload that can be embedded, others are only reachable the usual way.
+async fn embedded_upload(ctx: &AppContext, upload_id: &str) -> AppResult<Upload> {
+    let upload = fetch_upload(&ctx.db, upload_id)
+        .await?
+        .ok_or(AppError::UploadNotFound)?;
+
+    if !upload.embedded {
+        return Err(AppError::EmbedNotEnabled);
+    }
+    // reaper removes it soon enough, it's just not shown until then
+    if upload.is_expired() {
+        return Err(AppError::UploadExpired);
+    }
+
+    Ok(upload)
+}
+
+/// What an embed of the upload can show inline.
+enum Media {
+    /// Image with its size scaled down to the thumbnail in `og:image`.
+    Image { width: u32, height: u32 },
+    Video,
+    None,
+}
+
+impl Media {
+    async fn of(ctx: &AppContext, upload: &Upload) -> AppResult<Self> {
+        // preview of an encrypted upload needs its key, which mustn't end up in the page
+        if upload.nonce.is_some() || upload.e2e_version.is_some() {
+            return Ok(Self::None);
+        }
+        let kind = upload.content_type.as_deref().and_then(PreviewKind::of);
+
+        match kind {
+            Some(PreviewKind::Image) if upload.bytes as u64 <= ctx.cfg.general.max_thumbnail_source_bytes() => {
+                let (source, size) = upload_source(ctx, upload, Secret::default()).await?;
+                let head = source
+                    .range(0..size.min(HEADER_BYTES))
+                    .await?
+                    .map_ok(|chunk| chunk.to_vec())
+                    .try_concat()
+                    .await?;
+
+                Ok(match dimensions(&head) {
+                    Some((width, height)) => {
+                        let (width, height) = fit_within(width, height, IMAGE_WIDTH, IMAGE_HEIGHT);
+                        Self::Image { width, height }
+                    }
+                    None => Self::None,
+                })
+            }
+            Some(PreviewKind::Video) if upload.bytes as u64 <= ctx.cfg.general.max_preview_bytes => Ok(Self::Video),
+            _ => Ok(Self::None),
+        }
+    }
+}
+
+/// Absolute links to everything an embed points at.
+struct Links {
+    page: String,
+    preview: String,
+    download: String,
+    oembed: String,
+}
+
+impl Links {
+    fn new(ctx: &AppContext, upload_id: &str) -> Self {
+        let base = ctx.cfg.general.public_url();
+        let page = format!("{base}/embed/{upload_id}");
+        let oembed = format!(
+            "{base}/oembed?format=json&url={}",
+            form_urlencoded::byte_serialize(page.as_bytes()).collect::<String>()
+        );
+
+        Self {
+            preview: format!("{base}/preview/{upload_id}"),
+            download: format!("{base}/download/{upload_id}"),
+            page,
+            oembed,
+        }
+    }
+}
+
+fn page(upload: &Upload, media: Media, links: &Links) -> String {
+    let title = escape(&upload.file_name);
+    let mut meta = vec![
+        ("og:site_name", String::from(PROVIDER_NAME)),
+        ("og:title", upload.file_name.clone()),
+        ("og:url", links.page.clone()),
+    ];
+
+    let body = match media {
+        Media::Image { width, height } => {
+            meta.extend([
+                ("og:type", String::from("website")),
+                ("og:image", format!("{}?w={IMAGE_WIDTH}&format=jpeg", links.preview)),
+                ("og:image:type", String::from("image/jpeg")),
+                ("og:image:width", width.to_string()),
+                ("og:image:height", height.to_string()),
+                ("og:image:alt", upload.file_name.clone()),
+                ("twitter:card", String::from("summary_large_image")),
+            ]);
+            format!(r#"<img src="{}" alt="{title}">"#, escape(&links.preview))
+        }
+        Media::Video => {
+            let content_type = upload.content_type.clone().unwrap_or_default();
+            meta.extend([
+                ("og:type", String::from("video.other")),
+                ("og:video", links.preview.clone()),
+                ("og:video:type", content_type),
+                ("og:video:width", PLAYER_WIDTH.to_string()),
+                ("og:video:height", PLAYER_HEIGHT.to_string()),
+                ("twitter:card", String::from("player")),
+                ("twitter:player", links.page.clone()),
+                ("twitter:player:stream", links.preview.clone()),
+                ("twitter:player:width", PLAYER_WIDTH.to_string()),
+                ("twitter:player:height", PLAYER_HEIGHT.to_string()),
+            ]);
+            format!(r#"<video src="{}" controls playsinline></video>"#, escape(&links.preview))
+        }
+        Media::None => {
+            meta.extend([
+                ("og:type", String::from("website")),
+                ("twitter:card", String::from("summary")),
+            ]);
+            String::new()
+        }
+    };
+
+    // twitter reads its own tags by name, everyone else goes by property
+    let meta = meta
+        .into_iter()
+        .map(|(property, content)| {
+            let attribute = if property.starts_with("twitter:") { "name" } else { "property" };
+            format!(r#"<meta {attribute}="{property}" content="{}">"#, escape(&content))
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+{meta}
+<link rel="alternate" type="application/json+oembed" href="{oembed}" title="{title}">
+</head>
+<body>
+{body}
+<p><a href="{download}">Download {title}</a></p>
+</body>
+</html>
+"#,
+        oembed = escape(&links.oembed),
+        download = escape(&links.download),
+    )
+}
+
+/// Largest size with the same aspect ratio as `width` x `height` that fits the bounds, it's never enlarged.
+fn fit_within(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
+    if width <= max_width && height <= max_height {
+        return (width, height);
+    }
+    let scale = f64::min(max_width as f64 / width as f64, max_height as f64 / height as f64);
+    let scaled = |side: u32| ((side as f64 * scale).round() as u32).max(1);
+    (scaled(width), scaled(height))
+}
+
+/// Text that's safe within HTML and its quoted attributes.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for char in text.chars() {
+        match char {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OembedQuery {
+    /// Link to the embed page of an upload, `/embed/{upload_id}`.
+    url: String,
+    maxwidth: Option<u32>,
+    maxheight: Option<u32>,
+    /// Only `json` is supported.
+    format: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OembedType {
+    Photo,
+    Video,
+    Link,
+}
+
+/// Response as described by the oEmbed 1.0 spec.
+#[derive(Serialize, ToSchema)]
+pub struct OembedResponse {
+    version: String,
+    #[serde(rename = "type")]
+    kind: OembedType,
+    title: String,
+    provider_name: String,
+    provider_url: String,
+    /// Thumbnail of a photo.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    /// Player of a video.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    html: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    height: Option<u32>,
+}
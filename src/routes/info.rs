@@ -1,62 +1,166 @@
 use axum::{
+    response::{IntoResponse, Response},
     Extension, Json,
 };
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
 use crate::{
-    errors::{AppError, AppResult}, extractors, repository::fetch_upload, AppContext
+    crypto::{unlock, upload_key, Kdf, Secret}, errors::{AppError, AppResult, ErrorResponse}, extractors, models::Upload, repository::{fetch_collection, fetch_collection_uploads, fetch_upload}, AppContext
 };
 
-use super::delete::delete_upload;
+use super::{download::check_expiry, openapi::UploadInfo};
 
+#[utoipa::path(
+    get,
+    path = "/info/{upload_id}",
+    tag = "uploads",
+    params(("upload_id" = String, Path, description = "Id of an upload or a collection."), InfoQuery),
+    responses(
+        (status = 200, description = "Upload, or a collection when the id belongs to one.", body = UploadInfo),
+        (status = 400, description = "`upload-not-found`, `missing-key`, `missing-password`, `invalid-decryption-key`, `invalid-password`, `key-not-accepted`, `corrupted-upload` or `validation`.", body = ErrorResponse),
+        (status = 404, description = "`upload-expired`.", body = ErrorResponse),
+        (status = 429, description = "`rate-limited`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+)]
 pub async fn info_endpoint(
     ctx: Extension<AppContext>,
     extractors::Path(upload_id): extractors::Path<String>,
     extractors::Query(query): extractors::Query<InfoQuery>,
-) -> AppResult<Json<InfoResponse>> {
-    let upload = fetch_upload(&ctx.db, &upload_id)
+) -> AppResult<Response> {
+    let Some(upload) = fetch_upload(&ctx.db, &upload_id).await? else {
+        return collection_info(&ctx, &upload_id, query.secret()).await;
+    };
+
+    check_expiry(&ctx, &upload).await?;
+
+    if upload.key_hash.is_some() {
+        upload_key(&upload, query.secret()).await?;
+    } else if upload.e2e_version.is_some() && (query.key.is_some() || query.password.is_some()) {
+        return Err(AppError::KeyNotAccepted);
+    }
+
+    Ok(Json(InfoResponse::from(upload)).into_response())
+}
+
+async fn collection_info(ctx: &AppContext, collection_id: &str, secret: Secret<'_>) -> AppResult<Response> {
+    let collection = fetch_collection(&ctx.db, collection_id)
         .await?
         .ok_or(AppError::UploadNotFound)?;
+    let uploads = fetch_collection_uploads(&ctx.db, collection_id).await?;
 
-    // TODO(hito): better way of handling expired uploads
-    // because right now they are only removed IF someone tries to download them
-    // thus files that never get requested will stay in database and storage forever
-    if let Some(expiry_hours) = upload.expiry_hours {
-        if Utc::now() >= upload.created_at + Duration::hours(expiry_hours as _) {
-            if let Err(why) = delete_upload(&ctx.db, &ctx.cfg.general.storage_dir, &upload_id).await {
-                tracing::error!("Failed to remove expired upload with id {upload_id}: {why:?}");
-            }
-            return Err(AppError::UploadExpired);
-        }
+    if let Some(key_hash) = &collection.key_hash {
+        // every upload of the collection is encrypted with the same key
+        let kdf = match uploads.first() {
+            Some(upload) => Kdf::of(upload)?,
+            None => None,
+        };
+        unlock(key_hash, kdf.as_ref(), secret).await?;
     }
 
-    if let Some(key_hash) = upload.key_hash {
-        let query_key = query.key.ok_or(AppError::MissingKey)?;
+    let files = uploads
+        .into_iter()
+        .filter(|upload| !upload.is_expired())
+        .map(|upload| CollectionMember {
+            id: upload.id.clone(),
+            info: InfoResponse::from(upload),
+        })
+        .collect::<Vec<_>>();
 
-        if sha256::digest(query_key) != key_hash {
-            return Err(AppError::InvalidDecryptionKey)?;
-        }
+    // collection is removed by the reaper once all of its uploads are gone
+    if files.is_empty() {
+        return Err(AppError::UploadExpired);
     }
 
-    Ok(Json(InfoResponse {
-        file_name: upload.file_name,
-        bytes: upload.bytes,
-        downloads: upload.downloads,
-        embedded: upload.embedded,
-    }))
+    Ok(Json(CollectionInfoResponse {
+        id: collection.id,
+        created_at: collection.created_at,
+        bytes: files.iter().map(|file| file.info.bytes).sum(),
+        files,
+    })
+    .into_response())
 }
 
-#[derive(Deserialize)]
+/// Encrypted uploads need either their key or their password.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct InfoQuery {
     key: Option<String>,
+    password: Option<String>,
 }
 
-#[derive(Serialize)]
+impl InfoQuery {
+    fn secret(&self) -> Secret<'_> {
+        Secret {
+            key: self.key.as_deref(),
+            password: self.password.as_deref(),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct InfoResponse {
     file_name: String,
     bytes: i64,
     downloads: i32,
     embedded: bool,
+    /// Detected when the file was uploaded, missing for end-to-end encrypted ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    collection_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    e2e: Option<E2eInfo>,
+}
+
+/// Everything the client needs to decrypt end-to-end encrypted upload besides its key.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct E2eInfo {
+    version: i16,
+    chunk_size: i32,
+    nonce: String,
+}
+
+impl From<Upload> for InfoResponse {
+    fn from(upload: Upload) -> Self {
+        let e2e = match (upload.e2e_version, upload.e2e_chunk_size, &upload.nonce) {
+            (Some(version), Some(chunk_size), Some(nonce)) => Some(E2eInfo {
+                version,
+                chunk_size,
+                nonce: nonce.clone(),
+            }),
+            _ => None,
+        };
+
+        Self {
+            e2e,
+            file_name: upload.file_name,
+            bytes: upload.bytes,
+            downloads: upload.downloads,
+            embedded: upload.embedded,
+            content_type: upload.content_type,
+            collection_id: upload.collection_id,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionInfoResponse {
+    id: String,
+    created_at: DateTime<Utc>,
+    bytes: i64,
+    files: Vec<CollectionMember>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionMember {
+    id: String,
+    #[serde(flatten)]
+    info: InfoResponse,
 }
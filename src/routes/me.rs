@@ -0,0 +1,241 @@
+use axum::{
+    http::StatusCode,
+    routing::{delete, get, post},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    accounts::create_api_key,
+    errors::{AppError, AppResult, ErrorResponse},
+    extractors,
+    models::{Account, ApiKey, Upload},
+    repository::{delete_api_key, fetch_owned_uploads, fetch_upload},
+    AppContext,
+};
+
+use super::{
+    delete::delete_upload,
+    pagination::{Page, Paginated},
+};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/", get(account_endpoint))
+        .route("/uploads", get(uploads_endpoint))
+        .route("/uploads/:upload_id", delete(delete_endpoint))
+        .route("/keys", post(create_key_endpoint))
+        .route("/keys/:key_id", delete(revoke_key_endpoint))
+}
+
+#[utoipa::path(
+    get,
+    path = "/me",
+    tag = "me",
+    responses(
+        (status = 200, body = AccountResponse),
+        (status = 401, description = "`missing-api-key` or `invalid-api-key`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+    security(("api_key" = [])),
+)]
+pub async fn account_endpoint(account: Account) -> Json<AccountResponse> {
+    Json(account.into())
+}
+
+#[utoipa::path(
+    get,
+    path = "/me/uploads",
+    tag = "me",
+    params(UploadsQuery),
+    responses(
+        (status = 200, body = Paginated<OwnedUpload>),
+        (status = 400, description = "`validation`.", body = ErrorResponse),
+        (status = 401, description = "`missing-api-key` or `invalid-api-key`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+    security(("api_key" = [])),
+)]
+pub async fn uploads_endpoint(
+    ctx: Extension<AppContext>,
+    account: Account,
+    extractors::Query(query): extractors::Query<UploadsQuery>,
+) -> AppResult<Json<Paginated<OwnedUpload>>> {
+    let page = Page::new(query.page, query.per_page)?;
+    let (uploads, total) = fetch_owned_uploads(&ctx.db, &account.id, page.limit(), page.offset()).await?;
+    let uploads = uploads.into_iter().map(OwnedUpload::from).collect();
+
+    Ok(Json(Paginated::new(uploads, page, total)))
+}
+
+/// Owners don't need the delete key of their uploads.
+#[utoipa::path(
+    delete,
+    path = "/me/uploads/{upload_id}",
+    tag = "me",
+    params(("upload_id" = String, Path)),
+    responses(
+        (status = 204, description = "Upload is removed."),
+        (status = 400, description = "`upload-not-found`.", body = ErrorResponse),
+        (status = 401, description = "`missing-api-key` or `invalid-api-key`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+    security(("api_key" = [])),
+)]
+#[tracing::instrument(skip(ctx))]
+pub async fn delete_endpoint(
+    ctx: Extension<AppContext>,
+    account: Account,
+    extractors::Path(upload_id): extractors::Path<String>,
+) -> AppResult<StatusCode> {
+    // uploads of others look like they don't exist
+    fetch_upload(&ctx.db, &upload_id)
+        .await?
+        .filter(|upload| upload.owner_id.as_ref() == Some(&account.id))
+        .ok_or(AppError::UploadNotFound)?;
+
+    delete_upload(&ctx.db, ctx.storage.as_ref(), &upload_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/me/keys",
+    tag = "me",
+    request_body = CreateKeyBody,
+    responses(
+        (status = 201, body = CreatedKeyResponse),
+        (status = 400, description = "`validation`.", body = ErrorResponse),
+        (status = 401, description = "`missing-api-key` or `invalid-api-key`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+    security(("api_key" = [])),
+)]
+pub async fn create_key_endpoint(
+    ctx: Extension<AppContext>,
+    account: Account,
+    extractors::Json(body): extractors::Json<CreateKeyBody>,
+) -> AppResult<(StatusCode, Json<CreatedKeyResponse>)> {
+    let (api_key, key) = create_api_key(&ctx.db, &account.id, body.label.as_deref()).await?;
+    Ok((StatusCode::CREATED, Json(CreatedKeyResponse::new(api_key, key))))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/me/keys/{key_id}",
+    tag = "me",
+    params(("key_id" = i64, Path)),
+    responses(
+        (status = 204, description = "Key can't be used anymore."),
+        (status = 400, description = "`validation`.", body = ErrorResponse),
+        (status = 401, description = "`missing-api-key` or `invalid-api-key`.", body = ErrorResponse),
+        (status = 404, description = "`api-key-not-found`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+    security(("api_key" = [])),
+)]
+pub async fn revoke_key_endpoint(
+    ctx: Extension<AppContext>,
+    account: Account,
+    extractors::Path(key_id): extractors::Path<i64>,
+) -> AppResult<StatusCode> {
+    delete_api_key(&ctx.db, &account.id, key_id)
+        .await?
+        .ok_or(AppError::ApiKeyNotFound)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UploadsQuery {
+    /// Starts at 1.
+    page: Option<u32>,
+    /// Between 1 and 200, defaults to 50.
+    per_page: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateKeyBody {
+    pub label: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountResponse {
+    id: String,
+    name: String,
+    max_file_bytes: Option<i64>,
+    default_expiry_hours: Option<i32>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<Account> for AccountResponse {
+    fn from(account: Account) -> Self {
+        Self {
+            id: account.id,
+            name: account.name,
+            max_file_bytes: account.max_file_bytes,
+            default_expiry_hours: account.default_expiry_hours,
+            created_at: account.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedKeyResponse {
+    id: i64,
+    /// Only time the key is shown, it can't be recovered later.
+    key: String,
+    label: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl CreatedKeyResponse {
+    pub fn new(api_key: ApiKey, key: String) -> Self {
+        Self {
+            id: api_key.id,
+            key,
+            label: api_key.label,
+            created_at: api_key.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnedUpload {
+    id: String,
+    file_name: String,
+    bytes: i64,
+    downloads: i32,
+    expiry_hours: Option<i32>,
+    expiry_downloads: Option<i32>,
+    expired: bool,
+    created_at: DateTime<Utc>,
+    collection_id: Option<String>,
+    encrypted: bool,
+    e2e: bool,
+}
+
+impl From<Upload> for OwnedUpload {
+    fn from(upload: Upload) -> Self {
+        Self {
+            expired: upload.is_expired(),
+            encrypted: upload.key_hash.is_some(),
+            e2e: upload.e2e_version.is_some(),
+            id: upload.id,
+            file_name: upload.file_name,
+            bytes: upload.bytes,
+            downloads: upload.downloads,
+            expiry_hours: upload.expiry_hours,
+            expiry_downloads: upload.expiry_downloads,
+            created_at: upload.created_at,
+            collection_id: upload.collection_id,
+        }
+    }
+}
@@ -0,0 +1,13 @@
+use axum::{http::header::CONTENT_TYPE, response::IntoResponse, Extension};
+
+use crate::{metrics, AppContext};
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "service",
+    responses((status = 200, description = "Metrics in Prometheus text format.", content_type = "text/plain")),
+)]
+pub async fn metrics_endpoint(ctx: Extension<AppContext>) -> impl IntoResponse {
+    ([(CONTENT_TYPE, "text/plain; version=0.0.4")], metrics::render(&ctx.db))
+}
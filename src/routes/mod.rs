@@ -1,6 +1,14 @@
+pub mod admin;
 pub mod delete;
 pub mod download;
+pub mod embed;
 pub mod info;
+pub mod me;
+pub mod metrics;
+pub mod openapi;
+pub mod pagination;
 pub mod stats;
+pub mod tus;
 pub mod upload;
 pub mod preview;
+pub mod zip;
@@ -0,0 +1,237 @@
+//! OpenAPI description of the whole API, generated from route handlers and the types they use.
+
+use axum::Json;
+use serde::Serialize;
+use utoipa::{
+    openapi::{
+        example::ExampleBuilder,
+        path::Operation,
+        security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+        ContentBuilder, Ref, RefOr,
+    },
+    Modify, OpenApi, ToSchema,
+};
+
+use crate::{
+    errors::{AppError, ErrorResponse},
+    ratelimit::RateLimit,
+    thumbnails::{Fit, ThumbnailFormat},
+};
+
+use super::{
+    admin, delete, download, embed,
+    info::{self, CollectionInfoResponse, InfoResponse},
+    me, metrics, preview, stats, tus, upload, zip,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "cipherfiles", description = "File sharing with optional encryption."),
+    paths(
+        crate::health_check,
+        upload::upload_endpoint,
+        info::info_endpoint,
+        download::download_endpoint,
+        preview::preview_endpoint,
+        embed::embed_endpoint,
+        embed::oembed_endpoint,
+        zip::zip_endpoint,
+        delete::delete_endpoint,
+        stats::service_stats,
+        metrics::metrics_endpoint,
+        tus::options_endpoint,
+        tus::create_endpoint,
+        tus::offset_endpoint,
+        tus::patch_endpoint,
+        tus::terminate_endpoint,
+        me::account_endpoint,
+        me::uploads_endpoint,
+        me::delete_endpoint,
+        me::create_key_endpoint,
+        me::revoke_key_endpoint,
+        admin::uploads::list_endpoint,
+        admin::uploads::show_endpoint,
+        admin::uploads::delete_endpoint,
+        admin::accounts::create_endpoint,
+        admin::accounts::create_key_endpoint,
+        admin::stats::stats_endpoint,
+        admin::audit::list_endpoint,
+        admin::blacklist::list_endpoint,
+        admin::blacklist::add_endpoint,
+        admin::blacklist::remove_endpoint,
+        openapi_endpoint,
+    ),
+    // enums used only in query parameters aren't picked up on their own
+    components(schemas(ErrorResponse, stats::Granularity, Fit, ThumbnailFormat)),
+    modifiers(&Security, &Errors),
+    tags(
+        (name = "uploads", description = "Uploading, downloading and removing files."),
+        (name = "tus", description = "Resumable uploads following tus 1.0.0."),
+        (name = "me", description = "Account of the API key that's used."),
+        (name = "admin", description = "Moderation, requires an admin token."),
+        (name = "service", description = "State of the service itself."),
+    )
+)]
+pub struct ApiDoc;
+
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "service",
+    responses((status = 200, description = "This document.", content_type = "application/json")),
+)]
+pub async fn openapi_endpoint() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Files of an upload, `password` has to come before them as they're encrypted while they're received.
+/// Several files end up in a collection.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct UploadForm {
+    /// Encrypts the files with a key derived from it, instead of returning the key.
+    password: Option<String>,
+    #[schema(value_type = Vec<String>, format = Binary)]
+    file: Vec<Vec<u8>>,
+}
+
+/// Body of `/info`, which depends on whether the id belongs to an upload or a collection.
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+#[allow(dead_code)]
+pub enum UploadInfo {
+    Upload(InfoResponse),
+    Collection(CollectionInfoResponse),
+}
+
+struct Security;
+
+impl Modify for Security {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        let bearer = |description: &str| {
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some(description))
+                    .build(),
+            )
+        };
+        components.add_security_scheme("api_key", bearer("API key of an account, issued by an admin."));
+        components.add_security_scheme("admin_token", bearer("One of the tokens in the admin config."));
+    }
+}
+
+/// Adds examples to the error responses each operation declares, of the errors its description
+/// names by `errorCode`. Several errors with the same status are told apart by their code.
+struct Errors;
+
+impl Modify for Errors {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let errors = AppError::examples();
+
+        for item in openapi.paths.paths.values_mut() {
+            let operations = [
+                &mut item.get,
+                &mut item.post,
+                &mut item.patch,
+                &mut item.delete,
+                &mut item.head,
+                &mut item.options,
+            ];
+            for operation in operations.into_iter().flatten() {
+                add_examples(operation, &errors);
+            }
+        }
+    }
+}
+
+fn add_examples(operation: &mut Operation, errors: &[AppError]) {
+    for (status, response) in operation.responses.responses.iter_mut() {
+        let RefOr::T(response) = response else {
+            continue;
+        };
+        // codes are the ones in backticks
+        let codes = response.description.split('`').skip(1).step_by(2).collect::<Vec<_>>();
+        let examples = errors
+            .iter()
+            .filter(|error| error.status_code().as_str() == status && codes.contains(&error.error_code()))
+            .map(|error| {
+                let value = serde_json::json!({
+                    "errorCode": error.error_code(),
+                    "error": error.to_string(),
+                });
+                (error.error_code(), ExampleBuilder::new().value(Some(value)).build())
+            })
+            .collect::<Vec<_>>();
+        if examples.is_empty() {
+            continue;
+        }
+
+        let content = ContentBuilder::new()
+            .schema(Some(Ref::from_schema_name("ErrorResponse")))
+            .examples_from_iter(examples)
+            .build();
+        response.content.insert(String::from("application/json"), content);
+    }
+}
+
+impl AppError {
+    /// One of every error, to show what each of them looks like.
+    pub fn examples() -> Vec<Self> {
+        let mut examples = vec![Self::EmptyUpload];
+        while let Some(next) = examples.last().and_then(Self::next_example) {
+            examples.push(next);
+        }
+        examples
+    }
+
+    /// Example of the error declared after this one. There's no wildcard, so a new
+    /// variant doesn't compile until it's given a place here.
+    fn next_example(&self) -> Option<Self> {
+        let limit = RateLimit {
+            limit: 10,
+            remaining: 0,
+            reset: 60,
+            retry_after: Some(60),
+        };
+
+        let next = match self {
+            Self::EmptyUpload => Self::InvalidFileName,
+            Self::InvalidFileName => Self::UploadNotFound,
+            Self::UploadNotFound => Self::InvalidDeleteKey,
+            Self::InvalidDeleteKey => Self::InvalidDecryptionKey,
+            Self::InvalidDecryptionKey => Self::CorruptedUpload,
+            Self::CorruptedUpload => Self::MissingKey,
+            Self::MissingKey => Self::MissingPassword,
+            Self::MissingPassword => Self::InvalidPassword,
+            Self::InvalidPassword => Self::KeyNotAccepted,
+            Self::KeyNotAccepted => Self::BothExpirations,
+            Self::BothExpirations => Self::UploadExpired,
+            Self::UploadExpired => Self::MediaTooBig,
+            Self::MediaTooBig => Self::PreviewNotSupported,
+            Self::PreviewNotSupported => Self::EmbedNotEnabled,
+            Self::EmbedNotEnabled => Self::FileBlacklisted,
+            Self::FileBlacklisted => Self::HashNotBlacklisted,
+            Self::HashNotBlacklisted => Self::AdminUnauthorized,
+            Self::AdminUnauthorized => Self::MissingApiKey,
+            Self::MissingApiKey => Self::InvalidApiKey,
+            Self::InvalidApiKey => Self::AccountNotFound,
+            Self::AccountNotFound => Self::ApiKeyNotFound,
+            Self::ApiKeyNotFound => Self::FileTooLarge(1024 * 1024 * 1024),
+            Self::FileTooLarge(_) => Self::StorageFull,
+            Self::StorageFull => Self::RateLimited(limit),
+            Self::RateLimited(_) => Self::QuotaExceeded(limit),
+            Self::QuotaExceeded(_) => Self::Validation(String::from("page size has to be between 1 and 200.")),
+            Self::Validation(_) => Self::RangeNotSatisfiable(1024),
+            Self::RangeNotSatisfiable(_) => Self::UploadSessionNotFound,
+            Self::UploadSessionNotFound => Self::OffsetMismatch,
+            Self::OffsetMismatch => Self::InvalidChunkContentType,
+            Self::InvalidChunkContentType => Self::TusVersionMismatch,
+            Self::TusVersionMismatch => Self::Other(anyhow::anyhow!("example")),
+            // crypto errors look the same as any other
+            Self::Other(_) | Self::Crypto(_) => return None,
+        };
+        Some(next)
+    }
+}
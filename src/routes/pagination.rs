@@ -0,0 +1,58 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::errors::{AppError, AppResult};
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 200;
+
+/// Page requested with `page` (starting at 1) and `per_page` query parameters.
+#[derive(Debug, Clone, Copy)]
+pub struct Page {
+    number: u32,
+    size: u32,
+}
+
+impl Page {
+    pub fn new(number: Option<u32>, size: Option<u32>) -> AppResult<Self> {
+        let number = number.unwrap_or(1);
+        let size = size.unwrap_or(DEFAULT_PAGE_SIZE);
+
+        if number == 0 {
+            return Err(AppError::Validation(String::from("pages start at 1.")));
+        }
+        if !(1..=MAX_PAGE_SIZE).contains(&size) {
+            return Err(AppError::Validation(format!("page size has to be between 1 and {MAX_PAGE_SIZE}.")));
+        }
+
+        Ok(Self { number, size })
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.size
+    }
+
+    pub fn offset(&self) -> u32 {
+        (self.number - 1).saturating_mul(self.size)
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Paginated<T> {
+    items: Vec<T>,
+    page: u32,
+    per_page: u32,
+    total: i64,
+}
+
+impl<T> Paginated<T> {
+    pub fn new(items: Vec<T>, page: Page, total: i64) -> Self {
+        Self {
+            items,
+            page: page.number,
+            per_page: page.size,
+            total,
+        }
+    }
+}
@@ -1,48 +1,263 @@
-use axum::{body::Body, http::header::{CONTENT_DISPOSITION, CONTENT_TYPE}, response::IntoResponse, Extension};
-use infer::MatcherType;
-use tokio::fs::File;
-use tokio_util::io::ReaderStream;
+use std::path::Path;
 
-use crate::{errors::{AppError, AppResult}, extractors, repository::fetch_upload, AppContext};
+use axum::{http::{header::{ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_SECURITY_POLICY, CONTENT_TYPE, X_CONTENT_TYPE_OPTIONS}, HeaderMap, HeaderName, HeaderValue}, response::{IntoResponse, Response}, Extension};
+use futures::TryStreamExt;
+use mime::Mime;
+use serde::Deserialize;
+use utoipa::IntoParams;
 
+use crate::{content_type::{self, SNIFF_BYTES}, crypto::Secret, errors::{AppError, AppResult, ErrorResponse}, extractors, models::Upload, ranges::{self, requested_ranges, RangeSource, Validators}, repository::fetch_upload, text_preview, thumbnails::{thumbnail, Fit, ThumbnailFormat, Variant}, AppContext};
+
+use super::download::{check_expiry, record_download, upload_source};
+
+// text that isn't text/* but is still worth reading
+const TEXT_SUBTYPES: &[&str] = &["json", "xml", "javascript", "x-sh", "toml", "yaml", "x-yaml", "sql"];
+
+/// Key of an encrypted upload, so it doesn't have to be in the url.
+pub const DECRYPTION_KEY: HeaderName = HeaderName::from_static("decryption-key");
+
+#[utoipa::path(
+    get,
+    path = "/preview/{upload_id}",
+    tag = "uploads",
+    params(
+        ("upload_id" = String, Path, description = "Id of an image, video or text upload, encrypted ones need their key or password."),
+        PreviewQuery,
+        ("Decryption-Key" = Option<String>, Header, description = "Key of an encrypted upload, instead of the `key` parameter."),
+        ("Range" = Option<String>, Header, description = "Bytes to send, so videos can be seeked. \
+            Ignored for uploads that expire by downloads, every preview of them counts as a download."),
+    ),
+    responses(
+        (status = 200, description = "Image or video, or a thumbnail of the image when it's resized or too big to preview. \
+            Text is cut off after `text_preview_bytes` and sent as `text/plain`, or as `text/html` when it's highlighted.", content_type = "application/octet-stream"),
+        (status = 206, description = "Requested ranges of the media.", content_type = "application/octet-stream"),
+        (status = 400, description = "`upload-not-found`, `media-too-big`, `missing-key`, `missing-password`, `invalid-decryption-key`, `invalid-password`, `key-not-accepted`, `corrupted-upload` or `validation`.", body = ErrorResponse),
+        (status = 404, description = "`upload-expired`.", body = ErrorResponse),
+        (status = 415, description = "`preview-not-supported`.", body = ErrorResponse),
+        (status = 416, description = "`range-not-satisfiable`.", body = ErrorResponse),
+        (status = 429, description = "`rate-limited`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+)]
 pub async fn preview_endpoint(
     ctx: Extension<AppContext>,
     extractors::Path(upload_id): extractors::Path<String>,
-) -> AppResult<impl IntoResponse> {
+    extractors::Query(query): extractors::Query<PreviewQuery>,
+    headers: HeaderMap,
+) -> AppResult<Response> {
     let upload = fetch_upload(&ctx.db, &upload_id)
         .await?
         .ok_or(AppError::UploadNotFound)?;
 
-    if let Some(_) = upload.nonce {
+    // only the client can decrypt these
+    if upload.e2e_version.is_some() {
         return Err(AppError::PreviewNotSupported);
     }
+    check_expiry(&ctx, &upload).await?;
 
-    let file_path = format!("{}{upload_id}", ctx.cfg.general.storage_dir);
-    let kind = infer::get_from_path(&file_path)
-        .map_err(|why| {
-            tracing::error!("Failed to infer file type from {file_path}: {why:?}");
-            AppError::PreviewNotSupported
-        })?
-        .ok_or(AppError::PreviewNotSupported)?;
+    // previews show the content as well, so for uploads that expire by downloads every one of them is a download
+    let response = preview(&ctx, &upload, &query, &headers).await?;
+    Ok(match upload.expiry_downloads {
+        Some(_) => record_download(&ctx, &upload, response).await,
+        None => response,
+    })
+}
 
-    if kind.matcher_type() != MatcherType::Image && kind.matcher_type() != MatcherType::Video {
-        return Err(AppError::PreviewNotSupported);
+async fn preview(ctx: &AppContext, upload: &Upload, query: &PreviewQuery, headers: &HeaderMap) -> AppResult<Response> {
+    // key is checked the same way as for downloads, encrypted uploads are decrypted as they're read
+    let (source, size) = upload_source(ctx, upload, query.secret(headers)).await?;
+
+    let content_type = match &upload.content_type {
+        Some(content_type) => content_type.clone(),
+        None => {
+            // uploads from before types were stored are recognized the same way new ones are
+            let head = source
+                .range(0..size.min(SNIFF_BYTES as u64))
+                .await?
+                .map_ok(|chunk| chunk.to_vec())
+                .try_concat()
+                .await
+                .map_err(|why| {
+                    tracing::error!("Failed to read head of {} to infer file type: {why:?}", upload.id);
+                    AppError::PreviewNotSupported
+                })?;
+            content_type::detect(&head, None, &upload.file_name).ok_or(AppError::PreviewNotSupported)?
+        }
+    };
+
+    let kind = PreviewKind::of(&content_type).ok_or(AppError::PreviewNotSupported)?;
+    if kind == PreviewKind::Text && !query.is_resized() {
+        return preview_text(ctx, upload, source.as_ref(), size, query.highlight).await;
     }
 
-    if upload.bytes > ctx.cfg.general.max_preview_bytes as i64 {
+    let too_big = upload.bytes > ctx.cfg.general.max_preview_bytes as i64;
+    if kind == PreviewKind::Image && (query.is_resized() || too_big) {
+        return preview_thumbnail(ctx, upload, source.as_ref(), size, query).await;
+    }
+    if query.is_resized() {
+        // only images can be resized
+        return Err(AppError::PreviewNotSupported);
+    }
+    if too_big {
         return Err(AppError::MediaTooBig);
     }
 
-    let file = File::open(file_path).await?;
-
-    let stream = ReaderStream::new(file);
-    let body = Body::from_stream(stream);
+    // videos are usually played by seeking around, unless that would get around the download limit
+    let validators = Validators::of(upload);
+    let limited = upload.expiry_downloads.is_some();
+    let ranges = if limited {
+        Vec::new()
+    } else {
+        requested_ranges(headers, size, &validators)?
+    };
+    let mut response = ranges::respond(source, size, ranges, &validators, Some(&content_type)).await?;
+    if limited {
+        response.headers_mut().insert(ACCEPT_RANGES, HeaderValue::from_static("none"));
+    }
 
     Ok((
         [
-            (CONTENT_TYPE, kind.mime_type().to_string()),
             (CONTENT_DISPOSITION, format!(r#"attachment; filename="{}""#, upload.file_name)),
+            // declared types aren't verified, so browsers shouldn't guess a different one
+            (X_CONTENT_TYPE_OPTIONS, String::from("nosniff")),
+        ],
+        response,
+    )
+        .into_response())
+}
+
+/// What a preview of an upload shows it as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewKind {
+    Image,
+    Video,
+    Text,
+}
+
+impl PreviewKind {
+    pub fn of(content_type: &str) -> Option<Self> {
+        let mime = content_type.parse::<Mime>().ok()?;
+        let text_like = TEXT_SUBTYPES.contains(&mime.subtype().as_str())
+            || mime.suffix().is_some_and(|suffix| suffix == mime::JSON || suffix == mime::XML);
+
+        match mime.type_() {
+            // svg can carry scripts, so it's only shown as its source
+            mime::IMAGE if mime.subtype() == mime::SVG => Some(Self::Text),
+            mime::IMAGE => Some(Self::Image),
+            mime::VIDEO => Some(Self::Video),
+            mime::TEXT => Some(Self::Text),
+            mime::APPLICATION if text_like => Some(Self::Text),
+            _ => None,
+        }
+    }
+}
+
+async fn preview_thumbnail(
+    ctx: &AppContext,
+    upload: &Upload,
+    source: &dyn RangeSource,
+    size: u64,
+    query: &PreviewQuery,
+) -> AppResult<Response> {
+    let format = query.format.unwrap_or_default();
+    let variant = Variant::new(query.w, query.h, query.fit.unwrap_or_default(), format);
+    let body = thumbnail(ctx, upload, source, size, variant).await?;
+
+    let stem = Path::new(&upload.file_name)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("thumbnail");
+    let file_name = format!("{stem}.{}", format.extension());
+
+    Ok((
+        [
+            (CONTENT_TYPE, format.mime_type().to_string()),
+            (CONTENT_DISPOSITION, format!(r#"attachment; filename="{file_name}""#)),
         ],
         body,
-    ))
+    )
+        .into_response())
+}
+
+async fn preview_text(
+    ctx: &AppContext,
+    upload: &Upload,
+    source: &dyn RangeSource,
+    size: u64,
+    highlight: bool,
+) -> AppResult<Response> {
+    let len = size.min(ctx.cfg.general.text_preview_bytes);
+    let bytes = source
+        .range(0..len)
+        .await?
+        .map_ok(|chunk| chunk.to_vec())
+        .try_concat()
+        .await?;
+    // declared type could be wrong about it
+    if !text_preview::is_text(&bytes) {
+        return Err(AppError::PreviewNotSupported);
+    }
+    let text = text_preview::utf8_prefix(&bytes).to_string();
+    let disposition = format!(r#"attachment; filename="{}""#, upload.file_name);
+
+    if !highlight {
+        return Ok((
+            [(CONTENT_TYPE, String::from("text/plain; charset=utf-8")), (CONTENT_DISPOSITION, disposition)],
+            text,
+        )
+            .into_response());
+    }
+
+    let file_name = upload.file_name.clone();
+    let html = tokio::task::spawn_blocking(move || text_preview::highlight(&text, &file_name))
+        .await
+        .map_err(|why| anyhow::anyhow!(why))?
+        .map_err(|why| anyhow::anyhow!(why))?;
+
+    Ok((
+        [
+            (CONTENT_TYPE, String::from("text/html; charset=utf-8")),
+            (CONTENT_DISPOSITION, disposition),
+            // markup only has inline styles, anything else in it isn't ours
+            (CONTENT_SECURITY_POLICY, String::from("default-src 'none'; style-src 'unsafe-inline'")),
+        ],
+        html,
+    )
+        .into_response())
+}
+
+/// Any of `w`, `h`, `fit` or `format` turns the preview of an image into a thumbnail,
+/// sizes are rounded up to 64, 128, 256, 512, 1024 or 2048.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PreviewQuery {
+    /// Width of the thumbnail.
+    w: Option<u32>,
+    /// Height of the thumbnail.
+    h: Option<u32>,
+    /// How the image is fitted into the size, defaults to `contain`.
+    fit: Option<Fit>,
+    /// Defaults to `webp`.
+    format: Option<ThumbnailFormat>,
+    /// Text is sent as HTML highlighted by the syntax that goes with the file's extension.
+    #[serde(default)]
+    highlight: bool,
+    /// Key of an encrypted upload.
+    key: Option<String>,
+    /// Password of an encrypted upload.
+    password: Option<String>,
+}
+
+impl PreviewQuery {
+    fn secret<'a>(&'a self, headers: &'a HeaderMap) -> Secret<'a> {
+        let header = headers.get(DECRYPTION_KEY).and_then(|key| key.to_str().ok());
+        Secret {
+            key: self.key.as_deref().or(header),
+            password: self.password.as_deref(),
+        }
+    }
+
+    fn is_resized(&self) -> bool {
+        self.w.is_some() || self.h.is_some() || self.fit.is_some() || self.format.is_some()
+    }
 }
@@ -1,22 +1,158 @@
 use axum::{Extension, Json};
-use serde::Serialize;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
-use crate::{errors::AppResult, AppContext};
+use crate::{
+    errors::{AppError, AppResult, ErrorResponse},
+    extractors,
+    models::UsageBucket,
+    repository::fetch_usage_stats,
+    AppContext,
+};
 
-pub async fn service_stats(ctx: Extension<AppContext>) -> AppResult<Json<Stats>> {
+// keeps hourly stats of a long range from being a huge response
+const MAX_BUCKETS: i64 = 1000;
+// how many buckets there are when only one end of the range is given
+const DEFAULT_BUCKETS: i32 = 30;
+
+#[utoipa::path(
+    get,
+    path = "/stats",
+    tag = "service",
+    params(StatsQuery),
+    responses(
+        (status = 200, body = Stats),
+        (status = 400, description = "`validation`.", body = ErrorResponse),
+        (status = 429, description = "`rate-limited`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+)]
+pub async fn service_stats(
+    ctx: Extension<AppContext>,
+    extractors::Query(query): extractors::Query<StatsQuery>,
+) -> AppResult<Json<Stats>> {
     let row = sqlx::query!("SELECT * FROM stats WHERE id = 1")
         .fetch_one(&ctx.db)
         .await?;
+    let stored_bytes = ctx.usage.stored_bytes(&ctx.db).await?;
+
+    // series are only there when they're asked for, so responses of older clients stay the same
+    let series = if query.is_empty() {
+        None
+    } else {
+        Some(usage_series(&ctx, &query).await?)
+    };
 
     Ok(Json(Stats {
         uploads: row.files_uploaded as u32,
         bytes: row.bytes_uploaded as u64,
+        downloads: row.files_downloaded as u64,
+        bytes_downloaded: row.bytes_downloaded as u64,
+        stored_bytes,
+        storage_budget_bytes: ctx.cfg.general.storage_budget_bytes,
+        series,
     }))
 }
 
-#[derive(Default, Serialize)]
+async fn usage_series(ctx: &AppContext, query: &StatsQuery) -> AppResult<Vec<UsageBucketResponse>> {
+    let granularity = query.granularity.unwrap_or(Granularity::Day);
+    let step = granularity.step();
+    let to = query.to.unwrap_or_else(Utc::now);
+    let from = query.from.unwrap_or(to - step * DEFAULT_BUCKETS);
+
+    if from > to {
+        return Err(AppError::Validation(String::from("from has to be before to.")));
+    }
+    if (to - from).num_seconds() / step.num_seconds() >= MAX_BUCKETS {
+        return Err(AppError::Validation(format!("range can have at most {MAX_BUCKETS} buckets, try a larger granularity.")));
+    }
+
+    let buckets = fetch_usage_stats(&ctx.db, granularity.as_str(), from, to).await?;
+    Ok(buckets.into_iter().map(UsageBucketResponse::from).collect())
+}
+
+/// Any of these adds `series` to the response.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatsQuery {
+    /// Start of the series, defaults to 30 buckets before its end.
+    from: Option<DateTime<Utc>>,
+    /// End of the series, defaults to now.
+    to: Option<DateTime<Utc>>,
+    /// Size of the buckets, defaults to a day.
+    granularity: Option<Granularity>,
+}
+
+impl StatsQuery {
+    fn is_empty(&self) -> bool {
+        self.from.is_none() && self.to.is_none() && self.granularity.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Hour,
+    Day,
+}
+
+impl Granularity {
+    fn step(self) -> Duration {
+        match self {
+            Self::Hour => Duration::hours(1),
+            Self::Day => Duration::days(1),
+        }
+    }
+
+    /// Field name for `date_trunc`.
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+        }
+    }
+}
+
+#[derive(Default, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Stats {
     uploads: u32,
     bytes: u64,
+    downloads: u64,
+    bytes_downloaded: u64,
+    stored_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    storage_budget_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    series: Option<Vec<UsageBucketResponse>>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageBucketResponse {
+    start: DateTime<Utc>,
+    uploads: u64,
+    bytes_in: u64,
+    /// Uploads that were encrypted, either by us or by the client.
+    encrypted_uploads: u64,
+    downloads: u64,
+    bytes_out: u64,
+    expired: u64,
+    blacklisted: u64,
+}
+
+impl From<UsageBucket> for UsageBucketResponse {
+    fn from(bucket: UsageBucket) -> Self {
+        Self {
+            start: bucket.start,
+            uploads: bucket.uploads as u64,
+            bytes_in: bucket.bytes_in as u64,
+            encrypted_uploads: bucket.encrypted_uploads as u64,
+            downloads: bucket.downloads as u64,
+            bytes_out: bucket.bytes_out as u64,
+            expired: bucket.expired as u64,
+            blacklisted: bucket.blacklisted as u64,
+        }
+    }
 }
@@ -0,0 +1,683 @@
+//! Resumable uploads following tus 1.0.0 core protocol with creation and termination extensions.
+//!
+//! Every `PATCH` is stored as a separate part in storage, so sessions can be resumed on any
+//! instance. Encrypted sessions only keep a hash of their key, the client sends the key with every
+//! `PATCH`. Chunks are encrypted as they arrive, and plaintext shorter than one chunk that's left
+//! over between requests is kept in the database sealed with the same key.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{
+        header::{CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE, LOCATION},
+        HeaderMap, HeaderName, HeaderValue, Method, StatusCode,
+    },
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{head, post},
+    Extension, Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use chacha20poly1305::{
+    aead::{
+        stream::{NewStream, StreamBE32, StreamPrimitive},
+        Aead, AeadCore, KeyInit, OsRng,
+    },
+    XChaCha20Poly1305, XNonce,
+};
+use chrono::{DateTime, Duration, Utc};
+use futures::{stream, StreamExt, TryStreamExt};
+use sha2::{Digest, Sha256};
+use tokio::io::{self, AsyncRead, AsyncWriteExt};
+use tokio_util::io::StreamReader;
+
+use crate::{
+    accounts::MaybeAccount,
+    capacity,
+    content_type::{self, SNIFF_BYTES},
+    crypto::{generate_key, generate_nonce, unlock, Secret},
+    errors::{AppError, AppResult, ErrorResponse},
+    extractors,
+    metrics::{self, CryptoTimer, InFlightUpload},
+    models::UploadSession,
+    ratelimit::{check_quota, rate_limit, record_usage, Client, Group},
+    repository::{
+        acquire_blob, advance_upload_session, delete_upload_session, fetch_upload_session, insert_upload,
+        insert_upload_session, set_upload_session_content_type, update_stats, InsertUpload,
+        InsertUploadSession,
+    },
+    storage::{ByteStream, Storage},
+    utilities::{friendly_id, read_chunk, temp_file, ENC_CHUNK_SIZE},
+    AppContext,
+};
+
+use super::{
+    preview::DECRYPTION_KEY,
+    upload::{check_file_name, max_upload_bytes, UploadQuery, UploadResponse},
+};
+
+pub const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination";
+const CHUNK_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+pub const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+pub const TUS_VERSION_HEADER: HeaderName = HeaderName::from_static("tus-version");
+pub const TUS_EXTENSION: HeaderName = HeaderName::from_static("tus-extension");
+pub const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+pub const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+pub const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+pub const UPLOAD_EXPIRES: HeaderName = HeaderName::from_static("upload-expires");
+
+pub fn router() -> Router {
+    Router::new()
+        .route(
+            "/",
+            post(create_endpoint)
+                .layer(middleware::from_fn_with_state(Group::Upload, rate_limit))
+                .options(options_endpoint),
+        )
+        .route(
+            "/:session_id",
+            head(offset_endpoint)
+                .patch(patch_endpoint)
+                .delete(terminate_endpoint)
+                .layer(middleware::from_fn_with_state(Group::Upload, rate_limit)),
+        )
+        .layer(middleware::from_fn(tus_resumable))
+}
+
+/// Rejects requests made with other protocol versions and marks every response with the one we speak.
+async fn tus_resumable(req: Request, next: Next) -> Response {
+    let supported = req.method() == Method::OPTIONS
+        || req.headers().get(TUS_RESUMABLE).is_some_and(|v| v == TUS_VERSION);
+
+    let mut res = if supported {
+        next.run(req).await
+    } else {
+        AppError::TusVersionMismatch.into_response()
+    };
+
+    if res.status() == StatusCode::PRECONDITION_FAILED {
+        res.headers_mut()
+            .insert(TUS_VERSION_HEADER, HeaderValue::from_static(TUS_VERSION));
+    }
+    res.headers_mut()
+        .insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+    res
+}
+
+#[utoipa::path(
+    options,
+    path = "/tus",
+    tag = "tus",
+    responses((
+        status = 204,
+        description = "Supported protocol versions and extensions.",
+        headers(("Tus-Version" = String), ("Tus-Extension" = String)),
+    )),
+)]
+async fn options_endpoint() -> impl IntoResponse {
+    (
+        StatusCode::NO_CONTENT,
+        [
+            (TUS_VERSION_HEADER, TUS_VERSION),
+            (TUS_EXTENSION, TUS_EXTENSIONS),
+        ],
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/tus",
+    tag = "tus",
+    params(
+        UploadQuery,
+        ("Tus-Resumable" = String, Header, description = "Has to be `1.0.0`."),
+        ("Upload-Length" = u64, Header, description = "Size of the whole file."),
+        ("Upload-Metadata" = Option<String>, Header, description = "Comma separated keys with base64 values, `filename` names the file."),
+    ),
+    responses(
+        (
+            status = 201,
+            description = "Session was created, its id is the id of the upload once it's finished.",
+            body = UploadResponse,
+            headers(("Location" = String, description = "Where chunks of the upload are sent to.")),
+        ),
+        (status = 400, description = "`both-expirations`, `invalid-file-name`, `file-blacklist` or `validation`.", body = ErrorResponse),
+        (status = 401, description = "`invalid-api-key`.", body = ErrorResponse),
+        (status = 412, description = "`tus-version-mismatch`.", body = ErrorResponse),
+        (status = 413, description = "`file-too-large`.", body = ErrorResponse),
+        (status = 429, description = "`rate-limited` or `quota-exceeded`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+        (status = 507, description = "`storage-full`.", body = ErrorResponse),
+    ),
+    security((), ("api_key" = [])),
+)]
+#[tracing::instrument(skip(headers))]
+async fn create_endpoint(
+    ctx: Extension<AppContext>,
+    client: Client,
+    MaybeAccount(account): MaybeAccount,
+    extractors::Query(mut query): extractors::Query<UploadQuery>,
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    if query.expiry_downloads.is_some() && query.expiry_hours.is_some() {
+        return Err(AppError::BothExpirations);
+    }
+
+    if query.e2e {
+        return Err(AppError::Validation(String::from("end-to-end encrypted uploads can't be resumable yet.")));
+    }
+
+    let upload_length = header_u64(&headers, &UPLOAD_LENGTH)?.ok_or_else(|| {
+        AppError::Validation(String::from("Upload-Length header is required, deferred length is not supported."))
+    })?;
+    let mut max_bytes = max_upload_bytes(&ctx.cfg.general, account.as_ref());
+    if let Some(max_file_bytes) = account.as_ref().and_then(|account| account.max_file_bytes) {
+        max_bytes = max_bytes.min(max_file_bytes as u64);
+    }
+    if upload_length > max_bytes {
+        return Err(AppError::FileTooLarge(max_bytes));
+    }
+    check_quota(&ctx, &client, upload_length).await?;
+    // sessions count in storage usage with their whole length, so it's only checked once
+    capacity::check(capacity::available(&ctx).await?, upload_length)?;
+    query.apply_account_defaults(account.as_ref());
+
+    let metadata = headers
+        .get(UPLOAD_METADATA)
+        .map(parse_metadata)
+        .transpose()?
+        .unwrap_or_default();
+    let file_name = metadata
+        .get("filename")
+        .or_else(|| metadata.get("name"))
+        .ok_or(AppError::InvalidFileName)?
+        .clone();
+    check_file_name(&file_name)?;
+    // refined once the first chunk shows what the file actually is
+    let content_type = content_type::declared_or_guessed(metadata.get("filetype").map(String::as_str), &file_name);
+
+    let id = friendly_id(8);
+    let delete_key = friendly_id(21);
+    // key is only returned, the client has to send it with every chunk
+    let key = query.encrypt.then(generate_key);
+    let nonce_hex = query.encrypt.then(|| hex::encode(generate_nonce()));
+
+    insert_upload_session(
+        &ctx.db,
+        InsertUploadSession {
+            id: id.clone(),
+            delete_key: delete_key.clone(),
+            key_hash: key.map(|key| sha256::digest(hex::encode(key))),
+            nonce: nonce_hex,
+            file_name,
+            upload_length,
+            expiry_hours: query.expiry_hours,
+            expiry_downloads: query.expiry_downloads,
+            embedded: query.embedded,
+            content_type,
+            owner_id: account.map(|account| account.id),
+        },
+    )
+    .await?;
+    // whole length counts once the session starts, so parallel sessions can't get around the quota
+    record_usage(&ctx, &client, upload_length).await;
+
+    let session = fetch_upload_session(&ctx.db, &id)
+        .await?
+        .ok_or(AppError::UploadSessionNotFound)?;
+    let expires = session_expiry(&ctx, &session);
+
+    // there won't be any PATCH for empty files
+    if upload_length == 0 {
+        finish_session(&ctx, session, key.as_ref(), &CryptoTimer::encryption()).await?;
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        [
+            (LOCATION, format!("/tus/{id}")),
+            (UPLOAD_EXPIRES, expires),
+        ],
+        Json(UploadResponse {
+            id,
+            decryption_key: key.map(hex::encode),
+            delete_key,
+            files: Vec::new(),
+        }),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    head,
+    path = "/tus/{session_id}",
+    tag = "tus",
+    params(("session_id" = String, Path), ("Tus-Resumable" = String, Header, description = "Has to be `1.0.0`.")),
+    responses(
+        (
+            status = 200,
+            description = "How much of the file was received.",
+            headers(("Upload-Offset" = u64), ("Upload-Length" = u64), ("Upload-Expires" = String)),
+        ),
+        (status = 404, description = "`upload-session-not-found`.", body = ErrorResponse),
+        (status = 412, description = "`tus-version-mismatch`.", body = ErrorResponse),
+        (status = 429, description = "`rate-limited`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+)]
+async fn offset_endpoint(
+    ctx: Extension<AppContext>,
+    extractors::Path(session_id): extractors::Path<String>,
+) -> AppResult<Response> {
+    let session = fetch_upload_session(&ctx.db, &session_id)
+        .await?
+        .ok_or(AppError::UploadSessionNotFound)?;
+
+    Ok((
+        [
+            (UPLOAD_OFFSET, session.upload_offset.to_string()),
+            (UPLOAD_LENGTH, session.upload_length.to_string()),
+            (UPLOAD_EXPIRES, session_expiry(&ctx, &session)),
+            (CACHE_CONTROL, String::from("no-store")),
+        ],
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    patch,
+    path = "/tus/{session_id}",
+    tag = "tus",
+    params(
+        ("session_id" = String, Path),
+        ("Tus-Resumable" = String, Header, description = "Has to be `1.0.0`."),
+        ("Upload-Offset" = u64, Header, description = "Offset the chunk starts at, has to match the one of the session."),
+        ("Decryption-Key" = Option<String>, Header, description = "Key that was returned when an encrypted session was created, it's required by every chunk of it."),
+    ),
+    request_body(content = Vec<u8>, content_type = "application/offset+octet-stream"),
+    responses(
+        (status = 204, description = "Chunk was stored, the upload is finished once it reaches its length.", headers(("Upload-Offset" = u64))),
+        (status = 400, description = "`missing-key`, `invalid-decryption-key`, `corrupted-upload`, `file-blacklist` or `validation`.", body = ErrorResponse),
+        (status = 404, description = "`upload-session-not-found`.", body = ErrorResponse),
+        (status = 409, description = "`offset-mismatch`.", body = ErrorResponse),
+        (status = 412, description = "`tus-version-mismatch`.", body = ErrorResponse),
+        (status = 415, description = "`invalid-chunk-content-type`.", body = ErrorResponse),
+        (status = 429, description = "`rate-limited`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+        (status = 507, description = "`storage-full`.", body = ErrorResponse),
+    ),
+)]
+#[tracing::instrument(skip(headers, body))]
+async fn patch_endpoint(
+    ctx: Extension<AppContext>,
+    extractors::Path(session_id): extractors::Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> AppResult<Response> {
+    if headers.get(CONTENT_TYPE).is_none_or(|v| v != CHUNK_CONTENT_TYPE) {
+        return Err(AppError::InvalidChunkContentType);
+    }
+
+    let offset = header_u64(&headers, &UPLOAD_OFFSET)?
+        .ok_or_else(|| AppError::Validation(String::from("Upload-Offset header is required.")))?;
+
+    let session = fetch_upload_session(&ctx.db, &session_id)
+        .await?
+        .ok_or(AppError::UploadSessionNotFound)?;
+
+    if offset != session.upload_offset as u64 {
+        return Err(AppError::OffsetMismatch);
+    }
+
+    let key = match &session.key_hash {
+        Some(key_hash) => {
+            let key = headers.get(DECRYPTION_KEY).and_then(|key| key.to_str().ok());
+            Some(unlock(key_hash, None, Secret { key, password: None }).await?)
+        }
+        None => None,
+    };
+
+    let _in_flight = InFlightUpload::start();
+    let remaining = session.upload_length as u64 - offset;
+    // disk could've filled up since the session was created
+    let chunk_length = header_u64(&headers, &CONTENT_LENGTH)?.unwrap_or(remaining).min(remaining);
+    capacity::check(capacity::free_space(&ctx)?, chunk_length)?;
+
+    let received = Arc::new(AtomicU64::new(0));
+    let body = limited(body, remaining, received.clone());
+
+    // first chunk is where the file's type can be recognized, while it's still plaintext
+    let head = Arc::new(Mutex::new(Vec::new()));
+    let body = if offset == 0 {
+        sniffed(body, head.clone())
+    } else {
+        body
+    };
+
+    let part = format!("{session_id}-{}", friendly_id(8));
+    let tail = Arc::new(Mutex::new(Vec::new()));
+    // last chunk is encrypted when the session is finished, which is still part of this request
+    let timer = CryptoTimer::encryption();
+
+    let stream = match (&key, &session.nonce) {
+        (Some(key), Some(nonce)) => {
+            let leftover = open_tail(key, &session.tail)?;
+            let position = ((offset - leftover.len() as u64) / ENC_CHUNK_SIZE as u64) as u32;
+            let buffered = stream::iter([Ok(Bytes::from(leftover))]).chain(body);
+            encrypt_chunks(StreamReader::new(buffered), stream_primitive(key, nonce)?, position, tail.clone(), timer.clone())
+        }
+        _ => body,
+    };
+
+    let written = match ctx.storage.put(&part, stream).await {
+        Ok(written) => written,
+        Err(why) => {
+            // nothing is committed, so client can retry from the same offset
+            remove_blobs(ctx.storage.as_ref(), [part.as_str()]).await;
+            return Err(match why.kind() {
+                io::ErrorKind::InvalidInput => AppError::Validation(why.to_string()),
+                _ => why.into(),
+            });
+        }
+    };
+
+    let new_offset = offset + received.load(Ordering::SeqCst);
+    let tail = std::mem::take(&mut *tail.lock().unwrap());
+    let tail = match &key {
+        Some(key) => seal_tail(key, &tail)?,
+        None => tail,
+    };
+    let committed_part = (written > 0).then_some(part.as_str());
+
+    if !advance_upload_session(&ctx.db, &session_id, offset, new_offset, committed_part, &tail).await? {
+        remove_blobs(ctx.storage.as_ref(), [part.as_str()]).await;
+        return Err(AppError::OffsetMismatch);
+    }
+
+    if committed_part.is_none() {
+        remove_blobs(ctx.storage.as_ref(), [part.as_str()]).await;
+    }
+
+    if offset == 0 {
+        let head = std::mem::take(&mut *head.lock().unwrap());
+        let detected = content_type::detect(&head, session.content_type.as_deref(), &session.file_name);
+        if let Some(content_type) = detected.filter(|detected| session.content_type.as_ref() != Some(detected)) {
+            set_upload_session_content_type(&ctx.db, &session_id, &content_type).await?;
+        }
+    }
+
+    if new_offset == session.upload_length as u64 {
+        let session = fetch_upload_session(&ctx.db, &session_id)
+            .await?
+            .ok_or(AppError::UploadSessionNotFound)?;
+        finish_session(&ctx, session, key.as_ref(), &timer).await?;
+    }
+
+    Ok((StatusCode::NO_CONTENT, [(UPLOAD_OFFSET, new_offset.to_string())]).into_response())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/tus/{session_id}",
+    tag = "tus",
+    params(("session_id" = String, Path), ("Tus-Resumable" = String, Header, description = "Has to be `1.0.0`.")),
+    responses(
+        (status = 204, description = "Session and its chunks are removed."),
+        (status = 404, description = "`upload-session-not-found`.", body = ErrorResponse),
+        (status = 412, description = "`tus-version-mismatch`.", body = ErrorResponse),
+        (status = 429, description = "`rate-limited`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+)]
+async fn terminate_endpoint(
+    ctx: Extension<AppContext>,
+    extractors::Path(session_id): extractors::Path<String>,
+) -> AppResult<StatusCode> {
+    let session = delete_upload_session(&ctx.db, &session_id)
+        .await?
+        .ok_or(AppError::UploadSessionNotFound)?;
+
+    remove_blobs(ctx.storage.as_ref(), session.parts.iter().map(String::as_str)).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Joins all parts into a regular upload and removes the session, `key` is the one of an encrypted session.
+async fn finish_session(ctx: &AppContext, session: UploadSession, key: Option<&[u8; 32]>, timer: &CryptoTimer) -> AppResult<()> {
+    let storage = ctx.storage.clone();
+    let parts = stream::iter(session.parts.clone())
+        .then(move |part| {
+            let storage = storage.clone();
+            async move { storage.get(&part).await }
+        })
+        .try_flatten();
+
+    let last = match (key, &session.nonce) {
+        (Some(key), Some(nonce)) => {
+            let leftover = open_tail(key, &session.tail)?;
+            let position = ((session.upload_length as u64 - leftover.len() as u64) / ENC_CHUNK_SIZE as u64) as u32;
+            let primitive = stream_primitive(key, nonce)?;
+            let ciphertext = timer.time(leftover.len(), || primitive.encrypt(position, true, leftover.as_slice()))?;
+            Bytes::from(ciphertext)
+        }
+        _ => Bytes::new(),
+    };
+
+    let hasher = Arc::new(Mutex::new(Sha256::new()));
+    let hashing = hasher.clone();
+    let stream: ByteStream = Box::pin(
+        parts
+            .chain(stream::once(async { Ok(last) }))
+            .inspect_ok(move |chunk| hashing.lock().unwrap().update(chunk)),
+    );
+
+    // plain content is staged in temp dir, so identical files can be stored only once under their hash
+    let staged = match &session.nonce {
+        Some(_) => {
+            ctx.storage.put(&session.id, stream).await?;
+            None
+        }
+        None => {
+            let (mut file, file_path) = temp_file(&ctx.cfg.general.temp_dir).await?;
+            io::copy(&mut StreamReader::new(stream), &mut file).await?;
+            file.flush().await?;
+            Some(file_path)
+        }
+    };
+
+    // session is gone once it's claimed, so it can't be finished twice
+    let Some(session) = delete_upload_session(&ctx.db, &session.id).await? else {
+        return Err(AppError::UploadSessionNotFound);
+    };
+    remove_blobs(ctx.storage.as_ref(), session.parts.iter().map(String::as_str)).await;
+
+    let hash = hex::encode(hasher.lock().unwrap().clone().finalize());
+    if let Err(why) = ctx.blacklist.check(&ctx.db, &hash).await {
+        remove_blobs(ctx.storage.as_ref(), [session.id.as_str()]).await;
+        return Err(why);
+    }
+
+    let blob_hash = staged.as_ref().map(|_| hash);
+    let (bytes, encrypted) = (session.upload_length as u64, session.nonce.is_some());
+    let mut tx = ctx.db.begin().await?;
+    if let (Some(file_path), Some(hash)) = (&staged, &blob_hash) {
+        if acquire_blob(&mut tx, hash, session.upload_length as u64).await? {
+            ctx.storage.put_file(hash, file_path).await?;
+        }
+    }
+
+    insert_upload(
+        &mut *tx,
+        InsertUpload {
+            key_hash: session.key_hash,
+            id: session.id,
+            delete_key: session.delete_key,
+            nonce: session.nonce,
+            file_name: session.file_name,
+            bytes: session.upload_length as usize,
+            expiry_hours: session.expiry_hours.map(|n| n as u32),
+            expiry_downloads: session.expiry_downloads.map(|n| n as u32),
+            embedded: session.embedded,
+            kdf: None,
+            e2e_version: None,
+            e2e_chunk_size: None,
+            blob_hash,
+            owner_id: session.owner_id,
+            content_type: session.content_type,
+        },
+    )
+    .await?;
+    tx.commit().await?;
+
+    if let Err(why) = update_stats(&ctx.db, bytes, encrypted).await {
+        tracing::error!("failed to update stats: {why:?}");
+    }
+    metrics::uploaded(bytes);
+
+    Ok(())
+}
+
+/// Removes blobs, ignoring the ones that are already gone.
+pub async fn remove_blobs<'a>(storage: &dyn Storage, keys: impl IntoIterator<Item = &'a str>) {
+    for key in keys {
+        match storage.delete(key).await {
+            Ok(()) => (),
+            Err(why) if why.kind() == io::ErrorKind::NotFound => (),
+            Err(why) => tracing::warn!("failed to remove blob `{key}`: {why:?}"),
+        }
+    }
+}
+
+/// Passes the body through, counting bytes and failing once it's longer than `limit`.
+fn limited(body: Body, limit: u64, received: Arc<AtomicU64>) -> ByteStream {
+    let stream = body.into_data_stream().map(move |chunk| {
+        let chunk = chunk.map_err(io::Error::other)?;
+        let total = received.fetch_add(chunk.len() as u64, Ordering::SeqCst) + chunk.len() as u64;
+        if total > limit {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "chunk goes past declared Upload-Length.",
+            ));
+        }
+        Ok(chunk)
+    });
+    Box::pin(stream)
+}
+
+/// Keeps the first bytes that pass through the stream in `head`.
+fn sniffed(stream: ByteStream, head: Arc<Mutex<Vec<u8>>>) -> ByteStream {
+    let stream = stream.inspect_ok(move |chunk| {
+        let mut head = head.lock().unwrap();
+        let missing = SNIFF_BYTES.saturating_sub(head.len()).min(chunk.len());
+        head.extend_from_slice(&chunk[..missing]);
+    });
+    Box::pin(stream)
+}
+
+/// Encrypts every full chunk read from `reader`, leftover shorter than a chunk lands in `tail`.
+fn encrypt_chunks<R>(
+    reader: R,
+    primitive: StreamBE32<XChaCha20Poly1305>,
+    position: u32,
+    tail: Arc<Mutex<Vec<u8>>>,
+    timer: CryptoTimer,
+) -> ByteStream
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let primitive = Arc::new(primitive);
+    let chunks = stream::try_unfold((reader, position), move |(mut reader, position)| {
+        let primitive = primitive.clone();
+        let tail = tail.clone();
+        let timer = timer.clone();
+        async move {
+            let chunk = read_chunk(&mut reader, ENC_CHUNK_SIZE).await?;
+            if chunk.len() < ENC_CHUNK_SIZE {
+                *tail.lock().unwrap() = chunk;
+                return Ok(None);
+            }
+
+            let ciphertext = timer
+                .time(chunk.len(), || primitive.encrypt(position, false, chunk.as_slice()))
+                .map_err(|_| io::Error::other("failed to encrypt chunk"))?;
+            Ok(Some((Bytes::from(ciphertext), (reader, position + 1))))
+        }
+    });
+    Box::pin(chunks)
+}
+
+fn stream_primitive(key: &[u8; 32], nonce: &str) -> AppResult<StreamBE32<XChaCha20Poly1305>> {
+    let mut nonce_bytes = [0u8; 19];
+    hex::decode_to_slice(nonce, &mut nonce_bytes)?;
+
+    Ok(StreamBE32::new(key.into(), nonce_bytes.as_ref().into()))
+}
+
+/// Encrypts plaintext that's left over between chunks, it's stored behind its own random nonce.
+fn seal_tail(key: &[u8; 32], tail: &[u8]) -> AppResult<Vec<u8>> {
+    if tail.is_empty() {
+        return Ok(Vec::new());
+    }
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let mut sealed = nonce.to_vec();
+    sealed.extend(XChaCha20Poly1305::new(key.into()).encrypt(&nonce, tail)?);
+    Ok(sealed)
+}
+
+/// Plaintext sealed by [`seal_tail`].
+fn open_tail(key: &[u8; 32], sealed: &[u8]) -> AppResult<Vec<u8>> {
+    if sealed.is_empty() {
+        return Ok(Vec::new());
+    }
+    if sealed.len() < XNonce::default().len() {
+        return Err(AppError::CorruptedUpload);
+    }
+    let (nonce, ciphertext) = sealed.split_at(XNonce::default().len());
+    Ok(XChaCha20Poly1305::new(key.into()).decrypt(XNonce::from_slice(nonce), ciphertext)?)
+}
+
+fn session_expiry(ctx: &AppContext, session: &UploadSession) -> String {
+    let expires: DateTime<Utc> =
+        session.updated_at + Duration::hours(ctx.cfg.general.upload_session_ttl_hours as _);
+    expires.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn header_u64(headers: &HeaderMap, name: &HeaderName) -> AppResult<Option<u64>> {
+    headers
+        .get(name)
+        .map(|value| {
+            value
+                .to_str()
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| AppError::Validation(format!("{name} header must be a non-negative integer.")))
+        })
+        .transpose()
+}
+
+/// Parses `Upload-Metadata`, comma separated pairs of key and base64 encoded value.
+fn parse_metadata(value: &HeaderValue) -> AppResult<HashMap<String, String>> {
+    let invalid = || AppError::Validation(String::from("Upload-Metadata header is malformed."));
+    let value = value.to_str().map_err(|_| invalid())?;
+
+    let mut metadata = HashMap::new();
+    for pair in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once(' ').unwrap_or((pair, ""));
+        let value = STANDARD.decode(value.trim()).map_err(|_| invalid())?;
+        let value = String::from_utf8(value).map_err(|_| invalid())?;
+        metadata.insert(key.to_string(), value);
+    }
+
+    Ok(metadata)
+}
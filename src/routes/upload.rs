@@ -1,48 +1,48 @@
 use axum::{
     extract::{multipart::Field, Multipart},
+    http::{header::CONTENT_LENGTH, HeaderMap},
     Extension, Json,
 };
-use chacha20poly1305::{
-    aead::{rand_core::RngCore, stream::EncryptorBE32, OsRng},
-    XChaCha20Poly1305,
-};
+use chacha20poly1305::{aead::stream::EncryptorBE32, XChaCha20Poly1305};
 use futures::TryStreamExt;
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
-use tokio::{
-    fs::{self, File},
-    io::{self, AsyncRead, AsyncWrite, AsyncWriteExt},
-};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio_util::io::StreamReader;
+use utoipa::{IntoParams, ToSchema};
 
 use crate::{
-    errors::{AppError, AppResult}, extractors, repository::{insert_upload, update_stats, InsertUpload}, utilities::{friendly_id, read_chunk, ENC_CHUNK_SIZE}, AppContext
+    accounts::MaybeAccount, blacklist::Blacklist, capacity, config::GeneralConfig, content_type::{self, SNIFF_BYTES}, crypto::{generate_key, generate_nonce, Kdf}, e2e, errors::{AppError, AppResult, ErrorResponse}, extractors, metrics::{self, CryptoTimer, InFlightUpload}, models::Account, ratelimit::{check_quota, record_usage, Client, Quota}, repository::{acquire_blob, insert_collection, insert_upload, update_stats, InsertCollection, InsertUpload}, storage::Storage, utilities::{friendly_id, read_chunk, temp_file, ENC_CHUNK_SIZE}, AppContext
 };
 
+use super::{delete::delete_upload, openapi::UploadForm};
+
 async fn save_encrypted_file<W, R>(
     file: &mut W,
-    key: &[u8; 32],
+    encryption: &Encryption,
     nonce: &[u8; 19],
     body: &mut R,
+    size_limit: &mut SizeLimit,
 ) -> AppResult<usize>
 where
     W: AsyncWrite + Unpin,
     R: AsyncRead + Unpin,
 {
     let mut encryptor =
-        EncryptorBE32::<XChaCha20Poly1305>::new(key.as_ref().into(), nonce.as_ref().into());
+        EncryptorBE32::<XChaCha20Poly1305>::new(encryption.key.as_ref().into(), nonce.as_ref().into());
     let mut total_bytes = 0;
 
     loop {
         let chunk = read_chunk(body, ENC_CHUNK_SIZE).await?;
         total_bytes += chunk.len();
+        size_limit.consume(chunk.len())?;
 
         if chunk.len() < ENC_CHUNK_SIZE {
-            let ciphertext = encryptor.encrypt_last(chunk.as_slice())?;
+            let ciphertext = encryption.timer.time(chunk.len(), || encryptor.encrypt_last(chunk.as_slice()))?;
             file.write_all(&ciphertext).await?;
             break;
         } else {
-            let ciphertext = encryptor.encrypt_next(chunk.as_slice())?;
+            let ciphertext = encryption.timer.time(chunk.len(), || encryptor.encrypt_next(chunk.as_slice()))?;
             file.write_all(&ciphertext).await?;
         }
     }
@@ -50,7 +50,7 @@ where
     Ok(total_bytes)
 }
 
-async fn save_file<W, R>(file: &mut W, body: &mut R) -> AppResult<usize>
+async fn save_file<W, R>(file: &mut W, body: &mut R, size_limit: &mut SizeLimit) -> AppResult<usize>
 where
     W: AsyncWrite + Unpin,
     R: AsyncRead + Unpin,
@@ -60,6 +60,7 @@ where
     loop {
         let chunk = read_chunk(body, ENC_CHUNK_SIZE).await?;
         total_bytes += chunk.len();
+        size_limit.consume(chunk.len())?;
 
         file.write_all(&chunk).await?;
         if chunk.len() < ENC_CHUNK_SIZE {
@@ -70,106 +71,254 @@ where
     Ok(total_bytes)
 }
 
+const MAX_PASSWORD_LEN: usize = 1024;
+// same as the column
+const MAX_FILE_NAME_LEN: usize = 255;
+
+// room for boundaries, headers and text fields of the form, so it doesn't count towards the limit
+const MULTIPART_OVERHEAD: u64 = 64 * 1024;
+
+/// Most bytes the files of one upload can have together, callers with an API key can be allowed more.
+pub fn max_upload_bytes(cfg: &GeneralConfig, account: Option<&Account>) -> u64 {
+    match cfg.authenticated_max_upload_bytes {
+        Some(max_bytes) if account.is_some() => max_bytes,
+        _ => cfg.max_upload_bytes,
+    }
+}
+
+/// Fails for names that are empty or wouldn't fit in the database.
+pub fn check_file_name(file_name: &str) -> AppResult<()> {
+    if file_name.is_empty() || file_name.chars().count() > MAX_FILE_NAME_LEN {
+        return Err(AppError::InvalidFileName);
+    }
+    Ok(())
+}
+
+/// Counts received bytes of one upload and stops it as soon as it's over a limit.
+struct SizeLimit {
+    max_upload_bytes: u64,
+    max_file_bytes: Option<u64>,
+    quota: Option<Quota>,
+    upload_bytes: u64,
+    file_bytes: u64,
+}
+
+impl SizeLimit {
+    fn new(max_upload_bytes: u64, account: Option<&Account>, quota: Option<Quota>) -> Self {
+        Self {
+            max_upload_bytes,
+            max_file_bytes: account.and_then(|account| account.max_file_bytes).map(|n| n as u64),
+            quota,
+            upload_bytes: 0,
+            file_bytes: 0,
+        }
+    }
+
+    fn next_file(&mut self) {
+        self.file_bytes = 0;
+    }
+
+    fn consume(&mut self, bytes: usize) -> AppResult<()> {
+        self.upload_bytes += bytes as u64;
+        self.file_bytes += bytes as u64;
+
+        if self.upload_bytes > self.max_upload_bytes {
+            return Err(AppError::FileTooLarge(self.max_upload_bytes));
+        }
+        // body without a length only had an empty upload checked against the quota
+        if let Some(quota) = self.quota.filter(|quota| self.upload_bytes > quota.remaining()) {
+            return Err(quota.exceeded());
+        }
+        match self.max_file_bytes {
+            Some(max_bytes) if self.file_bytes > max_bytes => Err(AppError::FileTooLarge(max_bytes)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Key every file of the request is encrypted with.
+struct Encryption {
+    key: [u8; 32],
+    kdf: Option<Kdf>,
+    timer: CryptoTimer,
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn handle_upload(
-    storage_dir: &str,
-    blacklist: &[String],
+    storage: &dyn Storage,
+    temp_dir: &str,
+    blacklist: &Blacklist,
     db: &PgPool,
     field: Field<'_>,
     file_name: String,
-    encrypt: bool,
-    expiry_hours: Option<u32>,
-    expiry_downloads: Option<u32>,
-    embedded: bool,
-) -> AppResult<UploadResponse> {
-    let body = field.map_err(|err| io::Error::new(io::ErrorKind::Other, err));
-    let mut body_reader = StreamReader::new(body);
+    encryption: Option<&Encryption>,
+    delete_key: &str,
+    query: &UploadQuery,
+    account: Option<&Account>,
+    size_limit: &mut SizeLimit,
+    available: &mut Option<u64>,
+) -> AppResult<UploadedFile> {
+    size_limit.next_file();
+
+    // one byte past the free space is enough to tell that it's full, so the file is cut off right there
+    let limit = available.map_or(u64::MAX, |n| n + 1);
+    let declared_type = field.content_type().map(String::from);
+    let body = field.map_err(io::Error::other);
+    let mut body_reader = StreamReader::new(body).take(limit);
+
+    // type is detected from plaintext before it's encrypted, only the client knows what's in end-to-end encrypted ones
+    let head = if query.e2e {
+        Vec::new()
+    } else {
+        read_chunk(&mut body_reader, SNIFF_BYTES).await?
+    };
+    let content_type = if query.e2e {
+        None
+    } else {
+        content_type::detect(&head, declared_type.as_deref(), &file_name)
+    };
+    let mut reader = head.as_slice().chain(&mut body_reader);
 
     let id = friendly_id(8);
 
-    let mut key_hex = None;
     let mut nonce_hex = None;
+    let mut e2e_header = None;
 
-    let file_path = format!("{storage_dir}{id}");
-    let mut file = File::options()
-        .read(true)
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(&file_path)
-        .await?;
+    // upload is staged in temp dir, so nothing reaches storage before it's checked
+    let (mut file, file_path) = temp_file(temp_dir).await?;
 
-    let total_bytes = if encrypt {
-        let mut key = [0u8; 32];
-        OsRng.fill_bytes(&mut key);
+    let total_bytes = if let Some(encryption) = encryption {
+        // files sharing a key must never share a nonce
+        let nonce = generate_nonce();
+        nonce_hex = Some(hex::encode(nonce));
 
-        let mut nonce = [0u8; 19];
-        OsRng.fill_bytes(&mut nonce);
+        save_encrypted_file(&mut file, encryption, &nonce, &mut reader, size_limit).await?
+    } else if query.e2e {
+        // stored as it is, the header is only read to know how to describe the upload
+        let header = read_chunk(&mut reader, e2e::HEADER_SIZE).await?;
+        let parsed = e2e::Header::parse(&header)?;
 
-        key_hex = Some(hex::encode(key));
-        nonce_hex = Some(hex::encode(nonce));
+        let upload_size = save_file(&mut file, &mut header.as_slice().chain(&mut reader), size_limit).await?;
+        let plaintext_size = parsed.plaintext_size(upload_size as u64).ok_or_else(|| {
+            AppError::Validation(String::from("end-to-end encrypted upload has malformed chunks."))
+        })?;
 
-        save_encrypted_file(&mut file, &key, &nonce, &mut body_reader).await?
+        nonce_hex = Some(hex::encode(parsed.nonce));
+        e2e_header = Some(parsed);
+        plaintext_size as usize
     } else {
-        save_file(&mut file, &mut body_reader).await?
+        save_file(&mut file, &mut reader, size_limit).await?
     };
+    file.flush().await?;
+    drop(file);
 
-    // blacklist check
-    match sha256::try_async_digest(&file_path).await {
+    let received = limit - body_reader.limit();
+    capacity::check(*available, received)?;
+    if let Some(available) = available {
+        *available -= received;
+    }
+
+    // blacklist check, staged file is removed when `file_path` is dropped
+    let hash = match sha256::try_async_digest(&file_path).await {
         Ok(hash) => {
-            let lc_blacklist = blacklist.iter().map(|bl| bl.to_lowercase()).collect::<Vec<_>>(); // TODO(hito): save it somewhere so it doesnt have to be computed every upload
-            if lc_blacklist.contains(&hash.to_lowercase()) {
-                if let Err(why) = fs::remove_file(&file_path).await {
-                    tracing::error!("Failed to remove blacklisted file!! File name: {id}, error: {why:?}");
-                }
-                return Err(AppError::FileBlacklisted);
-            }
+            blacklist.check(db, &hash).await?;
+            Some(hash)
         }
-        Err(why) => tracing::error!("Failed to check file hash!! File name: {id}, error: {why:?}")
-    }
+        Err(why) => {
+            tracing::error!("Failed to check file hash!! File name: {id}, error: {why:?}");
+            None
+        }
+    };
 
-    if let Err(why) = update_stats(db, total_bytes as u64).await {
-        tracing::error!("failed to update stats: {why:?}");
+    // identical plain content is stored only once, encrypted content never repeats
+    let blob_hash = hash.filter(|_| nonce_hex.is_none());
+    let encrypted = nonce_hex.is_some();
+
+    let mut tx = db.begin().await?;
+    match &blob_hash {
+        Some(hash) => {
+            if acquire_blob(&mut tx, hash, total_bytes as u64).await? {
+                storage.put_file(hash, &file_path).await?;
+            }
+        }
+        None => {
+            storage.put_file(&id, &file_path).await?;
+        }
     }
 
-    let delete_key = friendly_id(21);
     insert_upload(
-        db,
+        &mut *tx,
         InsertUpload {
             id: id.clone(),
-            key_hash: key_hex.as_ref().map(sha256::digest),
-            delete_key: delete_key.clone(),
+            key_hash: encryption.map(|encryption| sha256::digest(hex::encode(encryption.key))),
+            delete_key: delete_key.to_string(),
             nonce: nonce_hex,
-            file_name,
+            file_name: file_name.clone(),
             bytes: total_bytes,
-            expiry_hours,
-            expiry_downloads,
-            embedded,
+            expiry_hours: query.expiry_hours,
+            expiry_downloads: query.expiry_downloads,
+            embedded: query.embedded,
+            kdf: encryption.and_then(|encryption| encryption.kdf.clone()),
+            e2e_version: e2e_header.as_ref().map(|header| header.version),
+            e2e_chunk_size: e2e_header.as_ref().map(|header| header.chunk_size),
+            blob_hash,
+            owner_id: account.map(|account| account.id.clone()),
+            content_type,
         },
     )
     .await?;
+    tx.commit().await?;
+
+    if let Err(why) = update_stats(db, total_bytes as u64, encrypted).await {
+        tracing::error!("failed to update stats: {why:?}");
+    }
+    metrics::uploaded(total_bytes as u64);
 
-    Ok(UploadResponse {
+    Ok(UploadedFile {
         id,
-        decryption_key: key_hex,
-        delete_key,
+        file_name,
+        bytes: total_bytes as u64,
     })
 }
 
-#[tracing::instrument]
-pub async fn upload_endpoint(
-    ctx: Extension<AppContext>,
-    extractors::Query(query): extractors::Query<UploadQuery>,
-    mut multipart: Multipart,
-) -> AppResult<Json<UploadResponse>> {
-    if let Some(_) = query.expiry_downloads {
-        if let Some(_) = query.expiry_hours {
-            return Err(AppError::BothExpirations);
-        }
-    }
-
+/// Saves every `file` field, several of them end up in a collection.
+/// Optional `password` field has to come before them, as files are encrypted while they're received.
+#[allow(clippy::too_many_arguments)]
+async fn handle_files(
+    ctx: &AppContext,
+    query: &UploadQuery,
+    encryption: &mut Option<Encryption>,
+    delete_key: &str,
+    multipart: &mut Multipart,
+    files: &mut Vec<UploadedFile>,
+    account: Option<&Account>,
+    mut size_limit: SizeLimit,
+    mut available: Option<u64>,
+) -> AppResult<()> {
     while let Some(field) = multipart.next_field().await? {
         match field.name() {
             Some("file") => (),
+            Some("password") => {
+                if query.e2e {
+                    return Err(AppError::KeyNotAccepted);
+                }
+                if !files.is_empty() {
+                    return Err(AppError::Validation(String::from("password has to be sent before files.")));
+                }
+
+                let password = field.text().await?;
+                if password.is_empty() || password.len() > MAX_PASSWORD_LEN {
+                    return Err(AppError::Validation(format!("password must have between 1 and {MAX_PASSWORD_LEN} bytes.")));
+                }
+
+                let kdf = Kdf::generate();
+                *encryption = Some(Encryption {
+                    key: kdf.derive(&password).await?,
+                    kdf: Some(kdf),
+                    timer: CryptoTimer::encryption(),
+                });
+                continue;
+            }
             _ => continue,
         };
 
@@ -178,32 +327,174 @@ pub async fn upload_endpoint(
             .ok_or(AppError::InvalidFileName)?
             .to_string();
 
-        if file_name.is_empty() {
-            return Err(AppError::InvalidFileName)?;
-        }
+        check_file_name(&file_name)?;
+
+        let file = handle_upload(ctx.storage.as_ref(), &ctx.cfg.general.temp_dir, &ctx.blacklist, &ctx.db, field, file_name, encryption.as_ref(), delete_key, query, account, &mut size_limit, &mut available).await?;
+        files.push(file);
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/upload",
+    tag = "uploads",
+    params(UploadQuery),
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Upload, or a collection when several files were uploaded.", body = UploadResponse),
+        (status = 400, description = "`empty-upload`, `invalid-file-name`, `both-expirations`, `key-not-accepted`, `file-blacklist` or `validation`.", body = ErrorResponse),
+        (status = 401, description = "`invalid-api-key`.", body = ErrorResponse),
+        (status = 413, description = "`file-too-large`.", body = ErrorResponse),
+        (status = 429, description = "`rate-limited` or `quota-exceeded`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+        (status = 507, description = "`storage-full`.", body = ErrorResponse),
+    ),
+    security((), ("api_key" = [])),
+)]
+#[tracing::instrument]
+pub async fn upload_endpoint(
+    ctx: Extension<AppContext>,
+    client: Client,
+    MaybeAccount(account): MaybeAccount,
+    extractors::Query(mut query): extractors::Query<UploadQuery>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> AppResult<Json<UploadResponse>> {
+    if query.expiry_downloads.is_some() && query.expiry_hours.is_some() {
+        return Err(AppError::BothExpirations);
+    }
+
+    if query.e2e && query.encrypt {
+        return Err(AppError::Validation(String::from("end-to-end encrypted uploads are already encrypted by the client.")));
+    }
+    query.apply_account_defaults(account.as_ref());
+    let _in_flight = InFlightUpload::start();
 
-        let res = handle_upload(&ctx.cfg.general.storage_dir, &ctx.cfg.blacklist, &ctx.db, field, file_name, query.encrypt, query.expiry_hours, query.expiry_downloads, query.embedded).await?;
-        return Ok(Json(res));
+    // body is only an estimate of what's stored, actual sizes are counted when it's done
+    let content_length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
+    let max_bytes = max_upload_bytes(&ctx.cfg.general, account.as_ref());
+    if content_length.is_some_and(|length| length > max_bytes + MULTIPART_OVERHEAD) {
+        return Err(AppError::FileTooLarge(max_bytes));
     }
+    let quota = check_quota(&ctx, &client, content_length.unwrap_or(0)).await?;
+    let available = capacity::available(&ctx).await?;
+    capacity::check(available, content_length.unwrap_or(0))?;
 
-    Err(AppError::EmptyUpload)
+    let mut encryption = query.encrypt.then(|| Encryption {
+        key: generate_key(),
+        kdf: None,
+        timer: CryptoTimer::encryption(),
+    });
+    let delete_key = friendly_id(21);
+
+    let mut files = Vec::new();
+    if let Err(why) = handle_files(&ctx, &query, &mut encryption, &delete_key, &mut multipart, &mut files, account.as_ref(), SizeLimit::new(max_bytes, account.as_ref(), quota), available).await {
+        // collection is all or nothing
+        remove_files(&ctx, &files).await;
+        return Err(why);
+    }
+    record_usage(&ctx, &client, files.iter().map(|file| file.bytes).sum()).await;
+
+    let key_hash = encryption.as_ref().map(|encryption| sha256::digest(hex::encode(encryption.key)));
+    // password is all that's needed to decrypt, so the key itself isn't handed out
+    let key_hex = encryption
+        .filter(|encryption| encryption.kdf.is_none())
+        .map(|encryption| hex::encode(encryption.key));
+    match files.len() {
+        0 => Err(AppError::EmptyUpload),
+        1 => Ok(Json(UploadResponse {
+            id: files.remove(0).id,
+            decryption_key: key_hex,
+            delete_key,
+            files: Vec::new(),
+        })),
+        _ => {
+            let id = friendly_id(8);
+            let upload_ids = files.iter().map(|file| file.id.clone()).collect::<Vec<_>>();
+            let inserted = insert_collection(
+                &ctx.db,
+                InsertCollection {
+                    id: id.clone(),
+                    key_hash,
+                    delete_key: delete_key.clone(),
+                },
+                &upload_ids,
+            )
+            .await;
+            if let Err(why) = inserted {
+                remove_files(&ctx, &files).await;
+                return Err(why.into());
+            }
+
+            Ok(Json(UploadResponse {
+                id,
+                decryption_key: key_hex,
+                delete_key,
+                files,
+            }))
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+/// Removes uploads of a collection that couldn't be finished.
+async fn remove_files(ctx: &AppContext, files: &[UploadedFile]) {
+    for file in files {
+        if let Err(why) = delete_upload(&ctx.db, ctx.storage.as_ref(), &file.id).await {
+            tracing::error!("Failed to remove upload {} of a failed collection: {why:?}", file.id);
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct UploadQuery {
+    /// Encrypt files with a random key that's returned, and never stored, as `decryptionKey`.
     #[serde(default)]
     pub encrypt: bool,
+    /// Give the upload a page at `/embed/{upload_id}` and oEmbed responses, so links to it unfurl with the media inline.
     #[serde(default)]
     pub embedded: bool,
+    /// Data is already encrypted by the client, see [`crate::e2e`].
+    #[serde(default)]
+    pub e2e: bool,
+    /// Remove the upload this many hours after it's uploaded.
     pub expiry_hours: Option<u32>,
+    /// Remove the upload once it's downloaded this many times.
     pub expiry_downloads: Option<u32>,
 }
 
-#[derive(Serialize, Deserialize)]
+impl UploadQuery {
+    /// Uploads of accounts with a default expiry get it unless they ask for another one.
+    pub fn apply_account_defaults(&mut self, account: Option<&Account>) {
+        if self.expiry_hours.is_none() && self.expiry_downloads.is_none() {
+            self.expiry_hours = account
+                .and_then(|account| account.default_expiry_hours)
+                .map(|hours| hours as u32);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct UploadResponse {
     pub id: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub decryption_key: Option<String>,
     pub delete_key: String,
+    /// Members of the collection, only present when several files were uploaded at once.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<UploadedFile>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadedFile {
+    pub id: String,
+    pub file_name: String,
+    pub bytes: u64,
 }
@@ -0,0 +1,171 @@
+use std::collections::{HashMap, HashSet};
+
+use axum::{
+    body::Body,
+    http::header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::{
+    crypto::{Kdf, Secret},
+    errors::{AppError, AppResult, ErrorResponse},
+    extractors,
+    models::Upload,
+    repository::{fetch_collection, fetch_collection_uploads, fetch_upload},
+    zip::{self, ZipEntry},
+    AppContext,
+};
+
+use super::download::{check_expiry, record_download, upload_source};
+
+// archive of more uploads than that should rather be a collection
+const MAX_ARCHIVE_UPLOADS: usize = 100;
+
+#[utoipa::path(
+    get,
+    path = "/zip",
+    tag = "uploads",
+    params(ZipQuery),
+    responses(
+        (status = 200, description = "Zip archive of the uploads.", content_type = "application/zip"),
+        (status = 400, description = "`upload-not-found`, `missing-key`, `missing-password`, `invalid-decryption-key`, `invalid-password`, `key-not-accepted`, `corrupted-upload` or `validation`.", body = ErrorResponse),
+        (status = 404, description = "`upload-expired`.", body = ErrorResponse),
+        (status = 429, description = "`rate-limited`.", body = ErrorResponse),
+        (status = 500, description = "`other`.", body = ErrorResponse),
+    ),
+)]
+#[tracing::instrument(skip(query))]
+pub async fn zip_endpoint(
+    ctx: Extension<AppContext>,
+    extractors::Query(query): extractors::Query<ZipQuery>,
+) -> AppResult<Response> {
+    let mut seen = HashSet::new();
+    let ids = query
+        .ids
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && seen.insert(*id))
+        .collect::<Vec<_>>();
+
+    if ids.is_empty() {
+        return Err(AppError::Validation(String::from("At least one upload id is required.")));
+    }
+    if ids.len() > MAX_ARCHIVE_UPLOADS {
+        return Err(AppError::Validation(format!("At most {MAX_ARCHIVE_UPLOADS} uploads can be archived at once.")));
+    }
+
+    let mut uploads = Vec::with_capacity(ids.len());
+    for id in ids {
+        let upload = fetch_upload(&ctx.db, id)
+            .await?
+            .ok_or(AppError::UploadNotFound)?;
+        check_expiry(&ctx, &upload).await?;
+        uploads.push(upload);
+    }
+
+    archive_response(&ctx, uploads, query.secret(), "uploads.zip").await
+}
+
+/// Archive with every upload of the collection that didn't expire yet.
+pub async fn collection_archive(ctx: &AppContext, collection_id: &str, secret: Secret<'_>) -> AppResult<Response> {
+    let collection = fetch_collection(&ctx.db, collection_id)
+        .await?
+        .ok_or(AppError::UploadNotFound)?;
+
+    let mut uploads = Vec::new();
+    for upload in fetch_collection_uploads(&ctx.db, &collection.id).await? {
+        if check_expiry(ctx, &upload).await.is_ok() {
+            uploads.push(upload);
+        }
+    }
+
+    if uploads.is_empty() {
+        return Err(AppError::UploadExpired);
+    }
+
+    archive_response(ctx, uploads, secret, &format!("{}.zip", collection.id)).await
+}
+
+async fn archive_response(
+    ctx: &AppContext,
+    uploads: Vec<Upload>,
+    secret: Secret<'_>,
+    file_name: &str,
+) -> AppResult<Response> {
+    let mut entries = Vec::with_capacity(uploads.len());
+    let mut derived_keys = HashMap::new();
+    for upload in &uploads {
+        // members of a collection share the salt, so their password is derived only once
+        let derived_key = match (secret.key, secret.password, Kdf::of(upload)?) {
+            (None, Some(password), Some(kdf)) => match derived_keys.get(&kdf.salt) {
+                Some(key) => Some(String::clone(key)),
+                None => {
+                    let key = hex::encode(kdf.derive(password).await?);
+                    derived_keys.insert(kdf.salt, key.clone());
+                    Some(key)
+                }
+            },
+            _ => None,
+        };
+
+        let (source, size) = match &derived_key {
+            Some(key) => {
+                let secret = Secret {
+                    key: Some(key),
+                    password: None,
+                };
+                upload_source(ctx, upload, secret).await.map_err(|why| match why {
+                    AppError::InvalidDecryptionKey => AppError::InvalidPassword,
+                    why => why,
+                })?
+            }
+            None => upload_source(ctx, upload, secret).await?,
+        };
+        entries.push(ZipEntry {
+            name: upload.file_name.clone(),
+            size,
+            modified: upload.created_at,
+            source,
+        });
+    }
+
+    let (length, stream) = zip::archive(entries);
+    let mut response = (
+        [
+            (CONTENT_TYPE, String::from("application/zip")),
+            (CONTENT_LENGTH, length.to_string()),
+            (CONTENT_DISPOSITION, format!(r#"attachment; filename="{file_name}""#)),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response();
+
+    // every member counts as downloaded, so `expiry_downloads` holds for archives as well
+    for upload in &uploads {
+        response = record_download(ctx, upload, response).await;
+    }
+
+    Ok(response)
+}
+
+/// Encrypted uploads need either their key or their password, which is the same for all of them.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ZipQuery {
+    /// Comma separated ids of the uploads.
+    ids: String,
+    key: Option<String>,
+    password: Option<String>,
+}
+
+impl ZipQuery {
+    fn secret(&self) -> Secret<'_> {
+        Secret {
+            key: self.key.as_deref(),
+            password: self.password.as_deref(),
+        }
+    }
+}
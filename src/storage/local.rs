@@ -0,0 +1,97 @@
+use std::{
+    io::SeekFrom,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use axum::async_trait;
+use futures::StreamExt;
+use tokio::{
+    fs::{self, File},
+    io::{self, AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+use tokio_util::io::ReaderStream;
+
+use super::{ByteStream, Storage};
+
+/// Stores every blob as a separate file in one directory.
+#[derive(Debug)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, mut stream: ByteStream) -> io::Result<u64> {
+        let path = self.path(key);
+        let mut file = File::create(&path).await?;
+        let mut total_bytes = 0;
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(why) => {
+                    drop(file);
+                    if let Err(why) = fs::remove_file(&path).await {
+                        tracing::warn!("failed to remove partially written {path:?}: {why:?}");
+                    }
+                    return Err(why);
+                }
+            };
+
+            file.write_all(&chunk).await?;
+            total_bytes += chunk.len() as u64;
+        }
+
+        file.flush().await?;
+        Ok(total_bytes)
+    }
+
+    async fn put_file(&self, key: &str, path: &Path) -> io::Result<u64> {
+        let bytes = fs::metadata(path).await?.len();
+        let target = self.path(key);
+
+        // rename fails when temp and storage directories are on different devices
+        if fs::rename(path, &target).await.is_err() {
+            fs::copy(path, &target).await?;
+            fs::remove_file(path).await?;
+        }
+
+        Ok(bytes)
+    }
+
+    async fn get(&self, key: &str) -> io::Result<ByteStream> {
+        let file = File::open(self.path(key)).await?;
+        Ok(Box::pin(ReaderStream::new(file)))
+    }
+
+    async fn get_range(&self, key: &str, range: Range<u64>) -> io::Result<ByteStream> {
+        let mut file = File::open(self.path(key)).await?;
+        file.seek(SeekFrom::Start(range.start)).await?;
+
+        let reader = file.take(range.end - range.start);
+        Ok(Box::pin(ReaderStream::new(reader)))
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        fs::remove_file(self.path(key)).await
+    }
+
+    async fn exists(&self, key: &str) -> io::Result<bool> {
+        fs::try_exists(self.path(key)).await
+    }
+
+    async fn size(&self, key: &str) -> io::Result<u64> {
+        Ok(fs::metadata(self.path(key)).await?.len())
+    }
+}
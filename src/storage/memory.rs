@@ -0,0 +1,81 @@
+use std::{collections::HashMap, ops::Range, sync::RwLock};
+
+use axum::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::{stream, StreamExt};
+use tokio::io;
+
+use super::{ByteStream, Storage};
+
+/// Keeps blobs in memory, everything is lost on restart so it's mostly useful in tests.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    blobs: RwLock<HashMap<String, Bytes>>,
+}
+
+impl MemoryStorage {
+    fn blob(&self, key: &str) -> io::Result<Bytes> {
+        self.blobs
+            .read()
+            .unwrap()
+            .get(key)
+            .cloned()
+            .ok_or_else(|| not_found(key))
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn put(&self, key: &str, mut stream: ByteStream) -> io::Result<u64> {
+        let mut blob = BytesMut::new();
+        while let Some(chunk) = stream.next().await {
+            blob.extend_from_slice(&chunk?);
+        }
+
+        let bytes = blob.len() as u64;
+        self.blobs
+            .write()
+            .unwrap()
+            .insert(key.to_string(), blob.freeze());
+        Ok(bytes)
+    }
+
+    async fn get(&self, key: &str) -> io::Result<ByteStream> {
+        let blob = self.blob(key)?;
+        Ok(Box::pin(stream::once(async { Ok(blob) })))
+    }
+
+    async fn get_range(&self, key: &str, range: Range<u64>) -> io::Result<ByteStream> {
+        let blob = self.blob(key)?;
+        if range.end > blob.len() as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("range {range:?} is out of bounds for `{key}`"),
+            ));
+        }
+
+        let slice = blob.slice(range.start as usize..range.end as usize);
+        Ok(Box::pin(stream::once(async { Ok(slice) })))
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        self.blobs
+            .write()
+            .unwrap()
+            .remove(key)
+            .map(|_| ())
+            .ok_or_else(|| not_found(key))
+    }
+
+    async fn exists(&self, key: &str) -> io::Result<bool> {
+        Ok(self.blobs.read().unwrap().contains_key(key))
+    }
+
+    async fn size(&self, key: &str) -> io::Result<u64> {
+        Ok(self.blob(key)?.len() as u64)
+    }
+}
+
+fn not_found(key: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("blob `{key}` not found"))
+}
@@ -0,0 +1,61 @@
+mod local;
+mod memory;
+mod object;
+
+use std::{fmt::Debug, ops::Range, path::Path, sync::Arc};
+
+use axum::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use tokio::{fs::File, io};
+use tokio_util::io::ReaderStream;
+
+pub use local::LocalStorage;
+pub use memory::MemoryStorage;
+pub use object::ObjectStorage;
+
+use crate::{
+    config::{Config, StorageConfig},
+    errors::AppResult,
+};
+
+pub type ByteStream = BoxStream<'static, io::Result<Bytes>>;
+
+/// Place where upload blobs live, keyed by upload id.
+///
+/// Missing blobs are reported as [`io::ErrorKind::NotFound`] by every backend.
+#[async_trait]
+pub trait Storage: Debug + Send + Sync {
+    /// Stores everything yielded by `stream` under `key` and returns how many bytes were written.
+    async fn put(&self, key: &str, stream: ByteStream) -> io::Result<u64>;
+
+    /// Moves a fully written file into storage, the file at `path` is consumed.
+    async fn put_file(&self, key: &str, path: &Path) -> io::Result<u64> {
+        let file = File::open(path).await?;
+        let bytes = self.put(key, Box::pin(ReaderStream::new(file))).await?;
+        tokio::fs::remove_file(path).await?;
+        Ok(bytes)
+    }
+
+    async fn get(&self, key: &str) -> io::Result<ByteStream>;
+
+    /// Streams bytes in `range`, the range must be within the blob.
+    async fn get_range(&self, key: &str, range: Range<u64>) -> io::Result<ByteStream>;
+
+    async fn delete(&self, key: &str) -> io::Result<()>;
+
+    async fn exists(&self, key: &str) -> io::Result<bool>;
+
+    async fn size(&self, key: &str) -> io::Result<u64>;
+}
+
+pub fn from_config(cfg: &Config) -> AppResult<Arc<dyn Storage>> {
+    let storage: Arc<dyn Storage> = match &cfg.storage {
+        StorageConfig::Local => Arc::new(LocalStorage::new(&cfg.general.storage_dir)),
+        StorageConfig::Memory => Arc::new(MemoryStorage::default()),
+        StorageConfig::S3(s3) => Arc::new(ObjectStorage::s3(s3)?),
+    };
+
+    tracing::debug!("using {storage:?} as storage backend");
+    Ok(storage)
+}
@@ -0,0 +1,141 @@
+use std::{ops::Range, sync::Arc};
+
+use anyhow::Context;
+use axum::async_trait;
+use futures::{StreamExt, TryStreamExt};
+use object_store::{
+    aws::AmazonS3Builder, path::Path as ObjectPath, GetOptions, GetRange, ObjectStore,
+    WriteMultipart,
+};
+use tokio::io;
+
+use super::{ByteStream, Storage};
+use crate::{config::S3Config, errors::AppResult};
+
+// how many parts can be uploaded concurrently before we stop reading the body
+const MAX_CONCURRENT_PARTS: usize = 4;
+
+/// Stores blobs in an object store, usually an S3 compatible bucket.
+#[derive(Debug)]
+pub struct ObjectStorage {
+    store: Arc<dyn ObjectStore>,
+}
+
+impl ObjectStorage {
+    pub fn new(store: Arc<dyn ObjectStore>) -> Self {
+        Self { store }
+    }
+
+    /// Credentials not set in config are taken from standard `AWS_*` environment variables.
+    pub fn s3(cfg: &S3Config) -> AppResult<Self> {
+        let mut builder = AmazonS3Builder::from_env()
+            .with_bucket_name(&cfg.bucket)
+            .with_allow_http(cfg.allow_http);
+
+        if let Some(region) = &cfg.region {
+            builder = builder.with_region(region);
+        }
+        if let Some(endpoint) = &cfg.endpoint {
+            builder = builder.with_endpoint(endpoint);
+        }
+        if let Some(access_key_id) = &cfg.access_key_id {
+            builder = builder.with_access_key_id(access_key_id);
+        }
+        if let Some(secret_access_key) = &cfg.secret_access_key {
+            builder = builder.with_secret_access_key(secret_access_key);
+        }
+
+        let store = builder.build().context("failed to configure s3 storage")?;
+        Ok(Self::new(Arc::new(store)))
+    }
+
+    async fn get_opts(&self, key: &str, range: Option<GetRange>) -> io::Result<ByteStream> {
+        let opts = GetOptions {
+            range,
+            ..Default::default()
+        };
+        let res = self
+            .store
+            .get_opts(&ObjectPath::from(key), opts)
+            .await
+            .map_err(into_io)?;
+
+        Ok(Box::pin(res.into_stream().map_err(into_io)))
+    }
+}
+
+#[async_trait]
+impl Storage for ObjectStorage {
+    async fn put(&self, key: &str, mut stream: ByteStream) -> io::Result<u64> {
+        let upload = self
+            .store
+            .put_multipart(&ObjectPath::from(key))
+            .await
+            .map_err(into_io)?;
+        let mut writer = WriteMultipart::new(upload);
+        let mut total_bytes = 0;
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(why) => {
+                    if let Err(why) = writer.abort().await {
+                        tracing::warn!("failed to abort multipart upload of `{key}`: {why:?}");
+                    }
+                    return Err(why);
+                }
+            };
+
+            writer
+                .wait_for_capacity(MAX_CONCURRENT_PARTS)
+                .await
+                .map_err(into_io)?;
+            total_bytes += chunk.len() as u64;
+            writer.put(chunk);
+        }
+
+        writer.finish().await.map_err(into_io)?;
+        Ok(total_bytes)
+    }
+
+    async fn get(&self, key: &str) -> io::Result<ByteStream> {
+        self.get_opts(key, None).await
+    }
+
+    async fn get_range(&self, key: &str, range: Range<u64>) -> io::Result<ByteStream> {
+        let range = GetRange::Bounded(range.start as usize..range.end as usize);
+        self.get_opts(key, Some(range)).await
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        let path = ObjectPath::from(key);
+
+        // most object stores don't report missing objects on delete
+        self.store.head(&path).await.map_err(into_io)?;
+        self.store.delete(&path).await.map_err(into_io)
+    }
+
+    async fn exists(&self, key: &str) -> io::Result<bool> {
+        match self.store.head(&ObjectPath::from(key)).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(why) => Err(into_io(why)),
+        }
+    }
+
+    async fn size(&self, key: &str) -> io::Result<u64> {
+        let meta = self
+            .store
+            .head(&ObjectPath::from(key))
+            .await
+            .map_err(into_io)?;
+        Ok(meta.size as u64)
+    }
+}
+
+fn into_io(why: object_store::Error) -> io::Error {
+    match why {
+        object_store::Error::NotFound { .. } => io::Error::new(io::ErrorKind::NotFound, why),
+        why => io::Error::other(why),
+    }
+}
@@ -0,0 +1,174 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::http::{header::AUTHORIZATION, HeaderValue, StatusCode};
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestResponse, TestServer,
+    };
+    use serde::{Deserialize, Serialize};
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, AdminToken, StorageConfig},
+        router,
+        routes::upload::UploadResponse,
+        storage, CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    const ADMIN_TOKEN: &str = "admin-token";
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct CreateAccount {
+        name: &'static str,
+        max_file_bytes: u64,
+        default_expiry_hours: u32,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct CreatedAccount {
+        api_key: CreatedKey,
+    }
+
+    #[derive(Deserialize)]
+    struct CreatedKey {
+        id: i64,
+        key: String,
+    }
+
+    #[derive(Deserialize)]
+    struct Page {
+        items: Vec<OwnedUpload>,
+        total: i64,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct OwnedUpload {
+        id: String,
+        expiry_hours: Option<i32>,
+    }
+
+    async fn server(db: PgPool) -> anyhow::Result<TestServer> {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        config.admin.tokens = vec![AdminToken {
+            name: String::from("admin"),
+            token: String::from(ADMIN_TOKEN),
+        }];
+        let storage = storage::from_config(&config)?;
+        TestServer::new(router(config, db, storage))
+    }
+
+    fn bearer(token: &str) -> HeaderValue {
+        HeaderValue::from_str(&format!("Bearer {token}")).unwrap()
+    }
+
+    async fn upload(server: &TestServer, key: Option<&str>, content: &[u8]) -> TestResponse {
+        let form = MultipartForm::new().add_part("file", Part::bytes(content.to_vec()).file_name("notes.txt"));
+        let mut request = server.post("/upload");
+        if let Some(key) = key {
+            request = request.add_header(AUTHORIZATION, bearer(key));
+        }
+        request.multipart(form).await
+    }
+
+    fn error_code(response: &TestResponse) -> String {
+        let body: HashMap<String, String> = response.json();
+        body["errorCode"].clone()
+    }
+
+    #[sqlx::test]
+    async fn account_owns_its_uploads(db: PgPool) -> TestResult {
+        let server = server(db).await?;
+        let account: CreatedAccount = server
+            .post("/admin/accounts")
+            .add_header(AUTHORIZATION, bearer(ADMIN_TOKEN))
+            .json(&CreateAccount {
+                name: "team",
+                max_file_bytes: 100,
+                default_expiry_hours: 24,
+            })
+            .await
+            .json();
+        let key = account.api_key.key.as_str();
+
+        let owned: UploadResponse = upload(&server, Some(key), b"ours").await.json();
+        let anonymous: UploadResponse = upload(&server, None, b"someone else's").await.json();
+
+        let response = upload(&server, Some("cf_revoked"), b"nope").await;
+        assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+        assert_eq!(error_code(&response), "invalid-api-key");
+
+        let response = upload(&server, Some(key), &[0; 101]).await;
+        assert_eq!(response.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(error_code(&response), "file-too-large");
+
+        let page: Page = server
+            .get("/me/uploads")
+            .add_header(AUTHORIZATION, bearer(key))
+            .await
+            .json();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].id, owned.id);
+        assert_eq!(page.items[0].expiry_hours, Some(24));
+
+        let response = server
+            .delete(&format!("/me/uploads/{}", anonymous.id))
+            .add_header(AUTHORIZATION, bearer(key))
+            .await;
+        assert_eq!(error_code(&response), "upload-not-found");
+
+        let response = server
+            .delete(&format!("/me/uploads/{}", owned.id))
+            .add_header(AUTHORIZATION, bearer(key))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+
+        let response = server.get("/me").await;
+        assert_eq!(error_code(&response), "missing-api-key");
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn keys_can_be_rotated(db: PgPool) -> TestResult {
+        let server = server(db).await?;
+        let account: CreatedAccount = server
+            .post("/admin/accounts")
+            .add_header(AUTHORIZATION, bearer(ADMIN_TOKEN))
+            .json(&CreateAccount {
+                name: "rotating",
+                max_file_bytes: 1024,
+                default_expiry_hours: 1,
+            })
+            .await
+            .json();
+        let old = account.api_key;
+
+        let new: CreatedKey = server
+            .post("/me/keys")
+            .add_header(AUTHORIZATION, bearer(&old.key))
+            .json(&HashMap::from([("label", "ci")]))
+            .await
+            .json();
+
+        let response = server
+            .delete(&format!("/me/keys/{}", old.id))
+            .add_header(AUTHORIZATION, bearer(&new.key))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+
+        let response = server.get("/me").add_header(AUTHORIZATION, bearer(&old.key)).await;
+        assert_eq!(error_code(&response), "invalid-api-key");
+        let response = server.get("/me").add_header(AUTHORIZATION, bearer(&new.key)).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        Ok(())
+    }
+}
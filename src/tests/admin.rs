@@ -0,0 +1,107 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::http::{header::AUTHORIZATION, HeaderValue, StatusCode};
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestServer,
+    };
+    use serde::Deserialize;
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, AdminToken, StorageConfig},
+        router,
+        routes::upload::UploadResponse,
+        storage, CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Page<T> {
+        items: Vec<T>,
+        total: i64,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct AdminUpload {
+        id: String,
+        file_name: String,
+    }
+
+    #[derive(Deserialize)]
+    struct AuditEntry {
+        admin: String,
+        action: String,
+        target: Option<String>,
+    }
+
+    async fn server(db: PgPool) -> anyhow::Result<TestServer> {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        config.admin.tokens = vec![AdminToken {
+            name: String::from("ops"),
+            token: String::from("ops-token"),
+        }];
+        let storage = storage::from_config(&config)?;
+
+        let mut server = TestServer::new(router(config, db, storage))?;
+        server.add_header(AUTHORIZATION, HeaderValue::from_static("Bearer ops-token"));
+        Ok(server)
+    }
+
+    async fn upload(server: &TestServer, file_name: &str) -> UploadResponse {
+        let form = MultipartForm::new().add_part("file", Part::bytes(file_name.as_bytes().to_vec()).file_name(file_name));
+        // admin token isn't an API key
+        server.post("/upload").clear_headers().multipart(form).await.json()
+    }
+
+    #[sqlx::test]
+    async fn moderate_uploads(db: PgPool) -> TestResult {
+        let server = server(db).await?;
+        let report = upload(&server, "report_100%.pdf").await;
+        upload(&server, "report_2.pdf").await;
+        upload(&server, "holiday.jpg").await;
+
+        let response = server.get("/admin/uploads").clear_headers().await;
+        assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+
+        let page: Page<AdminUpload> = server
+            .get("/admin/uploads")
+            .add_query_param("search", "report")
+            .add_query_param("per_page", 1)
+            .await
+            .json();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.items.len(), 1);
+
+        // wildcards are matched literally
+        let page: Page<AdminUpload> = server.get("/admin/uploads").add_query_param("search", "100%").await.json();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].file_name, "report_100%.pdf");
+
+        let response = server.get(&format!("/admin/uploads/{}", report.id)).await;
+        let upload: HashMap<String, serde::de::IgnoredAny> = response.json();
+        assert!(upload.contains_key("fileName"));
+        assert!(!upload.contains_key("deleteKey"));
+        assert_eq!(response.json::<AdminUpload>().id, report.id);
+
+        let response = server.delete(&format!("/admin/uploads/{}", report.id)).await;
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+        let response = server.get(&format!("/download/{}", report.id)).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        let audit: Page<AuditEntry> = server.get("/admin/audit").await.json();
+        assert_eq!(audit.total, 4);
+        let latest = &audit.items[0];
+        assert_eq!(latest.admin, "ops");
+        assert_eq!(latest.action, "delete-upload");
+        assert_eq!(latest.target.as_deref(), Some(report.id.as_str()));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use axum::http::{header::CONTENT_LENGTH, StatusCode};
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestServer,
+    };
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, StorageConfig},
+        router,
+        routes::upload::UploadResponse,
+        storage, CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    const FIRST: &[u8] = b"first file";
+
+    async fn server(db: PgPool) -> anyhow::Result<TestServer> {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        let storage = storage::from_config(&config)?;
+        TestServer::new(router(config, db, storage))
+    }
+
+    /// Second file spans several encryption chunks.
+    fn second() -> Vec<u8> {
+        (0..5000).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn entries(archive: &[u8]) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+        let mut archive = ::zip::ZipArchive::new(Cursor::new(archive))?;
+        let mut entries = Vec::new();
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            let mut content = Vec::new();
+            file.read_to_end(&mut content)?;
+            entries.push((file.name().to_string(), content));
+        }
+        Ok(entries)
+    }
+
+    #[sqlx::test]
+    async fn encrypted_collection_archive(db: PgPool) -> TestResult {
+        let server = server(db.clone()).await?;
+
+        let form = MultipartForm::new()
+            .add_part("file", Part::bytes(FIRST).file_name("same.txt"))
+            .add_part("file", Part::bytes(second()).file_name("same.txt"));
+        let collection: UploadResponse = server
+            .post("/upload")
+            .add_query_param("encrypt", true)
+            .add_query_param("expiry_downloads", 1)
+            .multipart(form)
+            .await
+            .json();
+
+        let response = server.get(&format!("/download/{}", collection.id)).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        let response = server
+            .get(&format!("/download/{}", collection.id))
+            .add_query_param("key", collection.decryption_key.as_ref().unwrap())
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let archive = response.as_bytes();
+        assert_eq!(response.header(CONTENT_LENGTH), archive.len().to_string().as_str());
+        assert_eq!(
+            entries(archive)?,
+            [
+                (String::from("same.txt"), FIRST.to_vec()),
+                (String::from("same (1).txt"), second()),
+            ]
+        );
+
+        // the only allowed download of every member was used up by the archive
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let remaining = sqlx::query_scalar!("SELECT COUNT(*) FROM uploads")
+            .fetch_one(&db)
+            .await?;
+        assert_eq!(remaining, Some(0));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn archive_of_uploads(db: PgPool) -> TestResult {
+        let server = server(db.clone()).await?;
+
+        let mut ids = Vec::new();
+        for (name, content) in [("a.txt", FIRST.to_vec()), ("b.bin", second())] {
+            let form = MultipartForm::new().add_part("file", Part::bytes(content).file_name(name));
+            let upload: UploadResponse = server.post("/upload").multipart(form).await.json();
+            ids.push(upload.id);
+        }
+
+        let response = server.get("/zip").add_query_param("ids", ids.join(",")).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let entries = entries(response.as_bytes())?;
+        assert_eq!(entries[0], (String::from("a.txt"), FIRST.to_vec()));
+        assert_eq!(entries[1], (String::from("b.bin"), second()));
+
+        let downloads = sqlx::query_scalar!("SELECT downloads FROM uploads ORDER BY file_name")
+            .fetch_all(&db)
+            .await?;
+        assert_eq!(downloads, [1, 1]);
+
+        let response = server.get("/zip").add_query_param("ids", format!("{},missing", ids[0])).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+}
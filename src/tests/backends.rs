@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod tests {
+    use std::{io::ErrorKind, sync::Arc};
+
+    use axum::http::StatusCode;
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestServer,
+    };
+    use bytes::Bytes;
+    use futures::{stream, TryStreamExt};
+    use object_store::memory::InMemory;
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, S3Config, StorageConfig},
+        router,
+        routes::upload::UploadResponse,
+        storage::{self, ByteStream, LocalStorage, MemoryStorage, ObjectStorage, Storage},
+        CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    fn body(chunks: &[&'static [u8]]) -> ByteStream {
+        let chunks = chunks.iter().map(|c| Ok(Bytes::from_static(c))).collect::<Vec<_>>();
+        Box::pin(stream::iter(chunks))
+    }
+
+    async fn collect(stream: ByteStream) -> anyhow::Result<Vec<u8>> {
+        Ok(stream.map_ok(|c| c.to_vec()).try_concat().await?)
+    }
+
+    async fn conformance(storage: &dyn Storage) -> TestResult {
+        assert!(!storage.exists("blob").await?);
+        assert_eq!(
+            storage.get("blob").await.err().map(|e| e.kind()),
+            Some(ErrorKind::NotFound)
+        );
+
+        let written = storage.put("blob", body(&[b"hello ", b"world"])).await?;
+        assert_eq!(written, 11);
+        assert!(storage.exists("blob").await?);
+        assert_eq!(storage.size("blob").await?, 11);
+
+        assert_eq!(collect(storage.get("blob").await?).await?, b"hello world");
+        assert_eq!(collect(storage.get_range("blob", 3..8).await?).await?, b"lo wo");
+
+        storage.delete("blob").await?;
+        assert!(!storage.exists("blob").await?);
+        assert_eq!(
+            storage.delete("blob").await.err().map(|e| e.kind()),
+            Some(ErrorKind::NotFound)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn local_storage() -> TestResult {
+        let dir = tempfile::tempdir()?;
+        conformance(&LocalStorage::new(dir.path())).await
+    }
+
+    #[tokio::test]
+    async fn memory_storage() -> TestResult {
+        conformance(&MemoryStorage::default()).await
+    }
+
+    #[tokio::test]
+    async fn object_storage() -> TestResult {
+        conformance(&ObjectStorage::new(Arc::new(InMemory::new()))).await
+    }
+
+    // run with a minio (or any other s3 compatible) server, e.g.
+    // S3_TEST_ENDPOINT=http://127.0.0.1:9000 S3_TEST_BUCKET=test AWS_ACCESS_KEY_ID=.. AWS_SECRET_ACCESS_KEY=.. cargo test -- --ignored
+    #[tokio::test]
+    #[ignore = "needs s3 compatible server"]
+    async fn s3_storage() -> TestResult {
+        let storage = ObjectStorage::s3(&S3Config {
+            bucket: std::env::var("S3_TEST_BUCKET")?,
+            region: Some(String::from("us-east-1")),
+            endpoint: Some(std::env::var("S3_TEST_ENDPOINT")?),
+            access_key_id: None,
+            secret_access_key: None,
+            allow_http: true,
+        })?;
+        conformance(&storage).await
+    }
+
+    #[sqlx::test]
+    async fn upload_and_download_from_memory(db: PgPool) -> TestResult {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        let storage = storage::from_config(&config)?;
+        let server = TestServer::new(router(config, db, storage))?;
+
+        let multipart_form = MultipartForm::new()
+            .add_part("file", Part::bytes(b"in memory".as_slice()).file_name("memory.txt"));
+        let response = server.post("/upload").multipart(multipart_form).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let body: UploadResponse = response.json();
+        let response = server.get(&format!("/download/{}", body.id)).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.as_bytes().as_ref(), b"in memory");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::http::{header::AUTHORIZATION, HeaderValue, StatusCode};
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestResponse, TestServer,
+    };
+    use serde::{Deserialize, Serialize};
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, AdminToken, StorageConfig},
+        router,
+        routes::upload::UploadResponse,
+        storage, CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    const CONTENT: &[u8] = b"definitely not malware";
+    const TOKEN: &str = "moderator-token";
+
+    #[derive(Serialize)]
+    struct AddBody<'a> {
+        hash: String,
+        reason: &'a str,
+        purge: bool,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Entry {
+        hash: String,
+        reason: Option<String>,
+        added_by: String,
+        purged: Option<u32>,
+    }
+
+    async fn server(db: PgPool) -> anyhow::Result<TestServer> {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        config.admin.tokens = vec![AdminToken {
+            name: String::from("moderator"),
+            token: String::from(TOKEN),
+        }];
+        let storage = storage::from_config(&config)?;
+        TestServer::new(router(config, db, storage))
+    }
+
+    fn bearer(token: &str) -> HeaderValue {
+        HeaderValue::from_str(&format!("Bearer {token}")).unwrap()
+    }
+
+    async fn upload(server: &TestServer) -> TestResponse {
+        let form = MultipartForm::new().add_part("file", Part::bytes(CONTENT).file_name("setup.exe"));
+        server.post("/upload").multipart(form).await
+    }
+
+    #[sqlx::test]
+    async fn blacklist_and_purge(db: PgPool) -> TestResult {
+        let server = server(db).await?;
+        let existing: UploadResponse = upload(&server).await.json();
+        let hash = sha256::digest(CONTENT);
+        let body = AddBody {
+            hash: hash.to_uppercase(),
+            reason: "malware",
+            purge: true,
+        };
+
+        let response = server.post("/admin/blacklist").json(&body).await;
+        assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+        let response = server
+            .post("/admin/blacklist")
+            .add_header(AUTHORIZATION, bearer("wrong-token"))
+            .json(&body)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::UNAUTHORIZED);
+
+        let response = server
+            .post("/admin/blacklist")
+            .add_header(AUTHORIZATION, bearer(TOKEN))
+            .json(&body)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::CREATED);
+        let entry: Entry = response.json();
+        assert_eq!(entry.hash, hash);
+        assert_eq!(entry.added_by, "moderator");
+        assert_eq!(entry.purged, Some(1));
+
+        let response = server.get(&format!("/download/{}", existing.id)).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        let response = upload(&server).await;
+        let body: HashMap<String, String> = response.json();
+        assert_eq!(body["errorCode"], "file-blacklist");
+
+        let entries: Vec<Entry> = server
+            .get("/admin/blacklist")
+            .add_header(AUTHORIZATION, bearer(TOKEN))
+            .await
+            .json();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].reason.as_deref(), Some("malware"));
+
+        let response = server
+            .delete(&format!("/admin/blacklist/{hash}"))
+            .add_header(AUTHORIZATION, bearer(TOKEN))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+
+        let response = upload(&server).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        Ok(())
+    }
+}
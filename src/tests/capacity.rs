@@ -0,0 +1,58 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::http::{HeaderValue, StatusCode};
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestResponse, TestServer,
+    };
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, StorageConfig},
+        router,
+        routes::tus::{TUS_RESUMABLE, UPLOAD_LENGTH, UPLOAD_METADATA},
+        storage, CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    async fn upload(server: &TestServer, len: usize) -> TestResponse {
+        let form = MultipartForm::new().add_part("file", Part::bytes(vec![7; len]).file_name("big.bin"));
+        server.post("/upload").multipart(form).await
+    }
+
+    #[sqlx::test]
+    async fn rejects_uploads_over_storage_budget(db: PgPool) -> TestResult {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        config.general.storage_budget_bytes = Some(1000);
+        let storage = storage::from_config(&config)?;
+        let server = TestServer::new(router(config, db, storage))?;
+
+        let response = upload(&server, 600).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let response = upload(&server, 600).await;
+        assert_eq!(response.status_code(), StatusCode::INSUFFICIENT_STORAGE);
+        let body: HashMap<String, String> = response.json();
+        assert_eq!(body["errorCode"], "storage-full");
+
+        // resumable uploads reserve their whole length up front
+        let response = server
+            .post("/tus")
+            .add_header(TUS_RESUMABLE, HeaderValue::from_static("1.0.0"))
+            .add_header(UPLOAD_LENGTH, HeaderValue::from(500))
+            // base64 of `big.bin`
+            .add_header(UPLOAD_METADATA, HeaderValue::from_static("filename YmlnLmJpbg=="))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::INSUFFICIENT_STORAGE);
+
+        let stats: HashMap<String, u64> = server.get("/stats").await.json();
+        assert_eq!(stats["storedBytes"], 600);
+        assert_eq!(stats["storageBudgetBytes"], 1000);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestServer,
+    };
+    use serde::Deserialize;
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, StorageConfig},
+        reaper::purge_expired,
+        router,
+        routes::upload::UploadResponse,
+        storage::{self, MemoryStorage},
+        CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct CollectionInfo {
+        bytes: i64,
+        files: Vec<MemberInfo>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct MemberInfo {
+        id: String,
+        file_name: String,
+        collection_id: String,
+    }
+
+    async fn server(db: PgPool) -> anyhow::Result<TestServer> {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        let storage = storage::from_config(&config)?;
+        TestServer::new(router(config, db, storage))
+    }
+
+    fn form() -> MultipartForm {
+        MultipartForm::new()
+            .add_part("file", Part::bytes(b"first file".as_slice()).file_name("first.txt"))
+            .add_part("file", Part::bytes(b"second".as_slice()).file_name("second.txt"))
+    }
+
+    #[sqlx::test]
+    async fn encrypted_collection(db: PgPool) -> TestResult {
+        let server = server(db).await?;
+
+        let response = server
+            .post("/upload")
+            .add_query_param("encrypt", true)
+            .multipart(form())
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let collection: UploadResponse = response.json();
+        let key = collection.decryption_key.clone().unwrap();
+        assert_eq!(collection.files.len(), 2);
+
+        let response = server
+            .get(&format!("/info/{}", collection.id))
+            .add_query_param("key", &key)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let info: CollectionInfo = response.json();
+        assert_eq!(info.bytes, 16);
+        let names = info.files.iter().map(|file| file.file_name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["first.txt", "second.txt"]);
+        assert!(info.files.iter().all(|file| file.collection_id == collection.id));
+        assert_eq!(info.files[0].id, collection.files[0].id);
+
+        let response = server.get(&format!("/info/{}", collection.id)).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        // every member shares the key, but is downloaded on its own
+        for (file, content) in collection.files.iter().zip([b"first file".as_slice(), b"second"]) {
+            let response = server
+                .get(&format!("/download/{}", file.id))
+                .add_query_param("key", &key)
+                .await;
+            assert_eq!(response.status_code(), StatusCode::OK);
+            assert_eq!(response.as_bytes().as_ref(), content);
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn delete_collection(db: PgPool) -> TestResult {
+        let server = server(db.clone()).await?;
+
+        let collection: UploadResponse = server.post("/upload").multipart(form()).await.json();
+
+        let response = server
+            .delete(&format!("/delete/{}", collection.id))
+            .add_query_param("key", "wrong")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        let response = server
+            .delete(&format!("/delete/{}", collection.id))
+            .add_query_param("key", &collection.delete_key)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+
+        for file in &collection.files {
+            let response = server.get(&format!("/info/{}", file.id)).await;
+            assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        }
+        let response = server.get(&format!("/info/{}", collection.id)).await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn single_file_is_not_a_collection(db: PgPool) -> TestResult {
+        let server = server(db.clone()).await?;
+
+        let form = MultipartForm::new().add_part("file", Part::bytes(b"alone".as_slice()).file_name("alone.txt"));
+        let response = server.post("/upload").multipart(form).await;
+        assert!(!response.text().contains("files"));
+
+        let count = sqlx::query_scalar!("SELECT COUNT(*) FROM collections")
+            .fetch_one(&db)
+            .await?;
+        assert_eq!(count, Some(0));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn reaper_removes_empty_collections(db: PgPool) -> TestResult {
+        sqlx::query!("INSERT INTO collections (id, delete_key) VALUES ('empty', '')")
+            .execute(&db)
+            .await?;
+
+        purge_expired(&db, &MemoryStorage::default()).await?;
+
+        let count = sqlx::query_scalar!("SELECT COUNT(*) FROM collections")
+            .fetch_one(&db)
+            .await?;
+        assert_eq!(count, Some(0));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use axum::http::{
+        header::{CONTENT_TYPE, LOCATION},
+        HeaderValue, StatusCode,
+    };
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestServer,
+    };
+    use bytes::Bytes;
+    use image::{ImageFormat, Rgb, RgbImage};
+    use serde_json::Value;
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, StorageConfig},
+        router,
+        routes::{
+            tus::{TUS_RESUMABLE, UPLOAD_LENGTH, UPLOAD_METADATA, UPLOAD_OFFSET},
+            upload::UploadResponse,
+        },
+        storage, CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    async fn server(db: PgPool) -> anyhow::Result<TestServer> {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        let storage = storage::from_config(&config)?;
+        TestServer::new(router(config, db, storage))
+    }
+
+    fn png() -> anyhow::Result<Vec<u8>> {
+        let mut encoded = Cursor::new(Vec::new());
+        RgbImage::from_pixel(8, 8, Rgb([0, 0, 0])).write_to(&mut encoded, ImageFormat::Png)?;
+        Ok(encoded.into_inner())
+    }
+
+    async fn upload(server: &TestServer, part: Part, encrypt: bool) -> UploadResponse {
+        let form = MultipartForm::new().add_part("file", part);
+        server
+            .post("/upload")
+            .add_query_param("encrypt", encrypt)
+            .multipart(form)
+            .await
+            .json()
+    }
+
+    async fn content_type(server: &TestServer, upload: &UploadResponse) -> Value {
+        let mut request = server.get(&format!("/info/{}", upload.id));
+        if let Some(key) = &upload.decryption_key {
+            request = request.add_query_param("key", key);
+        }
+        let info: Value = request.await.json();
+        info["contentType"].clone()
+    }
+
+    #[sqlx::test]
+    async fn content_type_is_detected_once_and_served(db: PgPool) -> TestResult {
+        let server = server(db).await?;
+
+        // content wins over what the client says and over the extension
+        let picture = upload(
+            &server,
+            Part::bytes(png()?)
+                .file_name("picture.txt")
+                .mime_type("text/plain"),
+            false,
+        )
+        .await;
+        assert_eq!(content_type(&server, &picture).await, "image/png");
+        let response = server.get(&format!("/download/{}", picture.id)).await;
+        assert_eq!(response.header(CONTENT_TYPE), "image/png");
+
+        // encrypted content is recognized before it's encrypted
+        let encrypted = upload(&server, Part::bytes(png()?).file_name("picture"), true).await;
+        assert_eq!(content_type(&server, &encrypted).await, "image/png");
+
+        let declared = upload(
+            &server,
+            Part::bytes(b"a,b\n1,2\n".as_slice())
+                .file_name("table")
+                .mime_type("text/csv"),
+            false,
+        )
+        .await;
+        assert_eq!(content_type(&server, &declared).await, "text/csv");
+
+        // octet-stream doesn't say anything, so the extension does
+        let guessed = upload(
+            &server,
+            Part::bytes(b"# notes".as_slice()).file_name("notes.md"),
+            false,
+        )
+        .await;
+        assert_eq!(content_type(&server, &guessed).await, "text/markdown");
+
+        let unknown = upload(
+            &server,
+            Part::bytes(b"\x00\x9f\x92\x96".as_slice()).file_name("blob"),
+            false,
+        )
+        .await;
+        assert_eq!(content_type(&server, &unknown).await, Value::Null);
+        let response = server.get(&format!("/download/{}", unknown.id)).await;
+        assert!(response.maybe_header(CONTENT_TYPE).is_none());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn resumable_uploads_detect_content_type_from_first_chunk(db: PgPool) -> TestResult {
+        let mut server = server(db).await?;
+        server.add_header(TUS_RESUMABLE, HeaderValue::from_static("1.0.0"));
+
+        let data = png()?;
+        // `pic.dat` that claims to be text/plain
+        let response = server
+            .post("/tus")
+            .add_header(UPLOAD_LENGTH, HeaderValue::from(data.len()))
+            .add_header(
+                UPLOAD_METADATA,
+                HeaderValue::from_static("filename cGljLmRhdA==,filetype dGV4dC9wbGFpbg=="),
+            )
+            .await;
+        let location = response.header(LOCATION).to_str()?.to_string();
+        let upload: UploadResponse = response.json();
+
+        let response = server
+            .patch(&location)
+            .add_header(UPLOAD_OFFSET, HeaderValue::from(0))
+            .content_type("application/offset+octet-stream")
+            .bytes(Bytes::from(data))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+
+        assert_eq!(content_type(&server, &upload).await, "image/png");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::http::StatusCode;
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestServer,
+    };
+    use sqlx::PgPool;
+
+    use crate::{
+        config::load_config,
+        router,
+        routes::upload::UploadResponse,
+        storage::{MemoryStorage, Storage},
+        CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    const CONTENT: &[u8] = b"the same iso over and over";
+
+    async fn upload(server: &TestServer, file_name: &str) -> UploadResponse {
+        let form = MultipartForm::new().add_part("file", Part::bytes(CONTENT).file_name(file_name));
+        server.post("/upload").multipart(form).await.json()
+    }
+
+    #[sqlx::test]
+    async fn identical_uploads_share_blob(db: PgPool) -> TestResult {
+        let config = load_config(CONFIG_PATH).await?;
+        let storage = Arc::new(MemoryStorage::default());
+        let server = TestServer::new(router(config, db.clone(), storage.clone()))?;
+
+        let first = upload(&server, "first.iso").await;
+        let second = upload(&server, "second.iso").await;
+
+        let hash = sha256::digest(CONTENT);
+        let refs = sqlx::query_scalar!("SELECT refs FROM blobs WHERE hash = $1", hash)
+            .fetch_one(&db)
+            .await?;
+        assert_eq!(refs, 2);
+        assert!(storage.exists(&hash).await?);
+        assert!(!storage.exists(&first.id).await?);
+
+        // metadata stays with every upload
+        let response = server
+            .delete(&format!("/delete/{}", first.id))
+            .add_query_param("key", &first.delete_key)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+        assert!(storage.exists(&hash).await?);
+
+        let response = server.get(&format!("/download/{}", second.id)).await;
+        assert_eq!(response.as_bytes().as_ref(), CONTENT);
+
+        // content goes away with its last upload
+        let response = server
+            .delete(&format!("/delete/{}", second.id))
+            .add_query_param("key", &second.delete_key)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+        assert!(!storage.exists(&hash).await?);
+        let blobs = sqlx::query_scalar!("SELECT COUNT(*) FROM blobs")
+            .fetch_one(&db)
+            .await?;
+        assert_eq!(blobs, Some(0));
+
+        // and the content can be uploaded again
+        let third = upload(&server, "third.iso").await;
+        let response = server.get(&format!("/download/{}", third.id)).await;
+        assert_eq!(response.as_bytes().as_ref(), CONTENT);
+
+        Ok(())
+    }
+}
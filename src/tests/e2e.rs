@@ -0,0 +1,126 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::http::StatusCode;
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestResponse, TestServer,
+    };
+    use chacha20poly1305::{aead::stream::EncryptorBE32, XChaCha20Poly1305};
+    use serde::Deserialize;
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, StorageConfig},
+        router,
+        routes::upload::UploadResponse,
+        storage, CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    const CHUNK_SIZE: usize = 1024;
+    const NONCE: [u8; 19] = [7; 19];
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Info {
+        bytes: i64,
+        e2e: E2eInfo,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct E2eInfo {
+        version: i16,
+        chunk_size: i32,
+        nonce: String,
+    }
+
+    async fn server(db: PgPool) -> anyhow::Result<TestServer> {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        let storage = storage::from_config(&config)?;
+        TestServer::new(router(config, db, storage))
+    }
+
+    /// Encrypts the way a client would, following the documented format.
+    fn client_encrypt(plaintext: &[u8]) -> Vec<u8> {
+        let key = [3u8; 32];
+        let mut encryptor = EncryptorBE32::<XChaCha20Poly1305>::new(key.as_ref().into(), NONCE.as_ref().into());
+
+        let mut blob = vec![1];
+        blob.extend_from_slice(&(CHUNK_SIZE as u32).to_be_bytes());
+        blob.extend_from_slice(&NONCE);
+
+        let mut chunks = plaintext.chunks_exact(CHUNK_SIZE);
+        for chunk in chunks.by_ref() {
+            blob.extend(encryptor.encrypt_next(chunk).unwrap());
+        }
+        blob.extend(encryptor.encrypt_last(chunks.remainder()).unwrap());
+        blob
+    }
+
+    async fn upload(server: &TestServer, blob: Vec<u8>) -> TestResponse {
+        let form = MultipartForm::new().add_part("file", Part::bytes(blob).file_name("opaque.bin"));
+        server.post("/upload").add_query_param("e2e", true).multipart(form).await
+    }
+
+    fn error_code(response: &TestResponse) -> String {
+        let body: HashMap<String, String> = response.json();
+        body["errorCode"].clone()
+    }
+
+    #[sqlx::test]
+    async fn opaque_upload_roundtrip(db: PgPool) -> TestResult {
+        let server = server(db).await?;
+        let blob = client_encrypt(&[42; 3 * CHUNK_SIZE]);
+
+        let upload: UploadResponse = upload(&server, blob.clone()).await.json();
+        assert!(upload.decryption_key.is_none());
+
+        let response = server.get(&format!("/info/{}", upload.id)).await;
+        let info: Info = response.json();
+        assert_eq!(info.bytes, 3 * CHUNK_SIZE as i64);
+        assert_eq!(info.e2e.version, 1);
+        assert_eq!(info.e2e.chunk_size, CHUNK_SIZE as i32);
+        assert_eq!(info.e2e.nonce, hex::encode(NONCE));
+
+        let response = server.get(&format!("/download/{}", upload.id)).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.as_bytes().as_ref(), blob.as_slice());
+
+        let response = server
+            .get(&format!("/download/{}", upload.id))
+            .add_query_param("key", "00")
+            .await;
+        assert_eq!(error_code(&response), "key-not-accepted");
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn rejects_malformed_uploads(db: PgPool) -> TestResult {
+        let server = server(db).await?;
+
+        // last chunk can't be shorter than the tag
+        let mut blob = client_encrypt(&[42; 100]);
+        blob.truncate(blob.len() - 110);
+        assert_eq!(error_code(&upload(&server, blob).await), "validation");
+
+        let mut blob = client_encrypt(&[42; 100]);
+        blob[0] = 2;
+        assert_eq!(error_code(&upload(&server, blob).await), "validation");
+
+        let response = server
+            .post("/upload")
+            .add_query_param("e2e", true)
+            .add_query_param("encrypt", true)
+            .multipart(MultipartForm::new().add_part("file", Part::bytes(client_encrypt(b"")).file_name("a")))
+            .await;
+        assert_eq!(error_code(&response), "validation");
+
+        Ok(())
+    }
+}
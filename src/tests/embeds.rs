@@ -0,0 +1,141 @@
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use axum::http::{header::CONTENT_TYPE, StatusCode};
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestServer,
+    };
+    use image::{ImageFormat, Rgb, RgbImage};
+    use serde_json::Value;
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, StorageConfig},
+        router,
+        routes::upload::UploadResponse,
+        storage, CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    const BASE: &str = "https://files.example";
+
+    async fn server(db: PgPool) -> anyhow::Result<TestServer> {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        // trailing slash isn't repeated in links
+        config.general.public_url = Some(format!("{BASE}/"));
+        let storage = storage::from_config(&config)?;
+        TestServer::new(router(config, db, storage))
+    }
+
+    fn png(width: u32, height: u32) -> anyhow::Result<Vec<u8>> {
+        let mut encoded = Cursor::new(Vec::new());
+        RgbImage::from_pixel(width, height, Rgb([0, 0, 0])).write_to(&mut encoded, ImageFormat::Png)?;
+        Ok(encoded.into_inner())
+    }
+
+    fn mp4() -> Vec<u8> {
+        let mut data = b"\x00\x00\x00\x18ftypisom\x00\x00\x02\x00isomiso2".to_vec();
+        data.resize(1024, 0);
+        data
+    }
+
+    async fn upload(server: &TestServer, data: Vec<u8>, file_name: &str, embedded: bool, encrypt: bool) -> UploadResponse {
+        let form = MultipartForm::new().add_part("file", Part::bytes(data).file_name(file_name));
+        server
+            .post("/upload")
+            .add_query_param("embedded", embedded)
+            .add_query_param("encrypt", encrypt)
+            .multipart(form)
+            .await
+            .json()
+    }
+
+    async fn oembed(server: &TestServer, upload_id: &str, max_width: Option<u32>) -> Value {
+        let mut request = server
+            .get("/oembed")
+            .add_query_param("url", format!("{BASE}/embed/{upload_id}"));
+        if let Some(max_width) = max_width {
+            request = request.add_query_param("maxwidth", max_width);
+        }
+        request.await.json()
+    }
+
+    #[sqlx::test]
+    async fn images_unfurl_as_their_thumbnail(db: PgPool) -> TestResult {
+        let server = server(db).await?;
+        let upload = upload(&server, png(3000, 1500)?, "<cat>.png", true, false).await;
+
+        let response = server.get(&format!("/embed/{}", upload.id)).await;
+        assert_eq!(response.header(CONTENT_TYPE), "text/html; charset=utf-8");
+        let page = response.text();
+        assert!(page.contains(&format!(r#"<meta property="og:image" content="{BASE}/preview/{}?w=1024&amp;format=jpeg">"#, upload.id)));
+        assert!(page.contains(r#"<meta property="og:image:width" content="1024">"#));
+        assert!(page.contains(r#"<meta property="og:image:height" content="512">"#));
+        assert!(page.contains(r#"<meta name="twitter:card" content="summary_large_image">"#));
+        assert!(page.contains("<title>&lt;cat&gt;.png</title>"));
+        assert!(page.contains(&format!("{BASE}/oembed?format=json&amp;url=https%3A%2F%2Ffiles.example%2Fembed%2F{}", upload.id)));
+
+        // largest thumbnail that fits is linked
+        let oembed = oembed(&server, &upload.id, Some(300)).await;
+        assert_eq!(oembed["type"], "photo");
+        assert_eq!(oembed["url"], format!("{BASE}/preview/{}?w=256&h=2048&format=jpeg", upload.id));
+        assert_eq!(oembed["width"], 256);
+        assert_eq!(oembed["height"], 128);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn videos_unfurl_as_a_player(db: PgPool) -> TestResult {
+        let server = server(db).await?;
+        let upload = upload(&server, mp4(), "clip.mp4", true, false).await;
+
+        let page = server.get(&format!("/embed/{}", upload.id)).await.text();
+        assert!(page.contains(&format!(r#"<meta property="og:video" content="{BASE}/preview/{}">"#, upload.id)));
+        assert!(page.contains(r#"<meta property="og:video:type" content="video/mp4">"#));
+        assert!(page.contains(r#"<meta name="twitter:card" content="player">"#));
+
+        let oembed = oembed(&server, &upload.id, Some(320)).await;
+        assert_eq!(oembed["type"], "video");
+        assert_eq!(oembed["width"], 320);
+        assert_eq!(oembed["height"], 180);
+        assert!(oembed["html"].as_str().unwrap().contains(&format!(r#"src="{BASE}/embed/{}""#, upload.id)));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn only_embedded_uploads_unfurl(db: PgPool) -> TestResult {
+        let server = server(db).await?;
+
+        let hidden = upload(&server, png(8, 8)?, "cat.png", false, false).await;
+        let response = server.get(&format!("/embed/{}", hidden.id)).await;
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+        let body: Value = response.json();
+        assert_eq!(body["errorCode"], "embed-not-enabled");
+        let response = server
+            .get("/oembed")
+            .add_query_param("url", format!("{BASE}/embed/{}", hidden.id))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+
+        // key would have to be in the links to show anything
+        let encrypted = upload(&server, png(8, 8)?, "cat.png", true, true).await;
+        let page = server.get(&format!("/embed/{}", encrypted.id)).await.text();
+        assert!(!page.contains("og:image"));
+        assert!(page.contains(r#"<meta name="twitter:card" content="summary">"#));
+        assert_eq!(oembed(&server, &encrypted.id, None).await["type"], "link");
+
+        let response = server
+            .get("/oembed")
+            .add_query_param("url", format!("https://elsewhere.example/embed/{}", encrypted.id))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+}
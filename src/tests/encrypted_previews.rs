@@ -0,0 +1,79 @@
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::Arc};
+
+    use axum::http::{header::CONTENT_TYPE, HeaderValue, StatusCode};
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestServer,
+    };
+    use image::{ImageFormat, Rgba, RgbaImage};
+    use serde_json::Value;
+    use sqlx::PgPool;
+
+    use crate::{
+        config::load_config,
+        router,
+        routes::{preview::DECRYPTION_KEY, upload::UploadResponse},
+        storage::MemoryStorage,
+        CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    #[sqlx::test]
+    async fn encrypted_uploads_are_previewed_with_their_key(db: PgPool) -> TestResult {
+        let config = load_config(CONFIG_PATH).await?;
+        let storage = Arc::new(MemoryStorage::default());
+        let server = TestServer::new(router(config, db.clone(), storage))?;
+
+        let mut content = Cursor::new(Vec::new());
+        RgbaImage::from_pixel(300, 150, Rgba([10, 120, 200, 255])).write_to(&mut content, ImageFormat::Png)?;
+        let content = content.into_inner();
+        let form = MultipartForm::new().add_part("file", Part::bytes(content.clone()).file_name("screenshot.png"));
+        let upload: UploadResponse = server
+            .post("/upload")
+            .add_query_param("encrypt", true)
+            .multipart(form)
+            .await
+            .json();
+        let key = upload.decryption_key.unwrap();
+
+        let response = server.get(&format!("/preview/{}", upload.id)).await;
+        let error: Value = response.json();
+        assert_eq!(error["errorCode"], "missing-key");
+
+        let response = server
+            .get(&format!("/preview/{}", upload.id))
+            .add_query_param("key", "00".repeat(32))
+            .await;
+        let error: Value = response.json();
+        assert_eq!(error["errorCode"], "invalid-decryption-key");
+
+        // type is sniffed from the decrypted content
+        let response = server
+            .get(&format!("/preview/{}", upload.id))
+            .add_query_param("key", &key)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.header(CONTENT_TYPE), "image/png");
+        assert_eq!(response.as_bytes().as_ref(), content.as_slice());
+
+        let response = server
+            .get(&format!("/preview/{}", upload.id))
+            .add_header(DECRYPTION_KEY, HeaderValue::from_str(&key)?)
+            .add_query_param("w", 64)
+            .await;
+        assert_eq!(response.header(CONTENT_TYPE), "image/webp");
+        let thumbnail = image::load_from_memory(response.as_bytes())?;
+        assert_eq!((thumbnail.width(), thumbnail.height()), (64, 32));
+
+        // decrypted content never ends up in storage
+        let thumbnails = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM thumbnails"#)
+            .fetch_one(&db)
+            .await?;
+        assert_eq!(thumbnails, 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use sqlx::PgPool;
+    use tokio::fs::File;
+
+    use crate::{errors::AppResult, reaper::purge_expired, storage::LocalStorage};
+
+    const STORAGE_DIR: &str = "src/tests/storage/";
+
+    #[sqlx::test]
+    async fn purge_expired_uploads(db: PgPool) -> AppResult<()> {
+        sqlx::query!("INSERT INTO uploads (id, delete_key, file_name, bytes, expiry_hours, created_at, embedded) VALUES ('expired1', '', 'expired', 0, 1, NOW() - INTERVAL '2 hours', FALSE)")
+            .execute(&db)
+            .await?;
+        sqlx::query!("INSERT INTO uploads (id, delete_key, file_name, bytes, downloads, expiry_downloads, embedded) VALUES ('expired2', '', 'expired', 0, 3, 3, FALSE)")
+            .execute(&db)
+            .await?;
+        sqlx::query!("INSERT INTO uploads (id, delete_key, file_name, bytes, expiry_hours, embedded) VALUES ('fresh', '', 'fresh', 0, 1, FALSE)")
+            .execute(&db)
+            .await?;
+
+        for id in ["expired1", "expired2", "fresh"] {
+            File::create(format!("{STORAGE_DIR}{id}")).await?;
+        }
+
+        let purged = purge_expired(&db, &LocalStorage::new(STORAGE_DIR)).await?;
+        assert_eq!(purged, 2);
+
+        let remaining = sqlx::query_scalar!("SELECT id FROM uploads")
+            .fetch_all(&db)
+            .await?;
+        assert_eq!(remaining, vec!["fresh".to_string()]);
+
+        assert!(!Path::new(&format!("{STORAGE_DIR}expired1")).exists());
+        assert!(!Path::new(&format!("{STORAGE_DIR}expired2")).exists());
+        assert!(Path::new(&format!("{STORAGE_DIR}fresh")).exists());
+
+        tokio::fs::remove_file(format!("{STORAGE_DIR}fresh")).await?;
+
+        Ok(())
+    }
+}
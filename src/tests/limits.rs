@@ -0,0 +1,144 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::{
+        body::Body,
+        http::{header::{CONTENT_TYPE, RETRY_AFTER}, HeaderName, HeaderValue, Request, StatusCode},
+    };
+    use axum_test::{TestResponse, TestServer};
+    use futures::stream;
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    use crate::{
+        config::{load_config, LimitsConfig, RateLimitConfig, StorageConfig},
+        ratelimit::RATELIMIT_REMAINING,
+        router,
+        routes::tus::{TUS_RESUMABLE, UPLOAD_LENGTH, UPLOAD_METADATA},
+        storage, CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    const FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+    async fn server(db: PgPool, limits: LimitsConfig) -> anyhow::Result<TestServer> {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        config.limits = LimitsConfig {
+            trust_forwarded_for: true,
+            ..limits
+        };
+        let storage = storage::from_config(&config)?;
+        TestServer::new(router(config, db, storage))
+    }
+
+    fn error_code(response: &TestResponse) -> String {
+        let body: HashMap<String, String> = response.json();
+        body["errorCode"].clone()
+    }
+
+    #[sqlx::test]
+    async fn limits_requests_per_client(db: PgPool) -> TestResult {
+        let limits = LimitsConfig {
+            info: Some(RateLimitConfig {
+                burst: 2,
+                per_minute: 1,
+            }),
+            ..Default::default()
+        };
+        let server = server(db, limits).await?;
+        let info = |client: &'static str| {
+            server
+                .get("/info/missing")
+                .add_header(FORWARDED_FOR, HeaderValue::from_static(client))
+        };
+
+        let response = info("10.0.0.1").await;
+        assert_eq!(response.header(RATELIMIT_REMAINING), "1");
+        let response = info("10.0.0.1").await;
+        assert_eq!(response.header(RATELIMIT_REMAINING), "0");
+
+        let response = info("10.0.0.1").await;
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(error_code(&response), "rate-limited");
+        assert_eq!(response.header(RETRY_AFTER), "60");
+
+        // only the last address is the one that connected to the proxy
+        let response = info("10.0.0.1, 10.0.0.2").await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        // other groups aren't affected
+        let response = server
+            .get("/download/missing")
+            .add_header(FORWARDED_FOR, HeaderValue::from_static("10.0.0.1"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn limits_uploaded_bytes_per_day(db: PgPool) -> TestResult {
+        let limits = LimitsConfig {
+            upload_quota_bytes: Some(100),
+            ..Default::default()
+        };
+        let server = server(db, limits).await?;
+        let create = |client: &'static str| {
+            server
+                .post("/tus")
+                .add_header(TUS_RESUMABLE, HeaderValue::from_static("1.0.0"))
+                .add_header(UPLOAD_LENGTH, HeaderValue::from(60))
+                // base64 of `quota.bin`
+                .add_header(UPLOAD_METADATA, HeaderValue::from_static("filename cXVvdGEuYmlu"))
+                .add_header(FORWARDED_FOR, HeaderValue::from_static(client))
+        };
+
+        let response = create("10.0.0.1").await;
+        assert_eq!(response.status_code(), StatusCode::CREATED);
+
+        let response = create("10.0.0.1").await;
+        assert_eq!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(error_code(&response), "quota-exceeded");
+        assert_eq!(response.header(RATELIMIT_REMAINING), "40");
+        assert!(response.maybe_header(RETRY_AFTER).is_some());
+
+        let response = create("10.0.0.2").await;
+        assert_eq!(response.status_code(), StatusCode::CREATED);
+
+        Ok(())
+    }
+    #[sqlx::test]
+    async fn limits_uploads_without_a_length(db: PgPool) -> TestResult {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        config.limits = LimitsConfig {
+            trust_forwarded_for: true,
+            upload_quota_bytes: Some(100),
+            ..Default::default()
+        };
+        let storage = storage::from_config(&config)?;
+
+        // streamed body is sent chunked, so only what's received says how big it is
+        let parts = [
+            "--X\r\nContent-Disposition: form-data; name=\"file\"; filename=\"big.bin\"\r\n\r\n".to_string(),
+            "a".repeat(150),
+            "\r\n--X--\r\n".to_string(),
+        ];
+        let body = Body::from_stream(stream::iter(parts.map(Ok::<_, std::io::Error>)));
+        let request = Request::post("/upload")
+            .header(CONTENT_TYPE, "multipart/form-data; boundary=X")
+            .header(FORWARDED_FOR, "10.0.0.1")
+            .body(body)?;
+
+        let response = router(config, db, storage).oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        let body: HashMap<String, String> = serde_json::from_slice(&body)?;
+        assert_eq!(body["errorCode"], "quota-exceeded");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+#[cfg(test)]
+mod tests {
+    use axum::http::{header::CONTENT_TYPE, StatusCode};
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestServer,
+    };
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, StorageConfig},
+        metrics, router,
+        routes::upload::UploadResponse,
+        storage, CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    #[sqlx::test]
+    async fn exposes_prometheus_metrics(db: PgPool) -> TestResult {
+        // recorder is shared by every test, so it has to be there before anything is recorded
+        metrics::handle();
+
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        config.metrics.enabled = true;
+        let storage = storage::from_config(&config)?;
+        let server = TestServer::new(router(config, db, storage))?;
+
+        let form = MultipartForm::new().add_part("file", Part::bytes(b"measured".as_slice()).file_name("metrics.txt"));
+        let upload: UploadResponse = server
+            .post("/upload")
+            .add_query_param("encrypt", true)
+            .multipart(form)
+            .await
+            .json();
+        let response = server
+            .get(&format!("/download/{}", upload.id))
+            .add_query_param("key", upload.decryption_key.unwrap())
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let response = server.get("/metrics").await;
+        assert!(response.header(CONTENT_TYPE).to_str()?.starts_with("text/plain"));
+        let text = response.text();
+        for name in [
+            "uploaded_bytes_total",
+            "downloaded_bytes_total",
+            "encrypted_bytes_total",
+            "decrypted_bytes_total",
+            "uploads_in_flight",
+            "db_pool_connections",
+            "http_request_duration_seconds_bucket",
+        ] {
+            assert!(text.contains(name), "{name} is missing");
+        }
+        // routes are labelled by their pattern, not by the id in the path
+        assert!(text.contains(r#"route="/download/:upload_id""#));
+        assert!(!text.contains(&upload.id));
+
+        Ok(())
+    }
+}
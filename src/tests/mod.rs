@@ -1 +1,23 @@
+mod accounts;
+mod admin;
+mod archives;
+mod backends;
+mod blacklist;
+mod capacity;
+mod collections;
+mod content_types;
+mod dedup;
+mod e2e;
+mod embeds;
+mod encrypted_previews;
+mod expiry;
+mod limits;
+mod metrics;
+mod openapi;
+mod passwords;
+mod ranges;
+mod stats;
+mod text_previews;
+mod thumbnails;
+mod tus;
 mod uploads;
@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use axum_test::TestServer;
+    use serde_json::Value;
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, StorageConfig},
+        errors::AppError,
+        router, storage, CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    #[sqlx::test]
+    async fn describes_routes_and_errors(db: PgPool) -> TestResult {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        let storage = storage::from_config(&config)?;
+        let server = TestServer::new(router(config, db, storage))?;
+
+        let document: Value = server.get("/openapi.json").await.json();
+        assert!(document["openapi"].as_str().unwrap().starts_with("3.1"));
+
+        let paths = &document["paths"];
+        for path in ["/upload", "/download/{upload_id}", "/tus/{session_id}", "/me/uploads", "/admin/blacklist"] {
+            assert!(paths.get(path).is_some(), "{path} is missing");
+        }
+        assert!(paths["/tus/{session_id}"].get("patch").is_some());
+        assert!(paths["/me"]["get"]["security"].is_array());
+
+        // errors are documented next to the success of operations that can return them, by their code
+        let upload = &paths["/upload"]["post"]["responses"];
+        assert!(upload.get("200").is_some());
+        assert!(upload["413"]["content"]["application/json"]["examples"].get("file-too-large").is_some());
+        assert!(upload["507"]["content"]["application/json"]["examples"].get("storage-full").is_some());
+        assert!(document["components"]["schemas"].get("ErrorResponse").is_some());
+
+        let health = paths["/health"]["get"]["responses"].as_object().unwrap();
+        assert_eq!(health.keys().collect::<Vec<_>>(), ["200"]);
+        assert!(paths["/stats"]["get"]["responses"].get("413").is_none());
+
+        Ok(())
+    }
+    #[sqlx::test]
+    async fn documents_every_error(db: PgPool) -> TestResult {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        let storage = storage::from_config(&config)?;
+        let server = TestServer::new(router(config, db, storage))?;
+
+        let document: Value = server.get("/openapi.json").await.json();
+        let operations = document["paths"].as_object().unwrap().values().flat_map(|item| item.as_object().unwrap().values());
+        let errors = operations
+            .filter_map(|operation| operation["responses"].as_object())
+            .flat_map(|responses| responses.iter())
+            .filter(|(status, _)| status.as_str() >= "400");
+
+        let mut documented = HashSet::new();
+        for (status, response) in errors {
+            // every code that's named has an example, so it exists and has that status
+            let description = response["description"].as_str().unwrap();
+            let codes = description.split('`').skip(1).step_by(2).collect::<Vec<_>>();
+            let examples = response["content"]["application/json"]["examples"].as_object().unwrap();
+            assert_eq!(examples.len(), codes.len(), "{status} `{description}` has a code that doesn't fit");
+            documented.extend(examples.keys().cloned());
+        }
+
+        for error in AppError::examples() {
+            assert!(documented.contains(error.error_code()), "{} isn't documented", error.error_code());
+        }
+
+        Ok(())
+    }
+}
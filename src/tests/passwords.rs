@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use axum::http::StatusCode;
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestServer,
+    };
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, StorageConfig},
+        router,
+        routes::upload::UploadResponse,
+        storage, CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    const CONTENT: &[u8] = b"protected with a password";
+
+    async fn server(db: PgPool) -> anyhow::Result<TestServer> {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        let storage = storage::from_config(&config)?;
+        TestServer::new(router(config, db, storage))
+    }
+
+    fn error_code(response: &axum_test::TestResponse) -> String {
+        let body: std::collections::HashMap<String, String> = response.json();
+        body["errorCode"].clone()
+    }
+
+    #[sqlx::test]
+    async fn password_protected_upload(db: PgPool) -> TestResult {
+        let server = server(db).await?;
+
+        let form = MultipartForm::new()
+            .add_text("password", "correct horse")
+            .add_part("file", Part::bytes(CONTENT).file_name("secret.txt"));
+        let upload: UploadResponse = server.post("/upload").multipart(form).await.json();
+        assert!(upload.decryption_key.is_none());
+
+        let info = format!("/info/{}", upload.id);
+        let response = server.get(&info).await;
+        assert_eq!(error_code(&response), "missing-password");
+
+        let response = server.get(&info).add_query_param("password", "wrong horse").await;
+        assert_eq!(error_code(&response), "invalid-password");
+
+        let response = server.get(&info).add_query_param("password", "correct horse").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let response = server
+            .get(&format!("/download/{}", upload.id))
+            .add_query_param("password", "correct horse")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.as_bytes().as_ref(), CONTENT);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn password_protected_collection(db: PgPool) -> TestResult {
+        let server = server(db).await?;
+
+        let form = MultipartForm::new()
+            .add_text("password", "hunter2")
+            .add_part("file", Part::bytes(CONTENT).file_name("first.txt"))
+            .add_part("file", Part::bytes(CONTENT).file_name("second.txt"));
+        let collection: UploadResponse = server.post("/upload").multipart(form).await.json();
+
+        let response = server
+            .get(&format!("/info/{}", collection.id))
+            .add_query_param("password", "hunter2")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let response = server
+            .get(&format!("/download/{}", collection.id))
+            .add_query_param("password", "hunter2")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let archive = ::zip::ZipArchive::new(Cursor::new(response.as_bytes().to_vec()))?;
+        assert_eq!(archive.len(), 2);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn password_after_file_is_rejected(db: PgPool) -> TestResult {
+        let server = server(db.clone()).await?;
+
+        let form = MultipartForm::new()
+            .add_part("file", Part::bytes(CONTENT).file_name("early.txt"))
+            .add_text("password", "too late");
+        let response = server.post("/upload").multipart(form).await;
+        assert_eq!(error_code(&response), "validation");
+
+        // the file that was already saved doesn't stay behind unencrypted
+        let uploads = sqlx::query_scalar!("SELECT COUNT(*) FROM uploads")
+            .fetch_one(&db)
+            .await?;
+        assert_eq!(uploads, Some(0));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,347 @@
+#[cfg(test)]
+mod tests {
+    use axum::http::{
+        header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, RANGE},
+        HeaderValue, StatusCode,
+    };
+    use std::io::ErrorKind;
+
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestServer,
+    };
+    use bytes::Bytes;
+    use futures::{stream, StreamExt, TryStreamExt};
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, StorageConfig},
+        crypto::{plaintext_size, EncryptedBlob},
+        errors::AppResult,
+        metrics::CryptoTimer,
+        ranges::{parse_range, RangeSource},
+        router,
+        routes::upload::UploadResponse,
+        storage,
+        utilities::{DEC_CHUNK_SIZE, ENC_CHUNK_SIZE},
+        CONFIG_PATH,
+    };
+
+    async fn server(db: PgPool) -> anyhow::Result<TestServer> {
+        sqlx::query!("INSERT INTO uploads (id, delete_key, file_name, bytes, embedded) VALUES ('basic', '', 'basic', 11, FALSE)")
+            .execute(&db)
+            .await?;
+
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.general.storage_dir = String::from("src/tests/storage/");
+        let storage = storage::from_config(&config)?;
+        TestServer::new(router(config, db, storage))
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn parse_ranges() {
+        assert_eq!(parse_range("bytes=0-4", 11), Some(Ok(vec![0..5])));
+        assert_eq!(parse_range("bytes=6-", 11), Some(Ok(vec![6..11])));
+        assert_eq!(parse_range("bytes=-5", 11), Some(Ok(vec![6..11])));
+        assert_eq!(parse_range("bytes=-50", 11), Some(Ok(vec![0..11])));
+        assert_eq!(parse_range("bytes=0-100", 11), Some(Ok(vec![0..11])));
+        assert_eq!(parse_range("bytes=0-0, 2-3", 11), Some(Ok(vec![0..1, 2..4])));
+        assert_eq!(parse_range("bytes=20-30", 11), Some(Err(())));
+        assert_eq!(parse_range("bytes=0-", 0), Some(Err(())));
+        assert_eq!(parse_range("bytes=5-2", 11), None);
+        assert_eq!(parse_range("items=0-1", 11), None);
+        assert_eq!(parse_range("bytes=abc", 11), None);
+    }
+
+    #[sqlx::test]
+    async fn full_download_advertises_ranges(db: PgPool) -> AppResult<()> {
+        let server = server(db).await?;
+
+        let response = server.get("/download/basic").await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.header(ACCEPT_RANGES), "bytes");
+        assert_eq!(response.header(CONTENT_LENGTH), "11");
+        assert_eq!(response.as_bytes().as_ref(), b"hello world");
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn single_range(db: PgPool) -> AppResult<()> {
+        let server = server(db).await?;
+
+        let response = server
+            .get("/download/basic")
+            .add_header(RANGE, HeaderValue::from_static("bytes=6-"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.header(CONTENT_RANGE), "bytes 6-10/11");
+        assert_eq!(response.header(CONTENT_LENGTH), "5");
+        assert_eq!(response.as_bytes().as_ref(), b"world");
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn multiple_ranges(db: PgPool) -> AppResult<()> {
+        let server = server(db).await?;
+
+        let response = server
+            .get("/download/basic")
+            .add_header(RANGE, HeaderValue::from_static("bytes=0-1,-2"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::PARTIAL_CONTENT);
+
+        let content_type = response.header(CONTENT_TYPE);
+        let boundary = content_type
+            .to_str()
+            .unwrap()
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap()
+            .to_string();
+
+        let expected = format!(
+            "\r\n--{boundary}\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes 0-1/11\r\n\r\nhe\
+             \r\n--{boundary}\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes 9-10/11\r\n\r\nld\
+             \r\n--{boundary}--\r\n"
+        );
+        assert_eq!(response.text(), expected);
+        assert_eq!(response.header(CONTENT_LENGTH), expected.len().to_string().as_str());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn unsatisfiable_range(db: PgPool) -> AppResult<()> {
+        let server = server(db).await?;
+
+        let response = server
+            .get("/download/basic")
+            .add_header(RANGE, HeaderValue::from_static("bytes=100-"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.header(CONTENT_RANGE), "bytes */11");
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn if_range(db: PgPool) -> AppResult<()> {
+        let server = server(db).await?;
+
+        let etag = server.get("/download/basic").await.header(ETAG);
+
+        let response = server
+            .get("/download/basic")
+            .add_header(RANGE, HeaderValue::from_static("bytes=0-4"))
+            .add_header(IF_RANGE, etag)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.as_bytes().as_ref(), b"hello");
+
+        let response = server
+            .get("/download/basic")
+            .add_header(RANGE, HeaderValue::from_static("bytes=0-4"))
+            .add_header(IF_RANGE, HeaderValue::from_static(r#""stale""#))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.as_bytes().as_ref(), b"hello world");
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn ranges_count_as_downloads_when_they_are_limited(db: PgPool) -> AppResult<()> {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        let storage = storage::from_config(&config)?;
+        let server = TestServer::new(router(config, db, storage))?;
+
+        let multipart_form = MultipartForm::new().add_part("file", Part::bytes(b"hello world".as_slice()).file_name("once.txt"));
+        let upload: UploadResponse = server
+            .post("/upload")
+            .add_query_param("expiry_downloads", 2)
+            .multipart(multipart_form)
+            .await
+            .json();
+
+        // skipping the first byte doesn't make it a resumed download
+        for _ in 0..2 {
+            let response = server
+                .get(&format!("/download/{}", upload.id))
+                .add_header(RANGE, HeaderValue::from_static("bytes=1-"))
+                .await;
+            assert_eq!(response.status_code(), StatusCode::OK);
+            assert_eq!(response.header(ACCEPT_RANGES), "none");
+            assert_eq!(response.as_bytes().as_ref(), b"hello world");
+        }
+
+        let response = server
+            .get(&format!("/download/{}", upload.id))
+            .add_header(RANGE, HeaderValue::from_static("bytes=1-"))
+            .await;
+        // it's either already removed or about to be
+        assert!(response.status_code().is_client_error());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn previews_count_as_downloads_when_they_are_limited(db: PgPool) -> AppResult<()> {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        let storage = storage::from_config(&config)?;
+        let server = TestServer::new(router(config, db, storage))?;
+
+        let multipart_form = MultipartForm::new().add_part("file", Part::bytes(b"hello world".as_slice()).file_name("once.txt"));
+        let upload: UploadResponse = server
+            .post("/upload")
+            .add_query_param("expiry_downloads", 1)
+            .multipart(multipart_form)
+            .await
+            .json();
+
+        let response = server.get(&format!("/preview/{}", upload.id)).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.as_bytes().as_ref(), b"hello world");
+
+        let response = server.get(&format!("/download/{}", upload.id)).await;
+        assert!(response.status_code().is_client_error());
+
+        Ok(())
+    }
+
+    #[test]
+    fn encrypted_sizes() {
+        assert_eq!(plaintext_size(16), 0);
+        assert_eq!(plaintext_size(2064 + 16), 2048);
+        assert_eq!(plaintext_size(2 * 2064 + 904 + 16), 5000);
+    }
+
+    async fn encrypted_upload(db: PgPool, len: usize) -> anyhow::Result<(TestServer, Vec<u8>, UploadResponse)> {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        let storage = storage::from_config(&config)?;
+        let server = TestServer::new(router(config, db, storage))?;
+
+        let content = (0..len).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let multipart_form = MultipartForm::new()
+            .add_part("file", Part::bytes(content.clone()).file_name("pattern.bin"));
+        let response = server
+            .post("/upload")
+            .add_query_param("encrypt", true)
+            .multipart(multipart_form)
+            .await;
+        let body: UploadResponse = response.json();
+
+        Ok((server, content, body))
+    }
+
+    #[sqlx::test]
+    async fn encrypted_ranges(db: PgPool) -> AppResult<()> {
+        let (server, content, upload) = encrypted_upload(db, 5000).await?;
+        let key = upload.decryption_key.unwrap();
+        let url = format!("/download/{}", upload.id);
+
+        for (range, expected) in [
+            ("bytes=0-9", &content[..10]),
+            ("bytes=2040-2060", &content[2040..2061]),
+            ("bytes=1000-4500", &content[1000..4501]),
+            ("bytes=-10", &content[4990..]),
+            ("bytes=4096-", &content[4096..]),
+        ] {
+            let response = server
+                .get(&url)
+                .add_query_param("key", &key)
+                .add_header(RANGE, HeaderValue::from_static(range))
+                .await;
+            assert_eq!(response.status_code(), StatusCode::PARTIAL_CONTENT, "{range}");
+            assert_eq!(response.as_bytes().as_ref(), expected, "{range}");
+        }
+
+        let response = server
+            .get(&url)
+            .add_query_param("key", &key)
+            .add_header(RANGE, HeaderValue::from_static("bytes=5000-"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.header(CONTENT_RANGE), "bytes */5000");
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn encrypted_range_at_chunk_boundary(db: PgPool) -> AppResult<()> {
+        let (server, content, upload) = encrypted_upload(db, 4096).await?;
+
+        let response = server
+            .get(&format!("/download/{}", upload.id))
+            .add_query_param("key", upload.decryption_key.unwrap())
+            .add_header(RANGE, HeaderValue::from_static("bytes=2048-"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.header(CONTENT_RANGE), "bytes 2048-4095/4096");
+        assert_eq!(response.as_bytes().as_ref(), &content[2048..]);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn encrypted_stream_aborts_on_tampered_chunk(db: PgPool) -> AppResult<()> {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        let storage = storage::from_config(&config)?;
+        let server = TestServer::new(router(config, db.clone(), storage.clone()))?;
+
+        let multipart_form = MultipartForm::new()
+            .add_part("file", Part::bytes(vec![7u8; 5000]).file_name("sevens.bin"));
+        let response = server
+            .post("/upload")
+            .add_query_param("encrypt", true)
+            .multipart(multipart_form)
+            .await;
+        let upload: UploadResponse = response.json();
+
+        // flip one byte in the second chunk
+        let mut ciphertext = storage
+            .get(&upload.id)
+            .await?
+            .map_ok(|chunk| chunk.to_vec())
+            .try_concat()
+            .await?;
+        ciphertext[DEC_CHUNK_SIZE + 10] ^= 1;
+        let ciphertext_size = ciphertext.len() as u64;
+        let tampered = Bytes::from(ciphertext);
+        storage
+            .put(&upload.id, Box::pin(stream::once(async { Ok(tampered) })))
+            .await?;
+
+        let nonce = sqlx::query_scalar!("SELECT nonce FROM uploads WHERE id = $1", upload.id)
+            .fetch_one(&db)
+            .await?
+            .unwrap();
+
+        let mut blob = EncryptedBlob {
+            storage,
+            key: upload.id,
+            cipher_key: [0u8; 32],
+            nonce: [0u8; 19],
+            ciphertext_size,
+            timer: CryptoTimer::decryption(),
+        };
+        hex::decode_to_slice(upload.decryption_key.unwrap(), &mut blob.cipher_key)?;
+        hex::decode_to_slice(nonce, &mut blob.nonce)?;
+
+        // first chunk is still fine, but nothing after the tampered one is sent
+        let mut plaintext = blob.all(blob.plaintext_size()).await?;
+        let first = plaintext.next().await.unwrap()?;
+        assert_eq!(first.as_ref(), &[7u8; ENC_CHUNK_SIZE]);
+
+        let second = plaintext.next().await.unwrap();
+        assert_eq!(second.err().map(|e| e.kind()), Some(ErrorKind::InvalidData));
+        assert!(plaintext.next().await.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestServer,
+    };
+    use serde::Deserialize;
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, StorageConfig},
+        router,
+        routes::upload::UploadResponse,
+        storage, CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Stats {
+        uploads: u32,
+        downloads: u64,
+        series: Option<Vec<Bucket>>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Bucket {
+        uploads: u64,
+        bytes_in: u64,
+        encrypted_uploads: u64,
+        downloads: u64,
+        expired: u64,
+    }
+
+    #[sqlx::test]
+    async fn usage_in_buckets(db: PgPool) -> TestResult {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        let storage = storage::from_config(&config)?;
+        let server = TestServer::new(router(config, db, storage))?;
+
+        for encrypt in [true, false] {
+            let form = MultipartForm::new().add_part("file", Part::bytes(b"counted".as_slice()).file_name("stats.txt"));
+            let upload: UploadResponse = server
+                .post("/upload")
+                .add_query_param("encrypt", encrypt)
+                .multipart(form)
+                .await
+                .json();
+            let mut download = server.get(&format!("/download/{}", upload.id));
+            if let Some(key) = upload.decryption_key {
+                download = download.add_query_param("key", key);
+            }
+            assert_eq!(download.await.status_code(), StatusCode::OK);
+        }
+
+        // totals look like they always did unless a range is asked for
+        let stats: Stats = server.get("/stats").await.json();
+        assert_eq!(stats.uploads, 2);
+        assert_eq!(stats.downloads, 2);
+        assert!(stats.series.is_none());
+
+        let stats: Stats = server.get("/stats").add_query_param("granularity", "hour").await.json();
+        let series = stats.series.unwrap();
+        assert_eq!(series.len(), 31);
+        // summed up, as the hour could've changed in the meantime
+        let sum = |field: fn(&Bucket) -> u64| series.iter().map(field).sum::<u64>();
+        assert_eq!(sum(|bucket| bucket.uploads), 2);
+        assert_eq!(sum(|bucket| bucket.bytes_in), 14);
+        assert_eq!(sum(|bucket| bucket.encrypted_uploads), 1);
+        assert_eq!(sum(|bucket| bucket.downloads), 2);
+
+        let response = server
+            .get("/stats")
+            .add_query_param("from", "2024-01-01T00:00:00Z")
+            .add_query_param("granularity", "hour")
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+    #[sqlx::test]
+    async fn info_counts_expired_uploads(db: PgPool) -> TestResult {
+        sqlx::query!("INSERT INTO uploads (id, delete_key, file_name, bytes, expiry_hours, created_at, embedded) VALUES ('hours', '', 'expired', 0, 1, NOW() - INTERVAL '2 hours', FALSE)")
+            .execute(&db)
+            .await?;
+        sqlx::query!("INSERT INTO uploads (id, delete_key, file_name, bytes, downloads, expiry_downloads, embedded) VALUES ('dloads', '', 'expired', 0, 1, 1, FALSE)")
+            .execute(&db)
+            .await?;
+
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        let storage = storage::from_config(&config)?;
+        let server = TestServer::new(router(config, db, storage))?;
+
+        for id in ["hours", "dloads"] {
+            let response = server.get(&format!("/info/{id}")).await;
+            assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+        }
+
+        // only the one past its time is removed right away, the other one is left to the reaper
+        let stats: Stats = server.get("/stats").add_query_param("granularity", "hour").await.json();
+        let expired: u64 = stats.series.unwrap().iter().map(|bucket| bucket.expired).sum();
+        assert_eq!(expired, 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+#[cfg(test)]
+mod tests {
+    use axum::http::{
+        header::{CONTENT_SECURITY_POLICY, CONTENT_TYPE},
+        StatusCode,
+    };
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestServer,
+    };
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, StorageConfig},
+        router,
+        routes::upload::UploadResponse,
+        storage, CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    async fn upload(server: &TestServer, content: &[u8], file_name: &str) -> UploadResponse {
+        let form = MultipartForm::new().add_part("file", Part::bytes(content.to_vec()).file_name(file_name));
+        server.post("/upload").multipart(form).await.json()
+    }
+
+    #[sqlx::test]
+    async fn text_is_previewed_as_plain_or_highlighted(db: PgPool) -> TestResult {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        // cuts the preview off in the middle of ö
+        config.general.text_preview_bytes = 17;
+        let storage = storage::from_config(&config)?;
+        let server = TestServer::new(router(config, db, storage))?;
+
+        let source = upload(&server, "fn main() { let ö = 1; }".as_bytes(), "main.rs").await;
+        let response = server.get(&format!("/preview/{}", source.id)).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.header(CONTENT_TYPE), "text/plain; charset=utf-8");
+        assert_eq!(response.text(), "fn main() { let ");
+
+        let response = server
+            .get(&format!("/preview/{}", source.id))
+            .add_query_param("highlight", true)
+            .await;
+        assert_eq!(response.header(CONTENT_TYPE), "text/html; charset=utf-8");
+        assert!(response.maybe_header(CONTENT_SECURITY_POLICY).is_some());
+        let html = response.text();
+        assert!(html.starts_with("<pre"));
+        // rust syntax is picked by extension, so the keyword gets a style of its own
+        assert!(html.contains(">fn </span>"));
+
+        let binary = upload(&server, b"\x00\x01\x02 not text", "data.bin").await;
+        let response = server.get(&format!("/preview/{}", binary.id)).await;
+        assert_eq!(response.status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        // text can't be resized like images
+        let response = server
+            .get(&format!("/preview/{}", source.id))
+            .add_query_param("w", 64)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::Arc};
+
+    use axum::http::{header::CONTENT_TYPE, StatusCode};
+    use bytes::Bytes;
+    use axum_test::{
+        multipart::{MultipartForm, Part},
+        TestServer,
+    };
+    use image::{ImageFormat, Rgb, RgbImage};
+    use sqlx::PgPool;
+
+    use crate::{
+        config::load_config,
+        router,
+        routes::upload::UploadResponse,
+        storage::{MemoryStorage, Storage},
+        thumbnails::ThumbnailCache,
+        CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    fn png(width: u32, height: u32) -> anyhow::Result<Vec<u8>> {
+        let mut encoded = Cursor::new(Vec::new());
+        RgbImage::from_pixel(width, height, Rgb([200, 40, 90])).write_to(&mut encoded, ImageFormat::Png)?;
+        Ok(encoded.into_inner())
+    }
+
+    #[sqlx::test]
+    async fn thumbnails_are_cached_and_removed_with_upload(db: PgPool) -> TestResult {
+        let mut config = load_config(CONFIG_PATH).await?;
+        // original is too big to preview, so it only gets a thumbnail
+        config.general.max_preview_bytes = 16;
+        let storage = Arc::new(MemoryStorage::default());
+        let server = TestServer::new(router(config, db.clone(), storage.clone()))?;
+
+        let content = png(400, 200)?;
+        let form = MultipartForm::new().add_part("file", Part::bytes(content.clone()).file_name("wide.png"));
+        let upload: UploadResponse = server.post("/upload").multipart(form).await.json();
+
+        let response = server.get(&format!("/preview/{}", upload.id)).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.header(CONTENT_TYPE), "image/webp");
+        let thumbnail = image::load_from_memory(response.as_bytes())?;
+        assert_eq!((thumbnail.width(), thumbnail.height()), (256, 128));
+
+        // width is rounded up to 128, aspect ratio is kept
+        let resized = server
+            .get(&format!("/preview/{}", upload.id))
+            .add_query_param("w", 100)
+            .add_query_param("format", "jpeg")
+            .await;
+        assert_eq!(resized.header(CONTENT_TYPE), "image/jpeg");
+        let thumbnail = image::load_from_memory(resized.as_bytes())?;
+        assert_eq!((thumbnail.width(), thumbnail.height()), (128, 64));
+
+        let cropped = server
+            .get(&format!("/preview/{}", upload.id))
+            .add_query_param("w", 64)
+            .add_query_param("fit", "cover")
+            .await;
+        let thumbnail = image::load_from_memory(cropped.as_bytes())?;
+        assert_eq!((thumbnail.width(), thumbnail.height()), (64, 64));
+
+        let cached = server
+            .get(&format!("/preview/{}", upload.id))
+            .add_query_param("w", 100)
+            .add_query_param("format", "jpeg")
+            .await;
+        assert_eq!(cached.as_bytes(), resized.as_bytes());
+
+        let hash = sha256::digest(&content);
+        let variants = sqlx::query_scalar!("SELECT variant FROM thumbnails WHERE blob_key = $1 ORDER BY variant", hash)
+            .fetch_all(&db)
+            .await?;
+        assert_eq!(variants, ["128x2048-contain.jpg", "256x256-contain.webp", "64x64-cover.webp"]);
+        for variant in &variants {
+            assert!(storage.exists(&format!("{hash}.{variant}")).await?);
+        }
+
+        // they take space just like uploads do
+        let thumbnail_bytes = sqlx::query_scalar!(r#"SELECT SUM(bytes)::BIGINT AS "bytes!" FROM thumbnails"#)
+            .fetch_one(&db)
+            .await?;
+        let stats: serde_json::Value = server.get("/stats").await.json();
+        assert_eq!(stats["storedBytes"], content.len() as i64 + thumbnail_bytes);
+
+        let response = server
+            .delete(&format!("/delete/{}", upload.id))
+            .add_query_param("key", &upload.delete_key)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+
+        let remaining = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM thumbnails"#)
+            .fetch_one(&db)
+            .await?;
+        assert_eq!(remaining, 0);
+        for variant in &variants {
+            assert!(!storage.exists(&format!("{hash}.{variant}")).await?);
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn images_too_big_to_preview_get_thumbnails(db: PgPool) -> TestResult {
+        let config = load_config(CONFIG_PATH).await?;
+        let too_big = config.general.max_preview_bytes as i64 + 1;
+        let server = TestServer::new(router(config, db.clone(), Arc::new(MemoryStorage::default())))?;
+
+        let form = MultipartForm::new().add_part("file", Part::bytes(png(300, 300)?).file_name("square.png"));
+        let upload: UploadResponse = server.post("/upload").multipart(form).await.json();
+        // limits are checked against the recorded size, so the image doesn't have to be that big
+        sqlx::query!("UPDATE uploads SET bytes = $1 WHERE id = $2", too_big, upload.id)
+            .execute(&db)
+            .await?;
+
+        let response = server.get(&format!("/preview/{}", upload.id)).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.header(CONTENT_TYPE), "image/webp");
+
+        Ok(())
+    }
+
+    #[test]
+    fn cache_drops_oldest_thumbnails() {
+        let cache = ThumbnailCache::default();
+        let body = Bytes::from(vec![0u8; 12 * 1024 * 1024]);
+        for key in ["first", "second", "third"] {
+            cache.insert(key.to_string(), body.clone());
+        }
+
+        assert!(cache.get("first").is_none());
+        assert!(cache.get("second").is_some());
+        assert!(cache.get("third").is_some());
+    }
+}
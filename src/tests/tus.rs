@@ -0,0 +1,266 @@
+#[cfg(test)]
+mod tests {
+    use axum::http::{header::LOCATION, HeaderValue, Method, StatusCode};
+    use axum_test::TestServer;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use bytes::Bytes;
+    use futures::stream;
+    use serde_json::Value;
+    use sqlx::PgPool;
+
+    use crate::{
+        config::{load_config, StorageConfig},
+        reaper::purge_stale_sessions,
+        router,
+        routes::{
+            preview::DECRYPTION_KEY,
+            tus::{TUS_RESUMABLE, UPLOAD_LENGTH, UPLOAD_METADATA, UPLOAD_OFFSET},
+            upload::UploadResponse,
+        },
+        storage::{self, MemoryStorage, Storage},
+        utilities::ENC_CHUNK_SIZE,
+        CONFIG_PATH,
+    };
+
+    type TestResult = anyhow::Result<()>;
+
+    const CHUNK_CONTENT_TYPE: &str = "application/offset+octet-stream";
+    // base64 of `hello.bin`
+    const METADATA: &str = "filename aGVsbG8uYmlu";
+
+    async fn server(db: PgPool) -> anyhow::Result<TestServer> {
+        let mut config = load_config(CONFIG_PATH).await?;
+        config.storage = StorageConfig::Memory;
+        let storage = storage::from_config(&config)?;
+
+        let mut server = TestServer::new(router(config, db, storage))?;
+        server.add_header(TUS_RESUMABLE, HeaderValue::from_static("1.0.0"));
+        Ok(server)
+    }
+
+    fn content(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    async fn create(server: &TestServer, len: usize, encrypt: bool) -> (String, UploadResponse) {
+        let response = server
+            .post("/tus")
+            .add_query_param("encrypt", encrypt)
+            .add_header(UPLOAD_LENGTH, HeaderValue::from(len))
+            .add_header(UPLOAD_METADATA, HeaderValue::from_static(METADATA))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::CREATED);
+
+        let location = response.header(LOCATION).to_str().unwrap().to_string();
+        (location, response.json())
+    }
+
+    async fn patch(server: &TestServer, location: &str, offset: usize, data: &[u8]) -> StatusCode {
+        server
+            .patch(location)
+            .add_header(UPLOAD_OFFSET, HeaderValue::from(offset))
+            .content_type(CHUNK_CONTENT_TYPE)
+            .bytes(Bytes::copy_from_slice(data))
+            .await
+            .status_code()
+    }
+
+    async fn upload_in_parts(db: PgPool, encrypt: bool) -> TestResult {
+        let mut server = server(db).await?;
+        let data = content(3 * ENC_CHUNK_SIZE + 1000);
+        let (location, upload) = create(&server, data.len(), encrypt).await;
+        assert_eq!(upload.decryption_key.is_some(), encrypt);
+        if let Some(key) = &upload.decryption_key {
+            server.add_header(DECRYPTION_KEY, HeaderValue::from_str(key)?);
+        }
+
+        // parts deliberately don't line up with encryption chunks
+        let mut offset = 0;
+        for part in [1, 3000, 2047, data.len() - 5048] {
+            let status = patch(&server, &location, offset, &data[offset..offset + part]).await;
+            assert_eq!(status, StatusCode::NO_CONTENT);
+            offset += part;
+
+            let response = server.method(Method::HEAD, &location).await;
+            if offset < data.len() {
+                assert_eq!(response.header(UPLOAD_OFFSET), offset.to_string().as_str());
+            } else {
+                assert_eq!(response.status_code(), StatusCode::NOT_FOUND);
+            }
+        }
+
+        let mut request = server.get(&format!("/download/{}", upload.id));
+        if let Some(key) = &upload.decryption_key {
+            request = request.add_query_param("key", key);
+        }
+        let response = request.await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.as_bytes().as_ref(), data.as_slice());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn plain_upload_in_parts(db: PgPool) -> TestResult {
+        upload_in_parts(db, false).await
+    }
+
+    #[sqlx::test]
+    async fn encrypted_upload_in_parts(db: PgPool) -> TestResult {
+        upload_in_parts(db, true).await
+    }
+
+    #[sqlx::test]
+    async fn identical_plain_uploads_share_blob(db: PgPool) -> TestResult {
+        let server = server(db.clone()).await?;
+        let data = content(1000);
+
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let (location, upload) = create(&server, data.len(), false).await;
+            assert_eq!(patch(&server, &location, 0, &data).await, StatusCode::NO_CONTENT);
+            ids.push(upload.id);
+        }
+
+        let hash = sha256::digest(data.as_slice());
+        let refs = sqlx::query_scalar!("SELECT refs FROM blobs WHERE hash = $1", hash)
+            .fetch_one(&db)
+            .await?;
+        assert_eq!(refs, 2);
+
+        for id in ids {
+            let response = server.get(&format!("/download/{id}")).await;
+            assert_eq!(response.as_bytes().as_ref(), data.as_slice());
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn encrypted_sessions_only_keep_what_the_key_seals(db: PgPool) -> TestResult {
+        let server = server(db.clone()).await?;
+        let data = content(ENC_CHUNK_SIZE + 1000);
+        let (location, upload) = create(&server, data.len(), true).await;
+        let key = upload.decryption_key.unwrap();
+
+        let response = server
+            .patch(&location)
+            .add_header(UPLOAD_OFFSET, HeaderValue::from(0))
+            .content_type(CHUNK_CONTENT_TYPE)
+            .bytes(Bytes::copy_from_slice(&data[..1500]))
+            .await;
+        let body: Value = response.json();
+        assert_eq!(body["errorCode"], "missing-key");
+
+        let response = server
+            .patch(&location)
+            .add_header(UPLOAD_OFFSET, HeaderValue::from(0))
+            .add_header(DECRYPTION_KEY, HeaderValue::from_str(&key)?)
+            .content_type(CHUNK_CONTENT_TYPE)
+            .bytes(Bytes::copy_from_slice(&data[..1500]))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+
+        let session = sqlx::query!("SELECT key_hash, tail FROM upload_sessions WHERE id = $1", upload.id)
+            .fetch_one(&db)
+            .await?;
+        assert_eq!(session.key_hash, Some(sha256::digest(&key)));
+        // 1500 bytes don't fill a chunk, so they're all left over
+        assert!(!session.tail.is_empty());
+        assert!(!session.tail.windows(64).any(|window| window == &data[..64]));
+
+        let response = server
+            .patch(&location)
+            .add_header(UPLOAD_OFFSET, HeaderValue::from(1500))
+            .add_header(DECRYPTION_KEY, HeaderValue::from_str(&key)?)
+            .content_type(CHUNK_CONTENT_TYPE)
+            .bytes(Bytes::copy_from_slice(&data[1500..]))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+
+        let response = server.get(&format!("/download/{}", upload.id)).add_query_param("key", &key).await;
+        assert_eq!(response.as_bytes().as_ref(), data.as_slice());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn rejects_wrong_offset_and_version(db: PgPool) -> TestResult {
+        let server = server(db).await?;
+        let (location, _) = create(&server, 10, false).await;
+
+        assert_eq!(patch(&server, &location, 3, b"abc").await, StatusCode::CONFLICT);
+        assert_eq!(patch(&server, &location, 0, b"more than ten bytes").await, StatusCode::BAD_REQUEST);
+
+        let response = server
+            .patch(&location)
+            .clear_headers()
+            .add_header(TUS_RESUMABLE, HeaderValue::from_static("0.2.2"))
+            .add_header(UPLOAD_OFFSET, HeaderValue::from(0))
+            .content_type(CHUNK_CONTENT_TYPE)
+            .bytes(Bytes::from_static(b"abc"))
+            .await;
+        assert_eq!(response.status_code(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(response.header("tus-version"), "1.0.0");
+
+        let response = server.method(Method::HEAD, &location).await;
+        assert_eq!(response.header(UPLOAD_OFFSET), "0");
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn rejects_file_names_that_dont_fit(db: PgPool) -> TestResult {
+        let server = server(db).await?;
+
+        let long_name = STANDARD.encode("a".repeat(256));
+        let response = server
+            .post("/tus")
+            .add_header(UPLOAD_LENGTH, HeaderValue::from(10))
+            .add_header(UPLOAD_METADATA, HeaderValue::from_str(&format!("filename {long_name}"))?)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        let body: Value = response.json();
+        assert_eq!(body["errorCode"], "invalid-file-name");
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn terminate_upload(db: PgPool) -> TestResult {
+        let server = server(db).await?;
+        let (location, _) = create(&server, 10, false).await;
+        assert_eq!(patch(&server, &location, 0, b"abc").await, StatusCode::NO_CONTENT);
+
+        let response = server.delete(&location).await;
+        assert_eq!(response.status_code(), StatusCode::NO_CONTENT);
+
+        assert_eq!(patch(&server, &location, 3, b"def").await, StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn purge_stale_upload_sessions(db: PgPool) -> TestResult {
+        let storage = MemoryStorage::default();
+        let part = stream::iter([Ok(Bytes::from_static(b"abc"))]);
+        storage.put("stale-part", Box::pin(part)).await?;
+
+        sqlx::query!("INSERT INTO upload_sessions (id, delete_key, file_name, upload_length, upload_offset, parts, updated_at) VALUES ('stale', '', 'stale', 10, 3, '{stale-part}', NOW() - INTERVAL '2 days')")
+            .execute(&db)
+            .await?;
+        sqlx::query!("INSERT INTO upload_sessions (id, delete_key, file_name, upload_length) VALUES ('fresh', '', 'fresh', 10)")
+            .execute(&db)
+            .await?;
+
+        assert_eq!(purge_stale_sessions(&db, &storage, 24).await?, 1);
+        assert!(!storage.exists("stale-part").await?);
+
+        let remaining = sqlx::query_scalar!("SELECT id FROM upload_sessions")
+            .fetch_all(&db)
+            .await?;
+        assert_eq!(remaining, vec!["fresh".to_string()]);
+
+        Ok(())
+    }
+}
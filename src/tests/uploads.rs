@@ -10,7 +10,9 @@ mod tests {
     use sqlx::PgPool;
     use tokio::fs::File;
 
-    use crate::{config::{load_config, Config}, errors::AppResult, router, routes::upload::UploadResponse, CONFIG_PATH};
+    use axum::Router;
+
+    use crate::{config::{load_config, Config, StorageConfig}, errors::AppResult, router, routes::upload::UploadResponse, storage, CONFIG_PATH};
 
     const BASIC_FILE: &[u8] = include_bytes!("./storage/basic");
 
@@ -22,10 +24,15 @@ mod tests {
         Ok(config)
     }
 
+    fn test_router(config: Config, db: PgPool) -> AppResult<Router> {
+        let storage = storage::from_config(&config)?;
+        Ok(router(config, db, storage))
+    }
+
     #[sqlx::test]
     async fn upload(db: PgPool) -> TestResult {
         let config = test_config().await?;
-        let router = router(config, db);
+        let router = test_router(config, db)?;
         let server = TestServer::new(router)?;
 
         let multipart_form = MultipartForm::new()
@@ -44,7 +51,7 @@ mod tests {
     #[sqlx::test]
     async fn upload_encrypted(db: PgPool) -> TestResult {
         let config = test_config().await?;
-        let router = router(config, db);
+        let router = test_router(config, db)?;
         let server = TestServer::new(router)?;
 
         let multipart_form = MultipartForm::new()
@@ -67,13 +74,13 @@ mod tests {
     #[sqlx::test]
     async fn download(db: PgPool) -> AppResult<()> {
         sqlx::query!(
-            "INSERT INTO uploads (id, delete_key, file_name, bytes) VALUES ('basic', '', 'basic', 0)"
+            "INSERT INTO uploads (id, delete_key, file_name, bytes, embedded) VALUES ('basic', '', 'basic', 0, FALSE)"
         )
         .execute(&db)
         .await?;
 
         let config = test_config().await?;
-        let router = router(config, db);
+        let router = test_router(config, db)?;
         let server = TestServer::new(router)?;
 
         let response = server.get("/download/basic").await;
@@ -87,12 +94,12 @@ mod tests {
 
     #[sqlx::test]
     async fn download_encrypted(db: PgPool) -> AppResult<()> {
-        sqlx::query!("INSERT INTO uploads (id, key_hash, delete_key, nonce, file_name, bytes) VALUES ('basic_ec', '8882d9c8f120896dd013f528362bac298fc8f14c2f6608c6c5db5fa8e14f2e8e', '', '5561039d74dbe779e061d3731c19d3ca93b92a', 'basic', 0)")
+        sqlx::query!("INSERT INTO uploads (id, key_hash, delete_key, nonce, file_name, bytes, embedded) VALUES ('basic_ec', '8882d9c8f120896dd013f528362bac298fc8f14c2f6608c6c5db5fa8e14f2e8e', '', '5561039d74dbe779e061d3731c19d3ca93b92a', 'basic', 0, FALSE)")
             .execute(&db)
             .await?;
 
         let config = test_config().await?;
-        let router = router(config, db);
+        let router = test_router(config, db)?;
         let server = TestServer::new(router)?;
 
         let response = server
@@ -113,7 +120,7 @@ mod tests {
     #[sqlx::test]
     async fn download_encrypted_invalid_key(db: PgPool) -> AppResult<()> {
         let config = test_config().await?;
-        let router = router(config, db);
+        let router = test_router(config, db)?;
         let server = TestServer::new(router)?;
 
         let response = server
@@ -127,13 +134,13 @@ mod tests {
 
     #[sqlx::test]
     async fn delete_upload(db: PgPool) -> AppResult<()> {
-        sqlx::query!("INSERT INTO uploads (id, delete_key, file_name, bytes) VALUES ('useless', 'MOjql910y1nyViKuJvFUx', 'useless', 0)")
+        sqlx::query!("INSERT INTO uploads (id, delete_key, file_name, bytes, embedded) VALUES ('useless', 'MOjql910y1nyViKuJvFUx', 'useless', 0, FALSE)")
             .execute(&db)
             .await?;
 
         let config = test_config().await?;
         let storage_dir = config.general.storage_dir.clone();
-        let router = router(config, db);
+        let router = test_router(config, db)?;
         let server = TestServer::new(router)?;
 
         File::create(format!("{storage_dir}useless")).await?;
@@ -149,4 +156,38 @@ mod tests {
 
         Ok(())
     }
+
+    #[sqlx::test]
+    async fn upload_too_large(db: PgPool) -> TestResult {
+        let mut config = test_config().await?;
+        config.storage = StorageConfig::Memory;
+        config.general.max_upload_bytes = 100;
+        let router = test_router(config, db)?;
+        let server = TestServer::new(router)?;
+
+        let upload = |files: &[usize], encrypt: bool| {
+            let form = files.iter().fold(MultipartForm::new(), |form, &len| {
+                form.add_part("file", Part::bytes(vec![1; len]).file_name("big.bin"))
+            });
+            server.post("/upload").add_query_param("encrypt", encrypt).multipart(form)
+        };
+
+        let response = upload(&[100], false).await;
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        // cut off while it's received
+        let response = upload(&[101], true).await;
+        assert_eq!(response.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(response.text().contains("at most 100 bytes"));
+
+        // limit is for the whole upload
+        let response = upload(&[60, 60], false).await;
+        assert_eq!(response.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // rejected before it's read
+        let response = upload(&[128 * 1024], false).await;
+        assert_eq!(response.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,59 @@
+//! Previews of plain text and source code, which `infer` doesn't recognize as anything.
+
+use std::{path::Path, sync::OnceLock};
+
+use syntect::{
+    highlighting::{Theme, ThemeSet},
+    html::highlighted_html_for_string,
+    parsing::{SyntaxReference, SyntaxSet},
+};
+
+const THEME: &str = "InspiredGitHub";
+
+static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+
+/// Whether the head of a file looks like UTF-8 text, a character cut off at its end is fine.
+pub fn is_text(head: &[u8]) -> bool {
+    if head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(why) => why.error_len().is_none(),
+    }
+}
+
+/// Longest valid UTF-8 prefix of `bytes`, so a preview never ends in the middle of a character.
+pub fn utf8_prefix(bytes: &[u8]) -> &str {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(why) => std::str::from_utf8(&bytes[..why.valid_up_to()]).expect("prefix is valid"),
+    }
+}
+
+/// Text as HTML with inline styles, highlighted by the syntax that goes with the file's extension.
+/// Loading syntaxes takes a while the first time, so it's better called from a blocking task.
+pub fn highlight(text: &str, file_name: &str) -> Result<String, syntect::Error> {
+    let syntaxes = SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines);
+    let theme = theme();
+    let syntax = find_syntax(syntaxes, text, file_name);
+
+    highlighted_html_for_string(text, syntaxes, syntax, theme)
+}
+
+fn theme() -> &'static Theme {
+    let themes = THEMES.get_or_init(ThemeSet::load_defaults);
+    &themes.themes[THEME]
+}
+
+fn find_syntax<'a>(syntaxes: &'a SyntaxSet, text: &str, file_name: &str) -> &'a SyntaxReference {
+    let extension = Path::new(file_name).extension().and_then(|extension| extension.to_str());
+
+    // some syntaxes go by the whole file name, like Makefile
+    extension
+        .and_then(|extension| syntaxes.find_syntax_by_extension(extension))
+        .or_else(|| syntaxes.find_syntax_by_extension(file_name))
+        .or_else(|| syntaxes.find_syntax_by_first_line(text))
+        .unwrap_or_else(|| syntaxes.find_syntax_plain_text())
+}
@@ -0,0 +1,279 @@
+//! Resized previews of image uploads. They're made the first time they're asked for and kept in
+//! storage next to their blob as `<blob_key>.<variant>`, so every instance can serve them.
+//! Ones of encrypted uploads are only kept in memory.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Cursor,
+    sync::{Arc, Mutex},
+};
+
+use axum::body::Body;
+use bytes::Bytes;
+use futures::TryStreamExt;
+use image::{
+    codecs::{jpeg::JpegEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    DynamicImage, ImageReader, ImageResult, Limits,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+use tokio::io;
+use utoipa::ToSchema;
+
+use crate::{
+    errors::{AppError, AppResult},
+    models::Upload,
+    ranges::RangeSource,
+    repository::{delete_thumbnails, insert_thumbnail, thumbnail_exists},
+    storage::Storage,
+    AppContext,
+};
+
+/// Requested sizes are rounded up to one of these, so there's only a handful of variants to keep.
+const SIZES: [u32; 6] = [64, 128, 256, 512, 1024, 2048];
+const DEFAULT_SIZE: u32 = 256;
+const JPEG_QUALITY: u8 = 80;
+// decoded pixels of a large image take far more memory than the image itself
+const MAX_DECODE_ALLOC: u64 = 256 * 1024 * 1024;
+// how much memory thumbnails of encrypted uploads can take together
+const MAX_CACHED_BYTES: usize = 32 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Fit {
+    /// Whole image fits within the size, it's never enlarged.
+    #[default]
+    Contain,
+    /// Image covers the whole size and whatever is outside of it is cropped.
+    Cover,
+    /// Image is stretched to the size.
+    Fill,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailFormat {
+    #[default]
+    Webp,
+    Jpeg,
+}
+
+impl ThumbnailFormat {
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Webp => "image/webp",
+            Self::Jpeg => "image/jpeg",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Webp => "webp",
+            Self::Jpeg => "jpg",
+        }
+    }
+}
+
+/// Size, fit and format of a thumbnail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Variant {
+    pub width: u32,
+    pub height: u32,
+    pub fit: Fit,
+    pub format: ThumbnailFormat,
+}
+
+impl Variant {
+    /// Missing height of a contained image is left unbounded, otherwise a missing side is the same as the other one.
+    pub fn new(width: Option<u32>, height: Option<u32>, fit: Fit, format: ThumbnailFormat) -> Self {
+        let largest = SIZES[SIZES.len() - 1];
+        let (width, height) = match (width.map(snap), height.map(snap)) {
+            (Some(width), Some(height)) => (width, height),
+            (Some(side), None) | (None, Some(side)) if fit != Fit::Contain => (side, side),
+            (Some(width), None) => (width, largest),
+            (None, Some(height)) => (largest, height),
+            (None, None) => (DEFAULT_SIZE, DEFAULT_SIZE),
+        };
+
+        Self {
+            width,
+            height,
+            fit,
+            format,
+        }
+    }
+
+    /// Name of the variant within its blob's thumbnails.
+    pub fn name(&self) -> String {
+        let fit = match self.fit {
+            Fit::Contain => "contain",
+            Fit::Cover => "cover",
+            Fit::Fill => "fill",
+        };
+        format!("{}x{}-{fit}.{}", self.width, self.height, self.format.extension())
+    }
+}
+
+fn snap(size: u32) -> u32 {
+    SIZES
+        .into_iter()
+        .find(|&step| step >= size)
+        .unwrap_or(SIZES[SIZES.len() - 1])
+}
+
+/// Largest size a thumbnail can have that isn't over `size`, or the smallest one.
+pub fn snap_down(size: u32) -> u32 {
+    SIZES
+        .into_iter()
+        .rev()
+        .find(|&step| step <= size)
+        .unwrap_or(SIZES[0])
+}
+
+/// Size of an image read from its header, so only the first few bytes of it are needed.
+pub fn dimensions(head: &[u8]) -> Option<(u32, u32)> {
+    ImageReader::new(Cursor::new(head))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// Thumbnails of encrypted uploads, which can't be stored as they're decrypted. Each instance keeps
+/// its own and the oldest ones are dropped once they take more than `MAX_CACHED_BYTES`.
+#[derive(Debug, Clone, Default)]
+pub struct ThumbnailCache {
+    inner: Arc<Mutex<CachedThumbnails>>,
+}
+
+#[derive(Debug, Default)]
+struct CachedThumbnails {
+    bodies: HashMap<String, Bytes>,
+    order: VecDeque<String>,
+    bytes: usize,
+}
+
+impl ThumbnailCache {
+    pub fn get(&self, key: &str) -> Option<Bytes> {
+        self.inner.lock().unwrap().bodies.get(key).cloned()
+    }
+
+    pub fn insert(&self, key: String, body: Bytes) {
+        let mut cached = self.inner.lock().unwrap();
+        if body.len() > MAX_CACHED_BYTES || cached.bodies.contains_key(&key) {
+            return;
+        }
+
+        cached.bytes += body.len();
+        cached.order.push_back(key.clone());
+        cached.bodies.insert(key, body);
+        while cached.bytes > MAX_CACHED_BYTES {
+            let Some(oldest) = cached.order.pop_front() else {
+                break;
+            };
+            if let Some(body) = cached.bodies.remove(&oldest) {
+                cached.bytes -= body.len();
+            }
+        }
+    }
+}
+
+/// Body of the thumbnail, which is made and stored first if it doesn't exist yet.
+/// Thumbnails of encrypted uploads are made from the decrypted `source` and only cached in memory.
+pub async fn thumbnail(
+    ctx: &AppContext,
+    upload: &Upload,
+    source: &dyn RangeSource,
+    size: u64,
+    variant: Variant,
+) -> AppResult<Body> {
+    let blob_key = upload.storage_key();
+    let name = variant.name();
+    let key = format!("{blob_key}.{name}");
+    let stored = upload.nonce.is_none();
+    // key was checked already, its hash tells apart uploads that reuse the id after the reaper
+    let memory_key = format!("{}.{}.{name}", upload.id, upload.key_hash.as_deref().unwrap_or_default());
+
+    // row is written only once the thumbnail is stored
+    if stored && thumbnail_exists(&ctx.db, blob_key, &name).await? {
+        return Ok(Body::from_stream(ctx.storage.get(&key).await?));
+    }
+    if let Some(body) = ctx.thumbnails.get(&memory_key).filter(|_| !stored) {
+        return Ok(Body::from(body));
+    }
+
+    if upload.bytes as u64 > ctx.cfg.general.max_thumbnail_source_bytes() {
+        return Err(AppError::MediaTooBig);
+    }
+    // whole original and its decoded pixels are in memory until it's done
+    let _permit = ctx.renders.acquire().await.map_err(|why| anyhow::anyhow!(why))?;
+    let source = source
+        .all(size)
+        .await?
+        .map_ok(|chunk| chunk.to_vec())
+        .try_concat()
+        .await?;
+
+    let id = upload.id.clone();
+    let encoded = tokio::task::spawn_blocking(move || render(&source, variant))
+        .await
+        .map_err(|why| anyhow::anyhow!(why))?
+        .map_err(|why| {
+            tracing::warn!("Failed to make {name} thumbnail of {id}: {why:?}");
+            AppError::PreviewNotSupported
+        })?;
+    let encoded = Bytes::from(encoded);
+    if !stored {
+        ctx.thumbnails.insert(memory_key, encoded.clone());
+        return Ok(Body::from(encoded));
+    }
+
+    let chunk = encoded.clone();
+    let stream = futures::stream::once(async { io::Result::Ok(chunk) });
+    let bytes = ctx.storage.put(&key, Box::pin(stream)).await?;
+    insert_thumbnail(&ctx.db, blob_key, &name, bytes).await?;
+
+    Ok(Body::from(encoded))
+}
+
+/// Decodes the image, resizes it and encodes it in the variant's format.
+fn render(source: &[u8], variant: Variant) -> ImageResult<Vec<u8>> {
+    let mut limits = Limits::default();
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+    let mut reader = ImageReader::new(Cursor::new(source)).with_guessed_format()?;
+    reader.limits(limits);
+    let image = reader.decode()?;
+
+    let Variant { width, height, .. } = variant;
+    let resized = match variant.fit {
+        Fit::Contain if image.width() <= width && image.height() <= height => image,
+        Fit::Contain => image.resize(width, height, FilterType::Lanczos3),
+        Fit::Cover => image.resize_to_fill(width, height, FilterType::Lanczos3),
+        Fit::Fill => image.resize_exact(width, height, FilterType::Lanczos3),
+    };
+
+    let mut encoded = Vec::new();
+    match variant.format {
+        // lossless is all the pure rust encoder can do
+        ThumbnailFormat::Webp => DynamicImage::ImageRgba8(resized.into_rgba8())
+            .write_with_encoder(WebPEncoder::new_lossless(&mut encoded))?,
+        ThumbnailFormat::Jpeg => DynamicImage::ImageRgb8(resized.into_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut encoded, JPEG_QUALITY))?,
+    }
+
+    Ok(encoded)
+}
+
+/// Removes every thumbnail of the blob, called when the blob itself is removed.
+pub async fn remove_thumbnails(db: &PgPool, storage: &dyn Storage, blob_key: &str) -> AppResult<()> {
+    for variant in delete_thumbnails(db, blob_key).await? {
+        let key = format!("{blob_key}.{variant}");
+        match storage.delete(&key).await {
+            Ok(()) => {}
+            Err(why) if why.kind() == io::ErrorKind::NotFound => {}
+            Err(why) => tracing::warn!("Failed to remove thumbnail {key}: {why:?}"),
+        }
+    }
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use nanoid::nanoid;
+use tempfile::{NamedTempFile, TempPath};
 use tokio::{
-    fs::{File, OpenOptions},
+    fs::File,
     io::{self, AsyncRead, AsyncReadExt},
 };
 
@@ -25,18 +26,15 @@ where
     Ok(chunk)
 }
 
-// TODO(hito): use tempfile crate
-pub async fn temp_file(temp_path: &str) -> io::Result<(File, String)> {
-    let file_path = format!("{temp_path}{}", nanoid!());
-    let file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(&file_path)
-        .await?;
+/// Creates a file in `temp_dir` which is removed once returned path is dropped.
+pub async fn temp_file(temp_dir: &str) -> io::Result<(File, TempPath)> {
+    let temp_dir = temp_dir.to_string();
+    let (file, path) = tokio::task::spawn_blocking(move || NamedTempFile::new_in(temp_dir))
+        .await
+        .map_err(io::Error::other)??
+        .into_parts();
 
-    Ok((file, file_path))
+    Ok((File::from_std(file), path))
 }
 
 pub fn friendly_id(len: usize) -> String {
@@ -0,0 +1,286 @@
+//! Streaming ZIP writer, entries are stored as they are so the archive is never built on disk.
+//!
+//! Sizes of every entry are known upfront, so the total length can be computed before anything
+//! is sent. CRC-32 is only known once an entry was streamed, so it's written in a data descriptor
+//! after the entry and in the central directory. ZIP64 records are used only when they're needed.
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use crc32fast::Hasher;
+use futures::{stream, StreamExt, TryStreamExt};
+
+use crate::{ranges::RangeSource, storage::ByteStream};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x0807_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const ZIP64_END_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const END_SIGNATURE: u32 = 0x0605_4b50;
+
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+// sizes follow in a data descriptor and names are utf-8
+const FLAGS: u16 = 0x0008 | 0x0800;
+const METHOD_STORED: u16 = 0;
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+
+const LOCAL_HEADER_LEN: u64 = 30;
+const CENTRAL_HEADER_LEN: u64 = 46;
+const END_LEN: u64 = 22;
+const ZIP64_END_LEN: u64 = 56;
+const ZIP64_LOCATOR_LEN: u64 = 20;
+
+pub struct ZipEntry {
+    pub name: String,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+    pub source: Arc<dyn RangeSource>,
+}
+
+/// Layout of one entry, everything but the checksum is known before streaming.
+struct Layout {
+    name: String,
+    size: u64,
+    offset: u64,
+    zip64: bool,
+    time: u16,
+    date: u16,
+}
+
+impl Layout {
+    fn local_header(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u32_le(LOCAL_HEADER_SIGNATURE);
+        buf.put_u16_le(self.version());
+        buf.put_u16_le(FLAGS);
+        buf.put_u16_le(METHOD_STORED);
+        buf.put_u16_le(self.time);
+        buf.put_u16_le(self.date);
+        // checksum comes in the data descriptor
+        buf.put_u32_le(0);
+        if self.zip64 {
+            buf.put_u32_le(u32::MAX);
+            buf.put_u32_le(u32::MAX);
+        } else {
+            buf.put_u32_le(self.size as u32);
+            buf.put_u32_le(self.size as u32);
+        }
+        buf.put_u16_le(self.name.len() as u16);
+        buf.put_u16_le(if self.zip64 { 20 } else { 0 });
+        buf.put_slice(self.name.as_bytes());
+        if self.zip64 {
+            buf.put_u16_le(ZIP64_EXTRA_ID);
+            buf.put_u16_le(16);
+            buf.put_u64_le(self.size);
+            buf.put_u64_le(self.size);
+        }
+        buf.freeze()
+    }
+
+    fn data_descriptor(&self, crc: u32) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u32_le(DATA_DESCRIPTOR_SIGNATURE);
+        buf.put_u32_le(crc);
+        if self.zip64 {
+            buf.put_u64_le(self.size);
+            buf.put_u64_le(self.size);
+        } else {
+            buf.put_u32_le(self.size as u32);
+            buf.put_u32_le(self.size as u32);
+        }
+        buf.freeze()
+    }
+
+    fn central_header(&self, crc: u32, buf: &mut BytesMut) {
+        buf.put_u32_le(CENTRAL_HEADER_SIGNATURE);
+        buf.put_u16_le(VERSION_ZIP64);
+        buf.put_u16_le(self.version());
+        buf.put_u16_le(FLAGS);
+        buf.put_u16_le(METHOD_STORED);
+        buf.put_u16_le(self.time);
+        buf.put_u16_le(self.date);
+        buf.put_u32_le(crc);
+        if self.zip64 {
+            buf.put_u32_le(u32::MAX);
+            buf.put_u32_le(u32::MAX);
+        } else {
+            buf.put_u32_le(self.size as u32);
+            buf.put_u32_le(self.size as u32);
+        }
+        buf.put_u16_le(self.name.len() as u16);
+        buf.put_u16_le(if self.zip64 { 28 } else { 0 });
+        // comment, disk, internal and external attributes
+        buf.put_u16_le(0);
+        buf.put_u16_le(0);
+        buf.put_u16_le(0);
+        buf.put_u32_le(0);
+        buf.put_u32_le(if self.zip64 { u32::MAX } else { self.offset as u32 });
+        buf.put_slice(self.name.as_bytes());
+        if self.zip64 {
+            buf.put_u16_le(ZIP64_EXTRA_ID);
+            buf.put_u16_le(24);
+            buf.put_u64_le(self.size);
+            buf.put_u64_le(self.size);
+            buf.put_u64_le(self.offset);
+        }
+    }
+
+    fn version(&self) -> u16 {
+        if self.zip64 {
+            VERSION_ZIP64
+        } else {
+            VERSION_DEFAULT
+        }
+    }
+
+    fn local_len(&self) -> u64 {
+        let extra = if self.zip64 { 20 } else { 0 };
+        let descriptor = if self.zip64 { 24 } else { 16 };
+        LOCAL_HEADER_LEN + self.name.len() as u64 + extra + self.size + descriptor
+    }
+
+    fn central_len(&self) -> u64 {
+        let extra = if self.zip64 { 28 } else { 0 };
+        CENTRAL_HEADER_LEN + self.name.len() as u64 + extra
+    }
+}
+
+/// Builds archive of `entries`, returns its exact length and the stream producing it.
+pub fn archive(entries: Vec<ZipEntry>) -> (u64, ByteStream) {
+    let mut names = HashSet::new();
+    let mut offset = 0;
+    let mut layouts = Vec::with_capacity(entries.len());
+    let mut sources = Vec::with_capacity(entries.len());
+
+    for entry in entries {
+        let (time, date) = dos_datetime(entry.modified);
+        let layout = Layout {
+            name: unique_name(&mut names, &entry.name),
+            size: entry.size,
+            offset,
+            zip64: entry.size >= u32::MAX as u64 || offset >= u32::MAX as u64,
+            time,
+            date,
+        };
+        offset += layout.local_len();
+        layouts.push(layout);
+        sources.push(entry.source);
+    }
+
+    let central_offset = offset;
+    let central_len = layouts.iter().map(Layout::central_len).sum::<u64>();
+    let zip64_end = layouts.len() >= u16::MAX as usize
+        || central_offset >= u32::MAX as u64
+        || central_len >= u32::MAX as u64;
+    let end_len = END_LEN + if zip64_end { ZIP64_END_LEN + ZIP64_LOCATOR_LEN } else { 0 };
+    let length = central_offset + central_len + end_len;
+
+    let layouts = Arc::new(layouts);
+    let checksums = Arc::new(Mutex::new(Vec::with_capacity(layouts.len())));
+
+    // entries are opened one after another, each of them only once the previous one was sent
+    let entries = {
+        let layouts = layouts.clone();
+        let checksums = checksums.clone();
+        stream::iter(sources.into_iter().enumerate())
+            .then(move |(i, source)| {
+                let layouts = layouts.clone();
+                let checksums = checksums.clone();
+                async move {
+                    let layout = &layouts[i];
+                    let data = source.all(layout.size).await?;
+                    let hasher = Arc::new(Mutex::new(Hasher::new()));
+
+                    let hashing = hasher.clone();
+                    let data = data.inspect_ok(move |chunk| hashing.lock().unwrap().update(chunk));
+
+                    let layouts = layouts.clone();
+                    let descriptor = stream::once(async move {
+                        let crc = std::mem::take(&mut *hasher.lock().unwrap()).finalize();
+                        checksums.lock().unwrap().push(crc);
+                        Ok(layouts[i].data_descriptor(crc))
+                    });
+
+                    let header = stream::iter([Ok(layout.local_header())]);
+                    std::io::Result::Ok(header.chain(data).chain(descriptor))
+                }
+            })
+            .try_flatten()
+    };
+
+    let central_directory = stream::once(async move {
+        let checksums = checksums.lock().unwrap();
+        let mut buf = BytesMut::with_capacity((central_len + end_len) as usize);
+        for (layout, crc) in layouts.iter().zip(checksums.iter()) {
+            layout.central_header(*crc, &mut buf);
+        }
+        end_of_central_directory(&mut buf, layouts.len() as u64, central_offset, central_len, zip64_end);
+        Ok(buf.freeze())
+    });
+
+    (length, Box::pin(entries.chain(central_directory)))
+}
+
+fn end_of_central_directory(buf: &mut BytesMut, entries: u64, offset: u64, len: u64, zip64: bool) {
+    if zip64 {
+        let zip64_end_offset = offset + len;
+        buf.put_u32_le(ZIP64_END_SIGNATURE);
+        buf.put_u64_le(ZIP64_END_LEN - 12);
+        buf.put_u16_le(VERSION_ZIP64);
+        buf.put_u16_le(VERSION_ZIP64);
+        buf.put_u32_le(0);
+        buf.put_u32_le(0);
+        buf.put_u64_le(entries);
+        buf.put_u64_le(entries);
+        buf.put_u64_le(len);
+        buf.put_u64_le(offset);
+
+        buf.put_u32_le(ZIP64_LOCATOR_SIGNATURE);
+        buf.put_u32_le(0);
+        buf.put_u64_le(zip64_end_offset);
+        buf.put_u32_le(1);
+    }
+
+    buf.put_u32_le(END_SIGNATURE);
+    buf.put_u16_le(0);
+    buf.put_u16_le(0);
+    buf.put_u16_le(entries.min(u16::MAX as u64) as u16);
+    buf.put_u16_le(entries.min(u16::MAX as u64) as u16);
+    buf.put_u32_le(len.min(u32::MAX as u64) as u32);
+    buf.put_u32_le(offset.min(u32::MAX as u64) as u32);
+    buf.put_u16_le(0);
+}
+
+/// Names can't contain directories and have to be unique within the archive.
+fn unique_name(names: &mut HashSet<String>, name: &str) -> String {
+    let name = name.replace(['/', '\\'], "_");
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{extension}")),
+        _ => (name.as_str(), String::new()),
+    };
+
+    let mut candidate = name.clone();
+    let mut n = 1;
+    while !names.insert(candidate.clone()) {
+        candidate = format!("{stem} ({n}){extension}");
+        n += 1;
+    }
+    candidate
+}
+
+/// MS-DOS time and date, which can't go below 1980.
+fn dos_datetime(time: DateTime<Utc>) -> (u16, u16) {
+    if time.year() < 1980 {
+        return (0, 0x21);
+    }
+
+    let dos_time = ((time.hour() << 11) | (time.minute() << 5) | (time.second() / 2)) as u16;
+    let dos_date = ((((time.year() - 1980) as u32) << 9) | (time.month() << 5) | time.day()) as u16;
+    (dos_time, dos_date)
+}